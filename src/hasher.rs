@@ -0,0 +1,137 @@
+use crate::{HashMap, HashSet};
+
+use std::hash::{BuildHasher, Hasher};
+
+/// A concurrent hash table keyed by pre-hashed `u64` identifiers.
+///
+/// Keys are hashed with [`PreHashed`], which diffuses the key with a single cheap
+/// finalizer rather than running a general purpose hash function. This is useful for keys
+/// that are already unique, such as snowflake identifiers or content hashes.
+///
+/// Note that keys are stored in the entry allocation along with their value, as in any
+/// other map. Storing keys inline in the table itself is not supported.
+///
+/// # Examples
+///
+/// ```
+/// use papaya_alloy::IntHashMap;
+///
+/// let map: IntHashMap<&str> = IntHashMap::default();
+/// map.pin().insert(0x5F3759DF, "a");
+/// assert_eq!(map.pin().get(&0x5F3759DF), Some(&"a"));
+/// ```
+pub type IntHashMap<V> = HashMap<u64, V, PreHashed>;
+
+/// A concurrent hash set of pre-hashed `u64` identifiers.
+///
+/// See [`IntHashMap`] for details.
+pub type IntHashSet = HashSet<u64, PreHashed>;
+
+/// A [`BuildHasher`] that produces [`IdentityHasher`]s.
+///
+/// Warning: `PreHashed` only diffuses the bits of the key, and so provides no protection
+/// against collisions. It should only be used with keys that are already unique and not under
+/// the control of an attacker.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PreHashed;
+
+impl BuildHasher for PreHashed {
+    type Hasher = IdentityHasher;
+
+    #[inline]
+    fn build_hasher(&self) -> IdentityHasher {
+        IdentityHasher::default()
+    }
+}
+
+/// A [`Hasher`] for integer keys that are already unique.
+///
+/// The table indexes with the low bits of the hash and derives its metadata tags from the
+/// top bits, so every bit of the key must reach both. Instead of a general purpose hash
+/// function, the key is diffused with the 64-bit finalizer of MurmurHash3. The finalizer is
+/// a bijection, so distinct keys never produce the same hash, while keys that differ only in
+/// their high bits, or sequential keys, are spread across the table and its tags.
+///
+/// Keys that are not a single integer are folded together with a multiplicative hash.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct IdentityHasher {
+    hash: u64,
+}
+
+impl IdentityHasher {
+    // A large odd constant derived from the golden ratio.
+    const MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+}
+
+impl Hasher for IdentityHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        // The MurmurHash3 `fmix64` finalizer.
+        let mut hash = self.hash;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+        hash ^ (hash >> 33)
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(buf));
+        }
+    }
+
+    #[inline]
+    fn write_u64(&mut self, n: u64) {
+        // The first write is used as-is, further writes are folded in.
+        self.hash = self.hash.wrapping_mul(Self::MIX) ^ n;
+    }
+
+    #[inline]
+    fn write_u8(&mut self, n: u8) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_u16(&mut self, n: u16) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_i8(&mut self, n: i8) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_i16(&mut self, n: i16) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_i32(&mut self, n: i32) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64)
+    }
+
+    #[inline]
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as u64)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::HashMap;
+
+use std::mem;
+
+/// Types that own memory on the heap.
+///
+/// This trait is used by [`HashMap::deep_heap_size`] to account for memory owned by keys and values,
+/// in addition to the memory used by the map itself. Types that do not own any heap memory
+/// should return `0`.
+///
+/// # Examples
+///
+/// ```
+/// use papaya_alloy::HeapSize;
+///
+/// struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// impl HeapSize for User {
+///     fn heap_size(&self) -> usize {
+///         self.id.heap_size() + self.name.heap_size()
+///     }
+/// }
+///
+/// let user = User { id: 0, name: String::with_capacity(16) };
+/// assert_eq!(user.heap_size(), 16);
+/// ```
+pub trait HeapSize {
+    /// Returns the number of bytes owned by this value on the heap, excluding the size
+    /// of the value itself.
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! impl_zero {
+    ($($ty:ty),*) => {
+        $(impl HeapSize for $ty {
+            #[inline]
+            fn heap_size(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+impl_zero!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+impl<T: ?Sized> HeapSize for &T {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for String {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Box<str> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        mem::size_of::<T>() + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<[T]> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        mem::size_of_val::<[T]>(self) + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        (self.capacity() * mem::size_of::<T>()) + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.as_ref().map(T::heap_size).unwrap_or(0)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.iter().map(T::heap_size).sum()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize, C: HeapSize> HeapSize for (A, B, C) {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size() + self.2.heap_size()
+    }
+}
+
+impl<K, V, S> HeapSize for HashMap<K, V, S>
+where
+    K: HeapSize,
+    V: HeapSize,
+{
+    #[inline]
+    fn heap_size(&self) -> usize {
+        HashMap::deep_heap_size(self)
+    }
+}
@@ -80,6 +80,8 @@
 //!
 //! Aggregate operations, such as iterators, rely on a weak snapshot of the table and return results reflecting the state of the table at or some point after the creation of the iterator. This means that they may, but are not guaranteed to, reflect concurrent modifications to the table that occur during iteration. Similarly, operations such as `clear` and `clone` rely on iteration and may not produce "perfect" results if the map is being concurrently modified.
 //!
+//! If a consistent view of the entire table is required, [`HashMap::snapshot`] returns a read-only [`Snapshot`] that reflects the state of the table at a single point in time, without blocking concurrent writers for the duration of the scan. Snapshots must be enabled with [`HashMapBuilder::snapshots`].
+//!
 //! Note that to obtain a stable snapshot of the table, aggregate table operations require completing any in-progress resizes. If you rely heavily on iteration or similar operations you should consider configuring [`ResizeMode::Blocking`].
 //!
 //! # Atomic Operations
@@ -210,6 +212,8 @@
 //!
 //! `papaya` is built with read-heavy workloads in mind. As such, read operations are extremely high throughput and provide consistent performance that scales with concurrency, meaning `papaya` will excel in workloads where reads are more common than writes. In write heavy workloads, `papaya` will still provide competitive performance despite not being it's primary use case. See the [benchmarks] for details.
 //!
+//! For update-heavy workloads, [`ShardedHashMap`] splits the keyspace across independent tables, so that a resize only stalls writers to a fraction of the keys.
+//!
 //! `papaya` aims to provide predictable and consistent latency across all operations. Most operations are lock-free, and those that aren't only block under rare and constrained conditions. `papaya` also features [incremental resizing](ResizeMode). Predictable latency is an important part of performance that doesn't often show up in benchmarks, but has significant implications for real-world usage.
 //!
 //! [benchmarks]: https://github.com/ibraheemdev/papaya/blob/master/BENCHMARKS.md
@@ -227,18 +231,36 @@ use std::gc::GcAllocator;
 #[global_allocator]
 static A: GcAllocator = GcAllocator;
 
+mod hasher;
+mod heap_size;
+mod log;
 mod map;
+mod observer;
 mod raw;
 mod reclaim;
 mod set;
+mod sharded;
+mod sync;
+mod table;
+mod watch;
 
 #[cfg(feature = "serde")]
 mod serde_impls;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
 pub use equivalent::Equivalent;
+pub use hasher::{IdentityHasher, IntHashMap, IntHashSet, PreHashed};
+pub use heap_size::HeapSize;
+pub use log::{Changes, LaggedError};
 pub use map::{
-    Compute, HashMap, HashMapBuilder, HashMapRef, Iter, Keys, OccupiedError, Operation, ResizeMode,
-    Values,
+    Compute, CounterMode, ExtractIf, HashIter, HashMap, HashMapBuilder, HashMapRef, Iter, Keys,
+    MoveError, OccupiedError, Operation, ResizeMode, ResizeStatus, Snapshot, Stats, Values,
 };
+pub use observer::MapObserver;
 pub use seize::{Guard, LocalGuard, OwnedGuard};
 pub use set::{HashSet, HashSetBuilder, HashSetRef};
+pub use sharded::{ShardedHashMap, ShardedHashMapBuilder, ShardedHashMapRef};
+pub use table::{HashTable, HashTableRef};
+pub use watch::{Change, ChangeKind, Receiver, Recv, Watcher};
@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fmt;
+use std::gc::Gc;
+use std::ptr;
+
+use crate::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use crate::Change;
+
+/// An iterator over the changes recorded in the change log of a [`HashMap`](crate::HashMap).
+///
+/// This type is created with [`HashMap::changes_since`](crate::HashMap::changes_since).
+/// See its documentation for details.
+pub struct Changes<'a, K, V> {
+    log: &'a ChangeLog<K, V>,
+
+    // The sequence number of the next change.
+    next: u64,
+}
+
+impl<K, V> Iterator for Changes<'_, K, V> {
+    type Item = (u64, Change<K, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Stop at the first change that was not yet recorded, or that was overwritten.
+            let record = self.log.get(self.next)?;
+            let seq = self.next;
+            self.next += 1;
+
+            // Skip sequence numbers of writes that failed.
+            if let Some(change) = &record.change {
+                return Some((seq, change.clone()));
+            }
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Changes<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Changes")
+            .field("next", &self.next)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An error returned by [`HashMap::changes_since`](crate::HashMap::changes_since) when
+/// changes after the given sequence number were overwritten in the change log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaggedError;
+
+impl fmt::Display for LaggedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("changes were overwritten in the change log")
+    }
+}
+
+impl Error for LaggedError {}
+
+// A bounded log of the changes to a map.
+//
+// Every write to the map reserves a sequence number before it is attempted, after loading
+// the entry it replaces. A write therefore always reserves a greater sequence number than
+// the write it observed, so the sequence numbers of writes to the same key follow the
+// order in which they took effect.
+//
+// Writes record their change, or that they failed, in a ring buffer indexed by sequence
+// number. Readers stop at the first sequence number that was not recorded yet, so changes
+// are never skipped.
+pub(crate) struct ChangeLog<K, V> {
+    // The next sequence number.
+    next: AtomicU64,
+
+    // The latest record for every sequence number modulo the capacity.
+    records: Box<[AtomicPtr<Record<K, V>>]>,
+}
+
+struct Record<K, V> {
+    // The sequence number of the change.
+    seq: u64,
+
+    // The change, or `None` if the write failed and the sequence number is unused.
+    change: Option<Change<K, V>>,
+}
+
+impl<K, V> ChangeLog<K, V> {
+    // Create a new `ChangeLog` that holds at least the given number of changes.
+    pub fn new(capacity: usize) -> ChangeLog<K, V> {
+        // Round up to the next power-of-two for fast modulo.
+        let capacity = capacity.max(1).next_power_of_two();
+
+        ChangeLog {
+            // Sequence numbers start at 1, so that `changes_since(0)` returns every change.
+            next: AtomicU64::new(1),
+            records: (0..capacity)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+        }
+    }
+
+    // Reserve a sequence number for a write.
+    //
+    // This must be called after loading the entry being replaced, and before the write
+    // is attempted.
+    #[inline]
+    pub fn reserve(&self) -> u64 {
+        // Any write we observed happens-before this increment, so it must have reserved a
+        // smaller sequence number.
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Returns the record slot for the given sequence number.
+    #[inline]
+    fn slot(&self, seq: u64) -> &AtomicPtr<Record<K, V>> {
+        &self.records[(seq as usize) & (self.records.len() - 1)]
+    }
+
+    // Record the change of the write with the given sequence number, or `None` if the
+    // write failed.
+    #[inline]
+    pub fn record(&self, seq: u64, change: Option<Change<K, V>>) {
+        let record = Gc::into_raw(Gc::new(Record { seq, change })) as *mut Record<K, V>;
+
+        let slot = self.slot(seq);
+        let mut current = slot.load(Ordering::Acquire);
+
+        loop {
+            // A later change was already recorded in this slot, so this one could
+            // never be read.
+            //
+            // Safety: Records are never deallocated while reachable.
+            if !current.is_null() && unsafe { (*current).seq } > seq {
+                return;
+            }
+
+            // `Release` ensures the record is visible to readers.
+            match slot.compare_exchange_weak(current, record, Ordering::Release, Ordering::Acquire)
+            {
+                Ok(_) => return,
+                Err(found) => current = found,
+            }
+        }
+    }
+
+    // Returns the record of the given sequence number, if it was recorded and not yet
+    // overwritten.
+    #[inline]
+    fn get(&self, seq: u64) -> Option<&Record<K, V>> {
+        // Safety: Records are never deallocated while reachable.
+        let record = unsafe { self.slot(seq).load(Ordering::Acquire).as_ref()? };
+        (record.seq == seq).then_some(record)
+    }
+
+    // Returns the changes after the given sequence number.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes<'_, K, V>, LaggedError> {
+        let next = seq + 1;
+
+        // Records are only ever overwritten by later ones, so if the slot holds a later
+        // record, the change after `seq` is lost.
+        //
+        // Safety: Records are never deallocated while reachable.
+        let record = unsafe { self.slot(next).load(Ordering::Acquire).as_ref() };
+        if record.is_some_and(|record| record.seq > next) {
+            return Err(LaggedError);
+        }
+
+        Ok(Changes { log: self, next })
+    }
+
+    // Returns the number of bytes allocated for the log.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        std::mem::size_of_val::<[_]>(&self.records)
+            + self.records.len() * std::mem::size_of::<Record<K, V>>()
+    }
+}
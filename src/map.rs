@@ -1,11 +1,15 @@
 use crate::raw::{self, InsertResult};
-use crate::Equivalent;
+use crate::{Change, Changes, Equivalent, HeapSize, LaggedError, MapObserver, Receiver, Watcher};
 use seize::{Collector, LocalGuard, OwnedGuard};
 
 use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
 use std::fmt;
+use std::future::Future;
 use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// A concurrent hash table.
 ///
@@ -59,6 +63,10 @@ pub struct HashMapBuilder<K, V, S = RandomState> {
     hasher: S,
     capacity: usize,
     resize_mode: ResizeMode,
+    counter_mode: CounterMode,
+    observer: Option<Arc<dyn MapObserver>>,
+    change_log: Option<usize>,
+    snapshots: bool,
     _kv: PhantomData<(K, V)>,
 }
 
@@ -77,6 +85,10 @@ impl<K, V> HashMapBuilder<K, V> {
             hasher,
             capacity: self.capacity,
             resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            change_log: self.change_log,
+            snapshots: self.snapshots,
             _kv: PhantomData,
         }
     }
@@ -93,6 +105,10 @@ impl<K, V, S> HashMapBuilder<K, V, S> {
             capacity,
             hasher: self.hasher,
             resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            change_log: self.change_log,
+            snapshots: self.snapshots,
             _kv: PhantomData,
         }
     }
@@ -103,6 +119,72 @@ impl<K, V, S> HashMapBuilder<K, V, S> {
             resize_mode,
             hasher: self.hasher,
             capacity: self.capacity,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            change_log: self.change_log,
+            snapshots: self.snapshots,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set the length counter mode of the map. See [`CounterMode`] for details.
+    pub fn counter_mode(self, counter_mode: CounterMode) -> Self {
+        HashMapBuilder {
+            counter_mode,
+            hasher: self.hasher,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            observer: self.observer,
+            change_log: self.change_log,
+            snapshots: self.snapshots,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set an observer for internal events of the map. See [`MapObserver`] for details.
+    pub fn observer(self, observer: Arc<dyn MapObserver>) -> Self {
+        HashMapBuilder {
+            observer: Some(observer),
+            hasher: self.hasher,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            change_log: self.change_log,
+            snapshots: self.snapshots,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Enable a log of the changes to the map that holds at least `capacity` changes.
+    ///
+    /// See [`HashMap::changes_since`] for details.
+    pub fn change_log(self, capacity: usize) -> Self {
+        HashMapBuilder {
+            change_log: Some(capacity),
+            hasher: self.hasher,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            snapshots: self.snapshots,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Enable consistent snapshots of the map.
+    ///
+    /// Snapshots require every write to the map to announce itself, which adds a small
+    /// cost to writes even if a snapshot is never taken, so they are disabled by default.
+    /// See [`HashMap::snapshot`] for details.
+    pub fn snapshots(self, enabled: bool) -> Self {
+        HashMapBuilder {
+            snapshots: enabled,
+            hasher: self.hasher,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            change_log: self.change_log,
             _kv: PhantomData,
         }
     }
@@ -128,7 +210,15 @@ impl<K, V, S> HashMapBuilder<K, V, S> {
     /// Construct a [`HashMap`] from the builder, using the configured options.
     pub fn build(self) -> HashMap<K, V, S> {
         HashMap {
-            raw: raw::HashMap::new(self.capacity, self.hasher, self.resize_mode),
+            raw: raw::HashMap::new(
+                self.capacity,
+                self.hasher,
+                self.resize_mode,
+                self.counter_mode,
+                self.observer,
+                self.change_log,
+                self.snapshots,
+            ),
         }
     }
 }
@@ -138,6 +228,10 @@ impl<K, V, S> fmt::Debug for HashMapBuilder<K, V, S> {
         f.debug_struct("HashMapBuilder")
             .field("capacity", &self.capacity)
             .field("resize_mode", &self.resize_mode)
+            .field("counter_mode", &self.counter_mode)
+            .field("observer", &self.observer.is_some())
+            .field("change_log", &self.change_log)
+            .field("snapshots", &self.snapshots)
             .finish()
     }
 }
@@ -147,7 +241,7 @@ impl<K, V, S> fmt::Debug for HashMapBuilder<K, V, S> {
 /// Hash maps must resize when the underlying table becomes full, migrating all key and value pairs
 /// to a new table. This type allows you to configure the resizing behavior when passed to
 /// [`HashMapBuilder::resize_mode`].
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ResizeMode {
     /// Writers copy a constant number of key/value pairs to the new table before making
     /// progress.
@@ -182,6 +276,111 @@ impl Default for ResizeMode {
     }
 }
 
+/// Length counter behavior for a [`HashMap`].
+///
+/// Maps keep track of the number of entries they contain so that [`HashMap::len`] is cheap.
+/// However, maintaining the counter has a cost on every insertion and removal. This type
+/// allows you to configure the trade-off when passed to [`HashMapBuilder::counter_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterMode {
+    /// The length is tracked by a single atomic counter.
+    ///
+    /// Reading the length is as cheap as possible, but concurrent writers will contend
+    /// on the counter. This mode is best suited to maps with few concurrent writers.
+    Exact,
+
+    /// The length is tracked by a counter sharded across the given number of slots.
+    ///
+    /// Each thread updates its own slot, avoiding contention between writers. Reading
+    /// the length requires summing all slots. The number of shards is rounded up to the
+    /// next power of two.
+    ///
+    /// This is the default counter mode, with one shard per available CPU.
+    Sharded(usize),
+
+    /// The length is not tracked.
+    ///
+    /// This provides the maximum write throughput. [`HashMap::len`] instead returns an
+    /// estimate based on the occupancy of the underlying table, and should not be relied
+    /// upon for exact results.
+    Disabled,
+}
+
+impl Default for CounterMode {
+    fn default() -> Self {
+        let num_cpus = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1);
+
+        CounterMode::Sharded(num_cpus)
+    }
+}
+
+/// A snapshot of the internal state of a [`HashMap`], returned by [`HashMap::stats`].
+///
+/// Statistics are collected without blocking concurrent operations, so the fields may not
+/// be consistent with each other if the map is being modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of slots in the root table.
+    pub capacity: usize,
+
+    /// The number of live entries in the root table.
+    pub live: usize,
+
+    /// The number of deleted entries in the root table that have not been reclaimed by a resize.
+    pub tombstones: usize,
+
+    /// The maximum probe length of the root table, after which the table is resized.
+    pub probe_limit: usize,
+
+    /// A histogram of probe lengths for live entries in the root table.
+    ///
+    /// The entry at index `i` is the number of entries found after `i` probes, up to and
+    /// including [`probe_limit`](Stats::probe_limit).
+    pub probe_lengths: Vec<usize>,
+
+    /// The number of tables, including the root table and any tables being resized into.
+    pub tables: usize,
+
+    /// The status of the resize out of the root table, if one is in progress.
+    pub resize: Option<ResizeStatus>,
+
+    /// The number of entries that have been copied out of the root table.
+    pub copied: usize,
+
+    /// The number of entries that have been claimed for copying out of the root table.
+    pub claimed: usize,
+
+    /// An estimate of the number of bytes used by all tables and live entries.
+    pub bytes: usize,
+}
+
+impl Stats {
+    /// Returns the ratio of live entries to the capacity of the root table.
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+
+        self.live as f64 / self.capacity as f64
+    }
+}
+
+/// The status of an in-progress resize. See [`Stats::resize`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeStatus {
+    /// Entries are being copied to the new table.
+    Pending,
+
+    /// The new table was abandoned in favor of a larger table.
+    Aborted,
+
+    /// All entries were copied, and the new table is being promoted to the root.
+    Promoted,
+}
+
 impl<K, V> HashMap<K, V> {
     /// Creates an empty `HashMap`.
     ///
@@ -226,6 +425,10 @@ impl<K, V> HashMap<K, V> {
             capacity: 0,
             hasher: RandomState::default(),
             resize_mode: ResizeMode::default(),
+            counter_mode: CounterMode::default(),
+            observer: None,
+            change_log: None,
+            snapshots: false,
             _kv: PhantomData,
         }
     }
@@ -293,7 +496,15 @@ impl<K, V, S> HashMap<K, V, S> {
     /// ```
     pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> HashMap<K, V, S> {
         HashMap {
-            raw: raw::HashMap::new(capacity, hash_builder, ResizeMode::default()),
+            raw: raw::HashMap::new(
+                capacity,
+                hash_builder,
+                ResizeMode::default(),
+                CounterMode::default(),
+                None,
+                None,
+                false,
+            ),
         }
     }
 
@@ -319,6 +530,73 @@ impl<K, V, S> HashMap<K, V, S> {
         HashMapRef { map: self }
     }
 
+    /// Returns a reference to the map's [`BuildHasher`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::hash::RandomState;
+    ///
+    /// let map: HashMap<i32, i32> = HashMap::with_hasher(RandomState::new());
+    /// let hasher: &RandomState = map.hasher();
+    /// ```
+    #[inline]
+    pub fn hasher(&self) -> &S {
+        &self.raw.hasher
+    }
+
+    /// Returns the number of bytes allocated on the heap by the map.
+    ///
+    /// This includes all table allocations, including any tables being resized into, the
+    /// allocation of each entry, entries whose reclamation was deferred during a resize,
+    /// and the shards of the length counter. Bytes owned by the keys and values themselves
+    /// are not included, see [`HashMap::deep_heap_size`].
+    ///
+    /// Computing the heap size requires visiting every entry in the map, and the result
+    /// may be inaccurate if the map is modified concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// let empty = map.heap_size();
+    ///
+    /// map.pin().insert(1, 'a');
+    /// assert!(map.heap_size() > empty);
+    /// ```
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        self.raw.heap_size(|_, _| 0)
+    }
+
+    /// Returns the number of bytes allocated on the heap by the map, including the bytes
+    /// owned by its keys and values.
+    ///
+    /// This is [`HashMap::heap_size`] plus the [`HeapSize`] of every key and value in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, String::with_capacity(64));
+    ///
+    /// assert!(map.deep_heap_size() >= map.heap_size() + 64);
+    /// ```
+    #[inline]
+    pub fn deep_heap_size(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.raw
+            .heap_size(|key, value| key.heap_size() + value.heap_size())
+    }
+
     /*
     /// Returns a guard for use with this map.
     ///
@@ -353,6 +631,10 @@ where
 {
     /// Returns the number of entries in the map.
     ///
+    /// If the map was built with [`CounterMode::Disabled`], this returns an estimate
+    /// based on the occupancy of a sample of slots spread across the table, which may be
+    /// inaccurate for small maps or while the table is being resized.
+    ///
     /// # Examples
     ///
     /// ```
@@ -371,6 +653,9 @@ where
 
     /// Returns `true` if the map is empty. Otherwise returns `false`.
     ///
+    /// Unlike [`len`](HashMap::len), this is exact even if the map was built with
+    /// [`CounterMode::Disabled`], in which case it searches the table for an entry.
+    ///
     /// # Examples
     ///
     /// ```
@@ -383,7 +668,68 @@ where
     /// ```
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.raw.is_empty()
+    }
+
+    /// Returns statistics about the internal state of the map.
+    ///
+    /// This is useful for diagnosing memory usage or poor performance, such as that caused by
+    /// a poorly distributed hash function. Collecting statistics requires visiting every entry
+    /// in the root table, but does not block concurrent operations. See [`Stats`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    ///
+    /// let stats = map.stats();
+    /// assert_eq!(stats.live, 1);
+    /// assert_eq!(stats.tables, 1);
+    /// ```
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.raw.stats()
+    }
+
+    /// Verifies the internal invariants of the map.
+    ///
+    /// This is intended for debugging the map itself, or a [`Hash`] implementation that
+    /// may be inconsistent. The following invariants are checked:
+    ///
+    /// - The metadata of every entry matches the hash of its key.
+    /// - Every entry is reachable within the probe limit of the table.
+    /// - Entries are only marked as copied in tables that are being resized.
+    /// - The tables being resized into do not form a cycle.
+    /// - [`len`](HashMap::len) matches the number of entries in the map.
+    ///
+    /// The map should be quiescent when this method is called, as the length of the map
+    /// cannot be verified consistently with concurrent operations. Enabling the
+    /// `debug_invariants` feature runs all other checks after every operation.
+    ///
+    /// Similarly, enabling the `debug_hash` feature verifies that keys matched by lookups,
+    /// removals, and [`compute`](HashMap::compute) hash to the same value as the key that
+    /// was searched for, panicking if an [`Equivalent`] or [`Hash`] implementation is
+    /// inconsistent or non-deterministic.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the invariants are violated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    /// map.check_invariants();
+    /// ```
+    #[inline]
+    pub fn check_invariants(&self) {
+        self.raw.check_invariants(true)
     }
 
     /// Returns `true` if the map contains a value for the specified key.
@@ -619,6 +965,169 @@ where
         self.raw.get_or_insert_with(key, f)
     }
 
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a fallible closure.
+    ///
+    /// If the given key is present, the corresponding value is returned. If it is not present,
+    /// the value computed from `f` is inserted, and a reference to the newly inserted value is
+    /// returned. If `f` returns an error, the error is returned and the map is not modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// assert_eq!(map.pin().get_or_try_insert_with("a", || "3".parse()), Ok(&3));
+    /// assert_eq!(map.pin().get_or_try_insert_with("a", || "x".parse()), Ok(&3));
+    /// assert!(map.pin().get_or_try_insert_with("b", || "x".parse()).is_err());
+    /// assert_eq!(map.pin().get(&"b"), None);
+    /// ```
+    #[inline]
+    pub fn get_or_try_insert_with<'g, F, E>(&self, key: K, f: F) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        K: 'g,
+    {
+        self.raw.get_or_try_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a closure, running the closure at most once.
+    ///
+    /// Unlike [`get_or_insert_with`](HashMap::get_or_insert_with), which may run the closure
+    /// on several threads at once for the same missing key, the first thread to find the key
+    /// missing claims its initialization, and any other threads block until the value is
+    /// inserted. This avoids redundant work for expensive initializers.
+    ///
+    /// If the closure panics, the claim is released and the next waiting thread runs its own
+    /// closure instead.
+    ///
+    /// Note that keys with the same hash are initialized one at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within the closure for the same key, which would otherwise
+    /// deadlock.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    ///
+    /// let map = HashMap::new();
+    /// let loads = AtomicUsize::new(0);
+    ///
+    /// thread::scope(|s| {
+    ///     for _ in 0..4 {
+    ///         s.spawn(|| {
+    ///             let value = map.get_or_insert_with_once("config", || {
+    ///                 loads.fetch_add(1, Ordering::Relaxed);
+    ///                 42
+    ///             });
+    ///
+    ///             assert_eq!(value, &42);
+    ///         });
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(loads.load(Ordering::Relaxed), 1);
+    /// ```
+    #[inline]
+    pub fn get_or_insert_with_once<'g, F>(&self, key: K, f: F) -> &'g V
+    where
+        F: FnOnce() -> V,
+        K: 'g,
+    {
+        match self
+            .raw
+            .get_or_try_insert_with_once(key, || Ok::<_, Infallible>(f()))
+        {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a future.
+    ///
+    /// Concurrent calls for the same missing key are coalesced: the first caller runs its
+    /// future, and any other callers wait for it to complete and return the same value,
+    /// without running their own future. This makes it possible to use the map as an
+    /// asynchronous loading cache.
+    ///
+    /// If the running future is cancelled or panics, the load is handed over to one of the
+    /// waiting callers, which runs its own future instead.
+    ///
+    /// Note that keys with the same hash are initialized one at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// async fn load(map: &HashMap<u64, String>, id: u64) -> &String {
+    ///     map.get_or_insert_async(id, || async move {
+    ///         // Load the value from a database...
+    ///         format!("user {id}")
+    ///     })
+    ///     .await
+    /// }
+    /// ```
+    #[inline]
+    pub async fn get_or_insert_async<'g, F, Fut>(&self, key: K, f: F) -> &'g V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+        K: 'g,
+        V: 'g,
+    {
+        let f = || async { Ok::<_, Infallible>(f().await) };
+        match self.raw.get_or_try_insert_async(key, f).await {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a fallible future.
+    ///
+    /// If the future returns an error, nothing is inserted, and the error is returned to its
+    /// caller as well as to every caller that was waiting on the load. Waiting callers only
+    /// run their own future if the running future is cancelled or panics. See
+    /// [`get_or_insert_async`](HashMap::get_or_insert_async) for details.
+    ///
+    /// Sharing the error requires it to be `Clone`. Errors that are not, such as
+    /// [`std::io::Error`], can be wrapped in an [`Arc`](std::sync::Arc).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::sync::Arc;
+    ///
+    /// async fn load(map: &HashMap<u64, String>, id: u64) -> Result<&String, Arc<std::io::Error>> {
+    ///     map.get_or_try_insert_async(id, || async move {
+    ///         // Load the value from a file...
+    ///         std::fs::read_to_string(format!("users/{id}")).map_err(Arc::new)
+    ///     })
+    ///     .await
+    /// }
+    /// ```
+    #[inline]
+    pub async fn get_or_try_insert_async<'g, F, Fut, E>(&self, key: K, f: F) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+        E: Clone + Send + 'static,
+        K: 'g,
+        V: 'g,
+    {
+        self.raw.get_or_try_insert_async(key, f).await
+    }
+
     /// Updates an existing entry atomically.
     ///
     /// If the value for the specified `key` is present, the new value is computed and stored the
@@ -657,6 +1166,38 @@ where
         self.raw.update(key, update)
     }
 
+    /// Updates an existing entry atomically with a fallible update function.
+    ///
+    /// If the value for the specified `key` is present, the new value is computed and stored
+    /// using the provided update function, and the new value is returned. Otherwise, `Ok(None)`
+    /// is returned. If the update function returns an error, the error is returned and the map
+    /// is not modified.
+    ///
+    /// See [`HashMap::update`] for details about how atomic updates are performed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert("a", 254u8);
+    /// let increment = |i: &u8| i.checked_add(1).ok_or("overflow");
+    ///
+    /// assert_eq!(map.pin().try_update("a", increment), Ok(Some(&255)));
+    /// assert_eq!(map.pin().try_update("a", increment), Err("overflow"));
+    /// assert_eq!(map.pin().try_update("b", increment), Ok(None));
+    /// assert_eq!(map.pin().get(&"a"), Some(&255));
+    /// ```
+    #[inline]
+    pub fn try_update<'g, F, E>(&self, key: K, update: F) -> Result<Option<&'g V>, E>
+    where
+        F: Fn(&V) -> Result<V, E>,
+        K: 'g,
+    {
+        self.raw.try_update(key, update)
+    }
+
     /// Updates an existing entry or inserts a default value.
     ///
     /// If the value for the specified `key` is present, the new value is computed and stored the
@@ -711,6 +1252,45 @@ where
         self.raw.update_or_insert_with(key, update, f)
     }
 
+    /// Updates an existing entry or inserts a default value computed from a closure, where
+    /// either closure may fail.
+    ///
+    /// If the value for the specified `key` is present, the new value is computed and stored
+    /// using the provided update function, and the new value is returned. Otherwise, the value
+    /// computed by `f` is inserted into the map, and a reference to the newly inserted value is
+    /// returned. If either closure returns an error, the error is returned and the map is not
+    /// modified.
+    ///
+    /// See [`HashMap::update`] for details about how atomic updates are performed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// let increment = |i: &u8| i.checked_add(1).ok_or("overflow");
+    ///
+    /// assert_eq!(map.pin().try_update_or_insert_with("a", increment, || Ok(254)), Ok(&254));
+    /// assert_eq!(map.pin().try_update_or_insert_with("a", increment, || Ok(0)), Ok(&255));
+    /// assert_eq!(map.pin().try_update_or_insert_with("a", increment, || Ok(0)), Err("overflow"));
+    /// assert_eq!(map.pin().get(&"a"), Some(&255));
+    /// ```
+    #[inline]
+    pub fn try_update_or_insert_with<'g, U, F, E>(
+        &self,
+        key: K,
+        update: U,
+        f: F,
+    ) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        U: Fn(&V) -> Result<V, E>,
+        K: 'g,
+    {
+        self.raw.try_update_or_insert_with(key, update, f)
+    }
+
     /// Updates an entry with a compare-and-swap (CAS) function.
     ///
     /// This method allows you to perform complex operations on the map atomically. The `compute`
@@ -857,10 +1437,233 @@ where
         self.raw.remove_if(key, should_remove)
     }
 
-    /// Tries to reserve capacity for `additional` more elements to be inserted
-    /// in the `HashMap`.
+    /// Removes an arbitrary entry from the map, returning the removed key and value.
     ///
-    /// After calling this method, the table should be able to hold at least `capacity` elements
+    /// The entry is chosen at random, so concurrent callers are unlikely to contend on
+    /// the same entries. This makes it suitable for work-stealing or approximate eviction.
+    ///
+    /// Returns `None` if the map is empty.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    ///
+    /// assert_eq!(map.pin().pop_any(), Some((&1, &"a")));
+    /// assert_eq!(map.pin().pop_any(), None);
+    /// ```
+    #[inline]
+    pub fn pop_any<'g>(&self) -> Option<(&'g K, &'g V)> {
+        self.raw.pop_any()
+    }
+
+    /// Atomically moves the entry at a key to a new key, returning a reference to the value
+    /// at the new key.
+    ///
+    /// The entry is never present at both keys or at neither, including to concurrent
+    /// readers, iterators, and snapshots. The key and value are cloned into the new entry.
+    ///
+    /// If the key is not in the map, [`MoveError::NotFound`] is returned. If the new key is
+    /// already in the map, [`MoveError::Occupied`] is returned with its value, and the map is
+    /// left unchanged. Moving an entry to its own key leaves it unchanged.
+    ///
+    /// Note that a move is aborted by concurrent writes to either key, in which case it is
+    /// retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::{HashMap, MoveError};
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    /// map.pin().insert(2, "b");
+    ///
+    /// assert_eq!(map.pin().rename(&1, 3), Ok(&"a"));
+    /// assert_eq!(map.pin().get(&1), None);
+    /// assert_eq!(map.pin().get(&3), Some(&"a"));
+    ///
+    /// assert_eq!(map.pin().rename(&3, 2), Err(MoveError::Occupied(&"b")));
+    /// assert_eq!(map.pin().rename(&1, 4), Err(MoveError::NotFound));
+    /// ```
+    #[inline]
+    pub fn rename<'g, Q>(&self, key: &Q, new_key: K) -> Result<&'g V, MoveError<'g, V>>
+    where
+        K: Clone + 'g,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.raw.move_entry(key, &self.raw, Some(new_key))
+    }
+
+    /// Atomically moves the entry at a key to another map, returning a reference to the
+    /// value in the other map.
+    ///
+    /// The entry is never present in both maps or in neither, including to concurrent
+    /// readers, iterators, and snapshots of either map. The key and value are cloned into
+    /// the new entry.
+    ///
+    /// If the key is not in this map, [`MoveError::NotFound`] is returned. If the key is
+    /// already in the other map, [`MoveError::Occupied`] is returned with its value, and
+    /// both maps are left unchanged. Moving an entry to the same map leaves it unchanged.
+    ///
+    /// Note that a move is aborted by concurrent writes to the key in either map, in which
+    /// case it is retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::{HashMap, MoveError};
+    ///
+    /// let active = HashMap::new();
+    /// let idle = HashMap::new();
+    /// active.pin().insert(1, "a");
+    ///
+    /// assert_eq!(active.pin().move_to(&idle, &1), Ok(&"a"));
+    /// assert_eq!(active.pin().get(&1), None);
+    /// assert_eq!(idle.pin().get(&1), Some(&"a"));
+    ///
+    /// active.pin().insert(1, "b");
+    /// assert_eq!(active.pin().move_to(&idle, &1), Err(MoveError::Occupied(&"a")));
+    /// assert_eq!(active.pin().move_to(&idle, &2), Err(MoveError::NotFound));
+    /// ```
+    #[inline]
+    pub fn move_to<'g, Q>(
+        &self,
+        other: &HashMap<K, V, S>,
+        key: &Q,
+    ) -> Result<&'g V, MoveError<'g, V>>
+    where
+        K: Clone + 'g,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.raw.move_entry(key, &other.raw, None)
+    }
+
+    /// Returns the key-value pair with the given hash for which `eq` returns `true`.
+    ///
+    /// This allows a hash computed once to be reused across lookups, avoiding the cost of
+    /// hashing the key again. The hash *must* be the one produced for the key by the map's
+    /// [`hasher`](HashMap::hasher), otherwise the entry may not be found, by this method or
+    /// any other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::hash::BuildHasher;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    ///
+    /// let hash = map.hasher().hash_one(&1);
+    /// assert_eq!(map.pin().get_with_hash(hash, |k| *k == 1), Some((&1, &"a")));
+    /// assert_eq!(map.pin().get_with_hash(hash, |k| *k == 2), None);
+    /// ```
+    #[inline]
+    pub fn get_with_hash<'g, F>(&self, hash: u64, eq: F) -> Option<(&'g K, &'g V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.raw.get_with_hash(hash, eq)
+    }
+
+    /// Inserts a key-value pair with the given hash into the map.
+    ///
+    /// The hash *must* be the one produced for the key by the map's [`hasher`](HashMap::hasher).
+    /// Entries copied to a new table during a resize are rehashed with the map's hasher, so an
+    /// incorrect hash will cause the entry to be lost. See [`HashMap::insert`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::hash::BuildHasher;
+    ///
+    /// let map = HashMap::new();
+    ///
+    /// let hash = map.hasher().hash_one(&37);
+    /// assert_eq!(map.pin().insert_with_hash(hash, 37, "a"), None);
+    /// assert_eq!(map.pin().insert_with_hash(hash, 37, "b"), Some(&"a"));
+    /// assert_eq!(map.pin().get(&37), Some(&"b"));
+    /// ```
+    #[inline]
+    pub fn insert_with_hash<'g>(&self, hash: u64, key: K, value: V) -> Option<&'g V> {
+        match self.raw.insert_with_hash(hash, key, value, true, K::eq) {
+            InsertResult::Inserted(_) => None,
+            InsertResult::Replaced(value) => Some(value),
+            InsertResult::Error { .. } => unreachable!(),
+        }
+    }
+
+    /// Removes the entry with the given hash for which `eq` returns `true`, returning the
+    /// stored key and value if it was previously in the map.
+    ///
+    /// See [`HashMap::get_with_hash`] for the requirements on `hash`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::hash::BuildHasher;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    ///
+    /// let hash = map.hasher().hash_one(&1);
+    /// assert_eq!(map.pin().remove_with_hash(hash, |k| *k == 1), Some((&1, &"a")));
+    /// assert_eq!(map.pin().remove_with_hash(hash, |k| *k == 1), None);
+    /// ```
+    #[inline]
+    pub fn remove_with_hash<'g, F>(&self, hash: u64, eq: F) -> Option<(&'g K, &'g V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.raw.remove_with_hash(hash, eq)
+    }
+
+    /// An iterator visiting the entries that may have the given hash.
+    /// The iterator element type is `(&K, &V)`.
+    ///
+    /// Entries are filtered using only a few bits of the hash, so the iterator may yield
+    /// entries with a different hash. This is useful for keys that are compared by something
+    /// other than their [`Eq`] implementation.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::hash::BuildHasher;
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(1, "a");
+    ///
+    /// let hash = map.hasher().hash_one(&1);
+    /// assert!(map.pin().iter_with_hash(hash).any(|(k, _)| *k == 1));
+    /// ```
+    #[inline]
+    pub fn iter_with_hash<'g>(&self, hash: u64) -> HashIter<'g, K, V> {
+        HashIter {
+            raw: self.raw.iter_with_hash(hash),
+        }
+    }
+
+    /// Tries to reserve capacity for `additional` more elements to be inserted
+    /// in the `HashMap`.
+    ///
+    /// After calling this method, the table should be able to hold at least `capacity` elements
     /// before resizing. However, the capacity is an estimate, and the table may prematurely resize
     /// due to poor hash distribution. The collection may also reserve more space to avoid frequent
     /// reallocations.
@@ -933,6 +1736,44 @@ where
         self.raw.retain(f)
     }
 
+    /// Creates an iterator that removes the entries for which the predicate returns `true`,
+    /// yielding the removed keys and values.
+    ///
+    /// Entries are removed lazily as the iterator advances, in unsorted (and unspecified)
+    /// order. If the iterator is dropped before it is exhausted, the remaining entries are
+    /// left in the map, even if they match the predicate.
+    ///
+    /// Note the predicate may be called more than once for a given key if its value is
+    /// concurrently modified during removal, and that an entry is only returned if it was
+    /// removed by this iterator.
+    ///
+    /// Additionally, this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map: HashMap<i32, i32> = (0..8).map(|x| (x, x * 10)).collect();
+    ///
+    /// let mut removed: Vec<_> = map.extract_if(|&k, _| k % 2 == 0).map(|(&k, _)| k).collect();
+    /// removed.sort();
+    ///
+    /// assert_eq!(removed, [0, 2, 4, 6]);
+    /// assert_eq!(map.len(), 4);
+    /// ```
+    #[inline]
+    pub fn extract_if<F>(&self, f: F) -> ExtractIf<'_, K, V, S, F>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        ExtractIf {
+            raw: self.raw.extract_if(f),
+        }
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order.
     /// The iterator element type is `(&K, &V)`.
     ///
@@ -1006,13 +1847,459 @@ where
     ///     ("c", 3),
     /// ]);
     ///
-    /// for value in map.pin().values() {
-    ///     println!("{value}");
-    /// }
+    /// for value in map.pin().values() {
+    ///     println!("{value}");
+    /// }
+    /// ```
+    #[inline]
+    pub fn values<'g>(&self) -> Values<'g, K, V> {
+        Values { iter: self.iter() }
+    }
+
+    /// Returns a batch of entries starting at the given cursor, along with the cursor to
+    /// pass to the next call.
+    ///
+    /// A scan starts with a cursor of `0`, and is complete once the returned cursor is `0`.
+    /// Unlike [`iter`](HashMap::iter), a scan does not borrow the map between calls, allowing
+    /// a large map to be visited in small slices, for example across multiple requests.
+    ///
+    /// Each call returns roughly `count` entries, but may return more or fewer, including
+    /// none at all. A full scan provides the following guarantees, even if the map is resized
+    /// between calls:
+    ///
+    /// - Every entry present in the map for the duration of the scan is returned at least once.
+    /// - Entries may be returned more than once, so duplicates should be handled by the caller.
+    /// - Entries inserted or removed during the scan may or may not be returned.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::new();
+    /// for i in 0..100 {
+    ///     map.pin().insert(i, i);
+    /// }
+    ///
+    /// let mut keys = Vec::new();
+    /// let mut cursor = 0;
+    /// loop {
+    ///     let (entries, next) = map.scan(cursor, 10);
+    ///     keys.extend(entries.into_iter().map(|(k, _)| *k));
+    ///
+    ///     cursor = next;
+    ///     if cursor == 0 {
+    ///         break;
+    ///     }
+    /// }
+    ///
+    /// keys.sort();
+    /// keys.dedup();
+    /// assert_eq!(keys, (0..100).collect::<Vec<_>>());
+    /// ```
+    #[inline]
+    pub fn scan<'g>(&self, cursor: usize, count: usize) -> (Vec<(&'g K, &'g V)>, usize) {
+        self.raw.scan(cursor, count)
+    }
+
+    /// Returns a key-value pair chosen at random, or `None` if the map is empty.
+    ///
+    /// Entries are picked by probing random slots of the table, so every entry is
+    /// equally likely to be returned, unless the table is very sparse.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::from([(1, "a"), (2, "b")]);
+    /// let map = map.pin();
+    ///
+    /// let (key, value) = map.random_entry().unwrap();
+    /// assert_eq!(map.get(key), Some(value));
+    /// ```
+    #[inline]
+    pub fn random_entry<'g>(&self) -> Option<(&'g K, &'g V)> {
+        self.raw.random_entry()
+    }
+
+    /// Returns up to `n` distinct key-value pairs chosen at random.
+    ///
+    /// Without concurrent removals, fewer than `n` entries are returned only if the map
+    /// contains fewer than `n` entries.
+    /// As with [`random_entry`](HashMap::random_entry), every entry is equally likely to be
+    /// included, unless the table is very sparse.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map: HashMap<i32, i32> = (0..100).map(|x| (x, x)).collect();
+    /// let map = map.pin();
+    ///
+    /// let sample = map.sample(10);
+    /// assert_eq!(sample.len(), 10);
+    /// assert!(sample.iter().all(|(k, v)| k == v));
+    ///
+    /// assert_eq!(map.sample(1000).len(), 100);
+    /// ```
+    #[inline]
+    pub fn sample<'g>(&self, n: usize) -> Vec<(&'g K, &'g V)> {
+        self.raw.sample(n)
+    }
+
+    /// Returns a read-only snapshot of the map at a single point in time.
+    ///
+    /// Unlike [`iter`](HashMap::iter), which may or may not reflect concurrent modifications,
+    /// the snapshot reflects exactly the writes that completed before some point during this
+    /// call, and none of the writes that started after. This makes it suitable for exporting
+    /// a consistent state of the map, such as for backups or checksums.
+    ///
+    /// Taking a snapshot does not block concurrent writers while the map is scanned, nor does
+    /// it copy any keys or values, which are shared with the map. However, writers may briefly
+    /// wait for any writes that were in progress when the snapshot was taken, and only one
+    /// snapshot may be taken at a time.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if snapshots were not enabled with [`HashMapBuilder::snapshots`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::builder().snapshots(true).build();
+    /// map.pin().insert(1, "a");
+    ///
+    /// let snapshot = map.snapshot();
+    /// map.pin().insert(1, "b");
+    /// map.pin().insert(2, "c");
+    ///
+    /// assert_eq!(snapshot.len(), 1);
+    /// assert_eq!(snapshot.get(&1), Some(&"a"));
+    /// assert_eq!(snapshot.get(&2), None);
+    /// ```
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<K, V, S>
+    where
+        S: Clone,
+    {
+        let entries = self.raw.snapshot();
+
+        // Safety: Entries are never deallocated while reachable, and a snapshot
+        // contains at most one entry for any given key.
+        let raw = unsafe {
+            raw::HashMap::from_entries(
+                entries,
+                self.raw.hasher.clone(),
+                ResizeMode::Blocking,
+                CounterMode::Exact,
+            )
+        };
+
+        Snapshot { raw }
+    }
+
+    /// Returns a copy of the map that shares its keys and values with this map.
+    ///
+    /// Unlike [`clone`](Clone::clone), this does not require `K: Clone` or `V: Clone`.
+    /// The underlying table is copied directly, without hashing any keys, so this runs in
+    /// time proportional to the capacity of the map regardless of the size of its entries.
+    /// Entries are never modified once inserted, so later writes to either map are not
+    /// visible to the other. Note however that any interior mutability of the values, such
+    /// as an atomic counter, is shared between the two maps.
+    ///
+    /// Any in-progress resize is completed before the table is copied. Like iteration, the
+    /// copy may or may not reflect writes that are concurrent with this method, see
+    /// [`snapshot`](HashMap::snapshot) if a consistent view of the map is required. Either way,
+    /// the copy contains each key at most once. The copy uses the same resize and counter
+    /// modes as this map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// // Neither the keys nor values implement `Clone`.
+    /// #[derive(PartialEq, Eq, Hash, Debug)]
+    /// struct Key(usize);
+    /// #[derive(PartialEq, Debug)]
+    /// struct Value(Vec<u8>);
+    ///
+    /// let map = HashMap::new();
+    /// map.pin().insert(Key(1), Value(vec![0; 1024]));
+    ///
+    /// let copy = map.share_clone();
+    /// copy.pin().insert(Key(2), Value(vec![1; 1024]));
+    ///
+    /// assert_eq!(map.len(), 1);
+    /// assert_eq!(copy.pin().get(&Key(1)), Some(&Value(vec![0; 1024])));
+    /// assert_eq!(copy.len(), 2);
+    /// ```
+    #[inline]
+    pub fn share_clone(&self) -> HashMap<K, V, S>
+    where
+        S: Clone,
+    {
+        HashMap {
+            raw: self.raw.share_clone(K::eq),
+        }
+    }
+
+    /// Subscribes to changes of the given key.
+    ///
+    /// The returned [`Watcher`] receives a [`Change`] every time the key is inserted,
+    /// replaced or removed after this method returns, through any operation on the map.
+    /// Changes can be received by blocking the current thread with [`Watcher::recv`], or
+    /// asynchronously with [`Watcher::recv_async`].
+    ///
+    /// Changes are queued until they are received, and the subscription is cancelled when
+    /// the watcher is dropped. Watching a key does not affect the performance of operations
+    /// on unrelated keys, but notifying watchers is relatively expensive.
+    ///
+    /// Note that the changes of concurrent writes to the key may be received in a different
+    /// order than the writes took effect. The latest value of the key can always be read
+    /// from the map after receiving a change.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::{ChangeKind, HashMap};
+    /// use std::thread;
+    ///
+    /// let map = HashMap::new();
+    /// let watcher = map.watch("config");
+    ///
+    /// thread::scope(|s| {
+    ///     s.spawn(|| {
+    ///         map.pin().insert("config", 1);
+    ///     });
+    ///
+    ///     let change = watcher.recv().unwrap();
+    ///     assert_eq!(change.kind(), ChangeKind::Inserted);
+    ///     assert_eq!(change.new_value(), Some(&1));
+    /// });
+    /// ```
+    #[inline]
+    pub fn watch(&self, key: K) -> Watcher<K, V> {
+        self.raw.watch(key)
+    }
+
+    /// Subscribes to changes of every key.
+    ///
+    /// The returned [`Receiver`] receives a [`Change`] every time a key is inserted,
+    /// replaced or removed after this method returns. See [`watch`](HashMap::watch)
+    /// for details.
+    ///
+    /// Note that the receiver is unbounded: every change is queued until it is received,
+    /// so a receiver that is not drained as fast as the map is written to grows without
+    /// bound. Drop the receiver to cancel the subscription.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::{ChangeKind, HashMap};
+    ///
+    /// let map = HashMap::new();
+    /// let changes = map.watch_all();
+    ///
+    /// map.pin().insert(1, "a");
+    /// map.pin().insert(1, "b");
+    /// map.pin().remove(&1);
+    ///
+    /// let kinds: Vec<_> = std::iter::from_fn(|| changes.try_recv())
+    ///     .map(|change| change.kind())
+    ///     .collect();
+    ///
+    /// assert_eq!(
+    ///     kinds,
+    ///     [ChangeKind::Inserted, ChangeKind::Replaced, ChangeKind::Removed]
+    /// );
+    /// ```
+    #[inline]
+    pub fn watch_all(&self) -> Receiver<Change<K, V>> {
+        self.raw.watch_all()
+    }
+
+    /// Blocks the current thread until the given key is present in the map, returning a
+    /// reference to its value.
+    ///
+    /// Waiters are registered by the hash of the key, and writers only pay for waking
+    /// waiters of keys that are being waited for. A waiter may be woken by a change to a
+    /// different key with the same hash, in which case it continues waiting.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::thread;
+    ///
+    /// let map = HashMap::new();
+    ///
+    /// thread::scope(|s| {
+    ///     s.spawn(|| {
+    ///         map.pin().insert("result", 42);
+    ///     });
+    ///
+    ///     assert_eq!(map.wait_for("result"), &42);
+    /// });
+    /// ```
+    #[inline]
+    pub fn wait_for<'g, Q>(&self, key: &Q) -> &'g V
+    where
+        K: 'g,
+        V: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        match self.raw.wait_for(key, None) {
+            Some((_, value)) => value,
+            None => unreachable!(),
+        }
+    }
+
+    /// Blocks the current thread until the given key is present in the map, or the timeout
+    /// elapses.
+    ///
+    /// Returns `None` if the key was not inserted before the timeout elapsed. See
+    /// [`wait_for`](HashMap::wait_for) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    /// use std::time::Duration;
+    ///
+    /// let map: HashMap<&str, i32> = HashMap::new();
+    /// assert_eq!(map.wait_for_timeout("result", Duration::from_millis(10)), None);
+    /// ```
+    #[inline]
+    pub fn wait_for_timeout<'g, Q>(&self, key: &Q, timeout: Duration) -> Option<&'g V>
+    where
+        K: 'g,
+        V: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let deadline = Instant::now() + timeout;
+        self.raw
+            .wait_for(key, Some(deadline))
+            .map(|(_, value)| value)
+    }
+
+    /// Waits asynchronously until the given key is present in the map, returning a
+    /// reference to its value.
+    ///
+    /// See [`wait_for`](HashMap::wait_for) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// async fn wait(map: &HashMap<&str, i32>) {
+    ///     assert_eq!(map.wait_for_async("result").await, &42);
+    /// }
+    /// ```
+    #[inline]
+    pub async fn wait_for_async<'g, Q>(&self, key: &Q) -> &'g V
+    where
+        K: 'g,
+        V: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.raw.wait_for_async(key).await.1
+    }
+
+    /// Returns the changes to the map after the given sequence number.
+    ///
+    /// When the change log is enabled with [`HashMapBuilder::change_log`], every insertion,
+    /// update or removal is recorded with a sequence number, starting from 1. The changes
+    /// of writes to the same key are ordered by sequence number in the order the writes took
+    /// effect, so applying the changes in order, for example with
+    /// [`apply_changes`](HashMap::apply_changes), keeps another map up to date. Note that
+    /// sequence numbers may be skipped.
+    ///
+    /// The returned iterator ends at the first change that was not recorded yet. Changes
+    /// can be read again later by passing the sequence number of the last change that was
+    /// received.
+    ///
+    /// The change log is bounded, and the oldest changes are overwritten once it is full.
+    /// If the change after `seq` was overwritten, an error is returned. Note that if changes
+    /// are overwritten during iteration the iterator ends early, and the next call returns
+    /// an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the change log was not enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::HashMap;
+    ///
+    /// let map = HashMap::builder().change_log(1024).build();
+    /// let follower = HashMap::new();
+    ///
+    /// map.pin().insert(1, "a");
+    /// map.pin().insert(2, "b");
+    /// let seq = follower.apply_changes(map.changes_since(0).unwrap()).unwrap();
+    ///
+    /// map.pin().remove(&1);
+    /// follower.apply_changes(map.changes_since(seq).unwrap());
+    ///
+    /// assert_eq!(follower, map);
     /// ```
     #[inline]
-    pub fn values<'g>(&self) -> Values<'g, K, V> {
-        Values { iter: self.iter() }
+    pub fn changes_since(&self, seq: u64) -> Result<Changes<'_, K, V>, LaggedError> {
+        self.raw.changes_since(seq)
+    }
+
+    /// Applies the given changes to the map in order, returning the sequence number of the
+    /// last change that was applied.
+    ///
+    /// The changes are typically read from another map with
+    /// [`changes_since`](HashMap::changes_since). Keys and values are cloned into this map.
+    #[inline]
+    pub fn apply_changes<I>(&self, changes: I) -> Option<u64>
+    where
+        I: IntoIterator<Item = (u64, Change<K, V>)>,
+        K: Clone,
+        V: Clone,
+    {
+        let map = self.pin();
+        let mut last = None;
+
+        for (seq, change) in changes {
+            match change.new_value() {
+                Some(value) => {
+                    map.insert(change.key().clone(), value.clone());
+                }
+                None => {
+                    map.remove(change.key());
+                }
+            }
+
+            last = Some(seq);
+        }
+
+        last
     }
 }
 
@@ -1055,6 +2342,17 @@ pub enum Compute<'g, K, V, T> {
     Aborted(T),
 }
 
+/// An error returned by [`rename`](HashMap::rename) or [`move_to`](HashMap::move_to) when
+/// the entry could not be moved.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveError<'a, V> {
+    /// The key to move was not present in the map.
+    NotFound,
+
+    /// The target key was already present, with the given value.
+    Occupied(&'a V),
+}
+
 /// An error returned by [`try_insert`](HashMap::try_insert) when the key already exists.
 ///
 /// Contains the existing value, and the value that was not inserted.
@@ -1232,7 +2530,7 @@ where
     /// See [`HashMap::is_empty`] for details.
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.map.raw.is_empty()
     }
 
     /// Returns `true` if the map contains a value for the specified key.
@@ -1339,6 +2637,30 @@ where
         self.map.raw.get_or_insert_with(key, f)
     }
 
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a fallible closure.
+    ///
+    /// See [`HashMap::get_or_try_insert_with`] for details.
+    #[inline]
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.map.raw.get_or_try_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a closure, running the closure at most once.
+    ///
+    /// See [`HashMap::get_or_insert_with_once`] for details.
+    #[inline]
+    pub fn get_or_insert_with_once<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        self.map.get_or_insert_with_once(key, f)
+    }
+
     /// Updates an existing entry atomically.
     ///
     /// See [`HashMap::update`] for details.
@@ -1350,6 +2672,17 @@ where
         self.map.raw.update(key, update)
     }
 
+    /// Updates an existing entry atomically with a fallible update function.
+    ///
+    /// See [`HashMap::try_update`] for details.
+    #[inline]
+    pub fn try_update<F, E>(&self, key: K, update: F) -> Result<Option<&V>, E>
+    where
+        F: Fn(&V) -> Result<V, E>,
+    {
+        self.map.raw.try_update(key, update)
+    }
+
     /// Updates an existing entry or inserts a default value.
     ///
     /// See [`HashMap::update_or_insert`] for details.
@@ -1373,6 +2706,19 @@ where
         self.map.raw.update_or_insert_with(key, update, f)
     }
 
+    /// Updates an existing entry or inserts a default value computed from a closure, where
+    /// either closure may fail.
+    ///
+    /// See [`HashMap::try_update_or_insert_with`] for details.
+    #[inline]
+    pub fn try_update_or_insert_with<U, F, E>(&self, key: K, update: U, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        U: Fn(&V) -> Result<V, E>,
+    {
+        self.map.raw.try_update_or_insert_with(key, update, f)
+    }
+
     // Updates an entry with a compare-and-swap (CAS) function.
     //
     /// See [`HashMap::compute`] for details.
@@ -1423,6 +2769,80 @@ where
         self.map.raw.remove_if(key, should_remove)
     }
 
+    /// Removes an arbitrary entry from the map, returning the removed key and value.
+    ///
+    /// See [`HashMap::pop_any`] for details.
+    #[inline]
+    pub fn pop_any(&self) -> Option<(&K, &V)> {
+        self.map.raw.pop_any()
+    }
+
+    /// Atomically moves the entry at a key to a new key, returning a reference to the value
+    /// at the new key.
+    ///
+    /// See [`HashMap::rename`] for details.
+    #[inline]
+    pub fn rename<Q>(&self, key: &Q, new_key: K) -> Result<&V, MoveError<'_, V>>
+    where
+        K: Clone,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.raw.move_entry(key, &self.map.raw, Some(new_key))
+    }
+
+    /// Atomically moves the entry at a key to another map, returning a reference to the
+    /// value in the other map.
+    ///
+    /// See [`HashMap::move_to`] for details.
+    #[inline]
+    pub fn move_to<Q>(&self, other: &HashMap<K, V, S>, key: &Q) -> Result<&V, MoveError<'_, V>>
+    where
+        K: Clone,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.raw.move_entry(key, &other.raw, None)
+    }
+
+    /// Returns the key-value pair with the given hash for which `eq` returns `true`.
+    ///
+    /// See [`HashMap::get_with_hash`] for details.
+    #[inline]
+    pub fn get_with_hash<F>(&self, hash: u64, eq: F) -> Option<(&K, &V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.map.raw.get_with_hash(hash, eq)
+    }
+
+    /// Inserts a key-value pair with the given hash into the map.
+    ///
+    /// See [`HashMap::insert_with_hash`] for details.
+    #[inline]
+    pub fn insert_with_hash(&self, hash: u64, key: K, value: V) -> Option<&V> {
+        self.map.insert_with_hash(hash, key, value)
+    }
+
+    /// Removes the entry with the given hash for which `eq` returns `true`.
+    ///
+    /// See [`HashMap::remove_with_hash`] for details.
+    #[inline]
+    pub fn remove_with_hash<F>(&self, hash: u64, eq: F) -> Option<(&K, &V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.map.raw.remove_with_hash(hash, eq)
+    }
+
+    /// An iterator visiting the entries that may have the given hash.
+    ///
+    /// See [`HashMap::iter_with_hash`] for details.
+    #[inline]
+    pub fn iter_with_hash(&self, hash: u64) -> HashIter<'_, K, V> {
+        self.map.iter_with_hash(hash)
+    }
+
     /// Clears the map, removing all key-value pairs.
     ///
     /// See [`HashMap::clear`] for details.
@@ -1442,6 +2862,20 @@ where
         self.map.raw.retain(f)
     }
 
+    /// Creates an iterator that removes the entries for which the predicate returns `true`,
+    /// yielding the removed keys and values.
+    ///
+    /// See [`HashMap::extract_if`] for details.
+    #[inline]
+    pub fn extract_if<F>(&self, f: F) -> ExtractIf<'_, K, V, S, F>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        ExtractIf {
+            raw: self.map.raw.extract_if(f),
+        }
+    }
+
     /// Tries to reserve capacity for `additional` more elements to be inserted
     /// in the map.
     ///
@@ -1479,6 +2913,42 @@ where
     pub fn values(&self) -> Values<'_, K, V> {
         Values { iter: self.iter() }
     }
+
+    /// Returns a batch of entries starting at the given cursor, along with the cursor to
+    /// pass to the next call.
+    ///
+    /// See [`HashMap::scan`] for details.
+    #[inline]
+    pub fn scan(&self, cursor: usize, count: usize) -> (Vec<(&K, &V)>, usize) {
+        self.map.raw.scan(cursor, count)
+    }
+
+    /// Returns a key-value pair chosen at random, or `None` if the map is empty.
+    ///
+    /// See [`HashMap::random_entry`] for details.
+    #[inline]
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        self.map.raw.random_entry()
+    }
+
+    /// Returns up to `n` distinct key-value pairs chosen at random.
+    ///
+    /// See [`HashMap::sample`] for details.
+    #[inline]
+    pub fn sample(&self, n: usize) -> Vec<(&K, &V)> {
+        self.map.raw.sample(n)
+    }
+
+    /// Returns a read-only snapshot of the map at a single point in time.
+    ///
+    /// See [`HashMap::snapshot`] for details.
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<K, V, S>
+    where
+        S: Clone,
+    {
+        self.map.snapshot()
+    }
 }
 
 impl<K, V, S> fmt::Debug for HashMapRef<'_, K, V, S>
@@ -1505,6 +2975,122 @@ where
     }
 }
 
+/// A read-only snapshot of a [`HashMap`] at a single point in time.
+///
+/// This struct is created by the [`snapshot`](HashMap::snapshot) method on [`HashMap`].
+/// See its documentation for details.
+pub struct Snapshot<K, V, S = RandomState> {
+    raw: raw::HashMap<K, V, S>,
+}
+
+// Safety: Keys and values are shared with the map the snapshot was taken from,
+// which may be accessed concurrently on a different thread.
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Send> Send for Snapshot<K, V, S> {}
+
+// Safety: We only ever hand out `&{K, V}` through shared references to the snapshot.
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Sync> Sync for Snapshot<K, V, S> {}
+
+impl<K, V, S> Snapshot<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the number of entries in the snapshot.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Returns `true` if the snapshot is empty. Otherwise returns `false`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Returns a reference to the snapshot's [`BuildHasher`].
+    #[inline]
+    pub fn hasher(&self) -> &S {
+        &self.raw.hasher
+    }
+
+    /// Returns `true` if the snapshot contains a value for the specified key.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    #[inline]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        match self.raw.get(key) {
+            Some((_, v)) => Some(v),
+            None => None,
+        }
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    #[inline]
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.raw.get(key)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    /// The iterator element type is `(&K, &V)`.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            raw: self.raw.iter(),
+        }
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    /// The iterator element type is `&K`.
+    #[inline]
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&V`.
+    #[inline]
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+}
+
+impl<K, V, S> fmt::Debug for Snapshot<K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a Snapshot<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 /// An iterator over a map's entries.
 ///
 /// This struct is created by the [`iter`](HashMap::iter) method on [`HashMap`]. See its documentation for details.
@@ -1535,6 +3121,37 @@ where
     }
 }
 
+/// An iterator over the entries of a map that may have a given hash.
+///
+/// This struct is created by the [`iter_with_hash`](HashMap::iter_with_hash) method on [`HashMap`].
+/// See its documentation for details.
+pub struct HashIter<'g, K, V> {
+    raw: raw::HashIter<'g, K, V>,
+}
+
+impl<'g, K: 'g, V: 'g> Iterator for HashIter<'g, K, V> {
+    type Item = (&'g K, &'g V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next()
+    }
+}
+
+impl<K, V> fmt::Debug for HashIter<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(HashIter {
+                raw: self.raw.clone(),
+            })
+            .finish()
+    }
+}
+
 /// An iterator over a map's keys.
 ///
 /// This struct is created by the [`keys`](HashMap::keys) method on [`HashMap`]. See its documentation for details.
@@ -1588,3 +3205,30 @@ where
         f.debug_tuple("Values").field(&self.iter).finish()
     }
 }
+
+/// An iterator that removes the entries of a map matching a predicate.
+///
+/// This struct is created by the [`extract_if`](HashMap::extract_if) method on [`HashMap`]. See its documentation for details.
+pub struct ExtractIf<'g, K, V, S, F> {
+    raw: raw::ExtractIf<'g, K, V, S, F>,
+}
+
+impl<'g, K, V, S, F> Iterator for ExtractIf<'g, K, V, S, F>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    F: FnMut(&K, &V) -> bool,
+{
+    type Item = (&'g K, &'g V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next()
+    }
+}
+
+impl<K, V, S, F> fmt::Debug for ExtractIf<'_, K, V, S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractIf").finish_non_exhaustive()
+    }
+}
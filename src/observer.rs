@@ -0,0 +1,72 @@
+/// Callbacks for internal events of a [`HashMap`](crate::HashMap).
+///
+/// Observers are useful for correlating latency spikes or memory usage with the internal
+/// behavior of a map, such as resizes. An observer can be set using
+/// [`HashMapBuilder::observer`](crate::HashMapBuilder::observer).
+///
+/// All methods have an empty default implementation. Note that callbacks are invoked inline
+/// on the thread performing the operation, often on latency sensitive paths, and so should be
+/// cheap, such as incrementing a counter.
+///
+/// # Examples
+///
+/// ```
+/// use papaya_alloy::{HashMap, MapObserver};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Resizes(AtomicUsize);
+///
+/// impl MapObserver for Resizes {
+///     fn resize_started(&self, _old_capacity: usize, _new_capacity: usize) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let resizes = Arc::new(Resizes::default());
+/// let map = HashMap::builder().observer(resizes.clone()).build();
+///
+/// for i in 0..1000 {
+///     map.pin().insert(i, i);
+/// }
+///
+/// assert!(resizes.0.load(Ordering::Relaxed) > 0);
+/// ```
+pub trait MapObserver: Send + Sync {
+    /// A resize was started, allocating a new table.
+    fn resize_started(&self, old_capacity: usize, new_capacity: usize) {
+        let _ = (old_capacity, new_capacity);
+    }
+
+    /// A resize completed, and the table with the given capacity was promoted to the root.
+    fn resize_promoted(&self, capacity: usize) {
+        let _ = capacity;
+    }
+
+    /// A resize was aborted because the table with the given capacity was too small to
+    /// hold every entry.
+    fn resize_aborted(&self, capacity: usize) {
+        let _ = capacity;
+    }
+
+    /// A thread started helping to copy entries out of the table with the given capacity.
+    ///
+    /// This is reported once per thread for each resize, even though threads performing
+    /// incremental resizes help with the copy on many separate operations.
+    fn copy_joined(&self, capacity: usize) {
+        let _ = capacity;
+    }
+
+    /// An insert exceeded the probe limit of the table with the given capacity, forcing
+    /// a resize.
+    fn probe_limit_exceeded(&self, capacity: usize) {
+        let _ = capacity;
+    }
+
+    /// A compare-and-swap on an entry failed due to a concurrent update, and is being retried.
+    fn cas_retry(&self) {}
+
+    /// A thread is about to park while waiting for a resize or copy to complete.
+    fn parked(&self) {}
+}
@@ -1,11 +1,12 @@
 use std::alloc::Layout;
 use std::gc::Gc;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
 use std::{alloc, iter, mem, ptr};
 
 use crate::reclaim::Atomic;
+use crate::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
 
+use super::utils::fault;
 use super::{probe, State};
 
 // A hash-table laid out in a single allocation.
@@ -29,7 +30,7 @@ impl<T> TableLayoutEntries<T> {
         // Pad the meta table to fulfill the alignment requirement of an entry.
         let len = len.max(mem::align_of::<AtomicPtr<T>>());
         let mask = len - 1;
-        let limit = probe::limit(len);
+        let limit = fault::probe_limit(probe::limit(len));
 
         let meta = iter::repeat_with(|| AtomicU8::new(super::meta::EMPTY))
             .take(len)
@@ -246,6 +247,11 @@ impl<T> Table<T> {
         };
     }
 
+    // Returns the number of bytes used by a table allocation of the given length.
+    pub fn size(len: usize) -> usize {
+        Self::layout(len).size()
+    }
+
     // Returns the non-zero layout for a table allocation.
     fn layout(len: usize) -> Layout {
         let size = mem::size_of::<TableLayout<T>>()
@@ -1,21 +1,31 @@
 mod alloc;
+mod moving;
 mod probe;
 
 pub(crate) mod utils;
 
+use std::collections::HashSet;
+use std::future::Future;
 use std::gc::Gc;
 use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
-use std::sync::Mutex;
-use std::{hint, panic, ptr};
+use std::sync::Arc;
+use std::time::Instant;
+use std::{mem, panic, ptr};
 
 use self::alloc::{RawTable, Table};
 use self::probe::Probe;
-use self::utils::{untagged, AtomicPtrFetchOps, Counter, Parker, StrictProvenance, Tagged};
-use crate::map::{Compute, Operation, ResizeMode};
-use crate::Equivalent;
+use self::utils::{
+    fault, random, untagged, AtomicPtrFetchOps, Counter, Epoch, EpochGuard, Initializers, Observer,
+    Parker, StrictProvenance, Tagged,
+};
+use crate::log::{ChangeLog, Changes, LaggedError};
+use crate::map::{Compute, CounterMode, Operation, ResizeMode, ResizeStatus, Stats};
+use crate::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+use crate::sync::{hint, thread, AtomicMut, Mutex};
+use crate::watch::{Change, Receiver, Watcher, Watchers};
+use crate::{Equivalent, MapObserver};
 
 use seize::{Collector, LocalGuard, OwnedGuard};
 use utils::{MapGuard, Stack, VerifiedGuard};
@@ -29,6 +39,8 @@ pub struct HashMap<K, V, S> {
     resize: ResizeMode,
 
     /// An atomic counter of the number of keys in the table.
+    ///
+    /// Note that the counter may be disabled, in which case the length is estimated.
     count: Counter,
 
     /// The initial capacity provided to `HashMap::new`.
@@ -36,10 +48,95 @@ pub struct HashMap<K, V, S> {
     /// The table is guaranteed to never shrink below this capacity.
     initial_capacity: usize,
 
+    /// Observer for internal events.
+    observer: Observer,
+
+    /// State for consistent snapshots of the table, if enabled.
+    snapshots: Option<Snapshots<K, V>>,
+
+    /// Subscriptions to changes in the table.
+    watchers: Watchers<K, V>,
+
+    /// A log of changes to the table, if enabled.
+    change_log: Option<ChangeLog<K, V>>,
+
+    /// Keys being initialized by `get_or_try_insert_with_once`.
+    initializers: Initializers,
+
     /// Hasher for keys.
     pub hasher: S,
 }
 
+/// State for consistent snapshots of the table.
+///
+/// When snapshots are enabled, every write to the table is performed within an epoch, and new entries are stamped with
+/// the epoch they were inserted in. To take a snapshot, the epoch is advanced to an odd
+/// capture epoch, after waiting for any writers of the previous epoch. Entries that were
+/// inserted before the capture epoch are then collected by the snapshot, either as they
+/// are found in the table, or by writers in the capture epoch after they are replaced
+/// or removed.
+struct Snapshots<K, V> {
+    /// The epoch of writers to the table.
+    epoch: Epoch,
+
+    /// The latest capture epoch in which writers are allowed to proceed.
+    ///
+    /// Writers in a capture epoch must wait for writers of the previous epoch to exit,
+    /// as their writes would otherwise be ordered before writes that are not part of
+    /// the snapshot.
+    ready: AtomicUsize,
+
+    /// Entries captured by writers in the current capture epoch.
+    captured: Mutex<Vec<*mut Entry<K, V>>>,
+
+    /// A lock that ensures only one snapshot is taken at a time.
+    lock: Mutex<()>,
+}
+
+// Safety: Captured entries are only ever read through shared references, by the snapshot
+// that collects them.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Snapshots<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Snapshots<K, V> {}
+
+impl<K, V> Snapshots<K, V> {
+    /// Create a new `Snapshots`.
+    fn new() -> Snapshots<K, V> {
+        Snapshots {
+            epoch: Epoch::new(),
+            ready: AtomicUsize::new(0),
+            captured: Mutex::new(Vec::new()),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Waits for writers of the epoch before the given capture epoch to exit.
+    ///
+    /// Writes in the capture epoch are not part of the snapshot, and so must not be
+    /// ordered before any writes that are.
+    #[cold]
+    #[inline(never)]
+    fn wait_for_capture(&self, epoch: usize) {
+        while self.ready.load(Ordering::Acquire) < epoch {
+            thread::yield_now();
+        }
+    }
+
+    /// Captures an entry that was replaced or removed for the snapshot being taken in
+    /// the given capture epoch.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must be a valid entry that was inserted into the map.
+    #[cold]
+    #[inline(never)]
+    unsafe fn capture(&self, entry: *mut Entry<K, V>, epoch: usize) {
+        // Safety: Guaranteed by caller.
+        if unsafe { Entry::inserted_before(entry, epoch) } {
+            self.captured.lock().unwrap().push(entry);
+        }
+    }
+}
+
 /// Resize state for the hash-table.
 pub struct State<T> {
     /// The next table used for resizing.
@@ -90,6 +187,26 @@ impl State<()> {
     pub const PROMOTED: u8 = 2;
 }
 
+// Hashes the keys stored in a table.
+//
+// This is implemented for every `BuildHasher`, and allows tables that hash
+// their keys through other means to share the table engine.
+pub trait KeyHasher<K> {
+    // Returns the hash of the given key.
+    fn hash_key(&self, key: &K) -> u64;
+}
+
+impl<K, S> KeyHasher<K> for S
+where
+    K: Hash,
+    S: BuildHasher,
+{
+    #[inline]
+    fn hash_key(&self, key: &K) -> u64 {
+        self.hash_one(key)
+    }
+}
+
 // The result of an insert operation.
 pub enum InsertResult<'g, V> {
     /// Inserted the given value.
@@ -104,21 +221,27 @@ pub enum InsertResult<'g, V> {
 
 // The raw result of an insert operation.
 pub enum RawInsertResult<'g, K, V> {
-    /// Inserted the given value.
-    Inserted(&'g V),
+    /// Inserted the given entry.
+    ///
+    /// The entry is valid for reads for the lifetime of the guard.
+    Inserted(*mut Entry<K, V>),
 
     /// Replaced the given value.
     Replaced(&'g V),
 
     /// Error returned by `try_insert`.
+    ///
+    /// The current entry is valid for reads for the lifetime of the guard.
     Error {
-        current: &'g V,
+        current: *mut Entry<K, V>,
         not_inserted: *mut Entry<K, V>,
     },
 }
 
 // An entry in the hash-table.
-#[repr(C)]
+//
+// Entries are aligned to leave room for the tag bits.
+#[repr(C, align(8))]
 pub struct Entry<K, V> {
     /// The key for this entry.
     pub key: K,
@@ -127,6 +250,73 @@ pub struct Entry<K, V> {
     pub value: V,
 }
 
+// An entry stamped with the epoch in which it was inserted.
+//
+// If snapshots are enabled, every entry of the table, including markers, is allocated with
+// a stamp in front of it. Pointers to the entry point past the stamp, so the entry can be
+// used like any other, and maps without snapshots do not pay for the epoch.
+#[repr(C)]
+struct Stamped<T> {
+    /// The epoch in which the entry was inserted.
+    epoch: AtomicUsize,
+
+    /// The entry.
+    entry: T,
+}
+
+impl<T> Stamped<T> {
+    /// The offset of the entry from the start of the allocation.
+    const OFFSET: usize = {
+        let align = mem::align_of::<T>();
+        (mem::size_of::<AtomicUsize>() + align - 1) & !(align - 1)
+    };
+
+    /// Allocates an entry, stamping it if `stamped` is `true`.
+    #[inline]
+    fn alloc(entry: T, stamped: bool) -> *mut T {
+        if !stamped {
+            return Gc::into_raw(Gc::new(entry)) as *mut T;
+        }
+
+        let stamped = Gc::into_raw(Gc::new(Stamped {
+            epoch: AtomicUsize::new(0),
+            entry,
+        })) as *mut Stamped<T>;
+
+        // Safety: We just allocated the entry.
+        unsafe { ptr::addr_of_mut!((*stamped).entry) }
+    }
+
+    /// Deallocates an entry allocated with `Stamped::alloc`, returning its contents.
+    ///
+    /// # Safety
+    ///
+    /// The entry must have been allocated with `Stamped::alloc` with the same value of
+    /// `stamped`, and must not be accessed after this call.
+    #[inline]
+    unsafe fn dealloc(entry: *mut T, stamped: bool) -> T {
+        if !stamped {
+            // Safety: Guaranteed by caller.
+            return *unsafe { Box::from_raw(entry) };
+        }
+
+        // Safety: Guaranteed by caller.
+        unsafe { Box::from_raw(Stamped::from_entry(entry)).entry }
+    }
+
+    /// Returns the stamped allocation of an entry.
+    ///
+    /// # Safety
+    ///
+    /// The entry must have been allocated with `Stamped::alloc` with `stamped` set
+    /// to `true`.
+    #[inline]
+    unsafe fn from_entry(entry: *mut T) -> *mut Stamped<T> {
+        // Safety: Guaranteed by caller. The entry is at a fixed offset in the allocation.
+        unsafe { entry.cast::<u8>().sub(Self::OFFSET).cast::<Stamped<T>>() }
+    }
+}
+
 impl Entry<(), ()> {
     /// The entry is being copied to the new table, no updates are allowed on the old table.
     ///
@@ -142,18 +332,17 @@ impl Entry<(), ()> {
     /// In blocking mode this is unused.
     const COPIED: usize = 0b010;
 
-    /// The entry was copied from a previous table.
-    ///
-    /// This bit indicates that an entry may still be accessible from previous tables
-    /// because the resize is still in progress, and so it is unsafe to reclaim.
+    /// The entry is a marker for an entry being moved to another key or table.
     ///
-    /// In blocking mode this is unused.
-    const BORROWED: usize = 0b100;
+    /// Whether the entry is present depends on the status of the move, see `Marker`
+    /// for details. Readers must check its presence, and writers must settle the move
+    /// before making progress.
+    const MOVING: usize = 0b100;
 }
 
 impl<K, V> utils::Unpack for Entry<K, V> {
     /// Mask for an entry pointer, ignoring any tag bits.
-    const MASK: usize = !(Entry::COPYING | Entry::COPIED | Entry::BORROWED);
+    const MASK: usize = !(Entry::COPYING | Entry::COPIED | Entry::MOVING);
 }
 
 impl<K, V> Entry<K, V> {
@@ -163,6 +352,53 @@ impl<K, V> Entry<K, V> {
     /// Note that tombstone entries may still be marked as `COPYING`, so this state
     /// cannot be used for direct equality.
     const TOMBSTONE: *mut Entry<K, V> = Entry::COPIED as _;
+
+    /// Create a new `Entry`.
+    #[inline]
+    pub fn new(key: K, value: V) -> Entry<K, V> {
+        Entry { key, value }
+    }
+
+    /// Returns the epoch in which the given entry was inserted.
+    ///
+    /// # Safety
+    ///
+    /// The entry must be a valid entry of a table with snapshots enabled.
+    #[inline]
+    unsafe fn epoch<'a>(entry: *mut Entry<K, V>) -> &'a AtomicUsize {
+        // Safety: Guaranteed by caller. Entries of a table with snapshots enabled are
+        // stamped, and markers are aligned like the entries they are prefixed with.
+        unsafe { &(*Stamped::from_entry(entry)).epoch }
+    }
+
+    /// Returns `true` if the given entry was inserted before the given capture epoch.
+    ///
+    /// # Safety
+    ///
+    /// The entry must be a valid entry of a table with snapshots enabled.
+    #[inline]
+    unsafe fn inserted_before(entry: *mut Entry<K, V>, epoch: usize) -> bool {
+        // Safety: Guaranteed by caller.
+        unsafe { Entry::epoch(entry) }.load(Ordering::Relaxed) < epoch
+    }
+}
+
+/// The number of random slots probed to pick an entry before falling back to a linear scan.
+///
+/// For a table that is at least a quarter full, the fallback is taken less than once in
+/// ten thousand calls.
+const RANDOM_PROBES: usize = 32;
+
+/// A write to the table announced by `begin_write`.
+struct Writer<'a, K, V> {
+    /// The epoch the write is performed in, if snapshots are enabled.
+    guard: Option<EpochGuard<'a>>,
+
+    /// The entry being written, or null for a deletion.
+    new_entry: *mut Entry<K, V>,
+
+    /// The sequence number of the write in the change log, or zero if disabled.
+    seq: u64,
 }
 
 /// The status of an entry.
@@ -212,7 +448,15 @@ enum InsertStatus<K, V> {
 impl<K, V, S> HashMap<K, V, S> {
     /// Creates new hash-table with the given options.
     #[inline]
-    pub fn new(capacity: usize, hasher: S, resize: ResizeMode) -> HashMap<K, V, S> {
+    pub fn new(
+        capacity: usize,
+        hasher: S,
+        resize: ResizeMode,
+        counter: CounterMode,
+        observer: Option<Arc<dyn MapObserver>>,
+        change_log: Option<usize>,
+        snapshots: bool,
+    ) -> HashMap<K, V, S> {
         // The table is lazily allocated.
         if capacity == 0 {
             return HashMap {
@@ -220,20 +464,30 @@ impl<K, V, S> HashMap<K, V, S> {
                 hasher,
                 initial_capacity: 1,
                 table: AtomicPtr::new(ptr::null_mut()),
-                count: Counter::default(),
+                count: Counter::new(counter),
+                observer: Observer::new(observer),
+                snapshots: snapshots.then(Snapshots::new),
+                watchers: Watchers::new(),
+                change_log: change_log.map(ChangeLog::new),
+                initializers: Initializers::new(),
             };
         }
 
         // Initialize the table and mark it as the root.
         let mut table = Table::alloc(probe::entries_for(capacity));
-        *table.state_mut().status.get_mut() = State::PROMOTED;
+        table.state_mut().status.store_mut(State::PROMOTED);
 
         HashMap {
             hasher,
             resize,
             initial_capacity: capacity,
             table: AtomicPtr::new(table.raw),
-            count: Counter::default(),
+            count: Counter::new(counter),
+            observer: Observer::new(observer),
+            snapshots: snapshots.then(Snapshots::new),
+            watchers: Watchers::new(),
+            change_log: change_log.map(ChangeLog::new),
+            initializers: Initializers::new(),
         }
     }
 
@@ -292,150 +546,531 @@ impl<K, V, S> HashMap<K, V, S> {
     /// Returns the number of entries in the table.
     #[inline]
     pub fn len(&self) -> usize {
-        self.count.sum()
+        match self.count.sum() {
+            Some(len) => len,
+
+            // The counter is disabled, estimate the length instead.
+            None => self.estimate_len(),
+        }
     }
 
-    /// Returns true if incremental resizing is enabled.
+    /// Returns `true` if the table contains no entries.
     #[inline]
-    fn is_incremental(&self) -> bool {
-        matches!(self.resize, ResizeMode::Incremental(_))
+    pub fn is_empty(&self) -> bool {
+        match self.count.sum() {
+            Some(len) => len == 0,
+
+            // The counter is disabled, search for a live entry instead.
+            None => self.count_live(1) == 0,
+        }
     }
-}
 
-impl<K, V, S> HashMap<K, V, S>
-where
-    K: Hash + Eq,
-    S: BuildHasher,
-{
-    /// Returns a reference to the entry corresponding to the key.
-    #[inline]
-    pub fn get<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
-    where
-        Q: Equivalent<K> + Hash + ?Sized,
-    {
-        // Load the root table.
+    /// Counts the live entries in the table, stopping once `limit` entries are found.
+    ///
+    /// Unlike `estimate_len`, this visits every entry, including entries in any tables
+    /// being resized into. Entries that are concurrently copied to the next table may be
+    /// counted twice or not at all.
+    #[cold]
+    #[inline(never)]
+    fn count_live(&self, limit: usize) -> usize {
         let mut table = self.root();
 
         // The table has not been initialized yet.
         if table.raw.is_null() {
-            return None;
+            return 0;
         }
 
-        let (h1, h2) = self.hash(key);
-
+        let mut live = 0;
         loop {
-            // Initialize the probe state.
-            let mut probe = Probe::start(h1, table.mask);
+            for i in 0..table.len() {
+                // Safety: `i` is in bounds for the table length.
+                let entry = unsafe { table.entry(i).load(Ordering::Acquire) }.unpack();
 
-            // Probe until we reach the limit.
-            'probe: while probe.len <= table.limit {
-                // Load the entry metadata first for cheap searches.
-                //
-                // Safety: `probe.i` is always in-bounds for the table length.
-                let meta = unsafe { table.meta(probe.i) }.load(Ordering::Acquire);
+                // The entry is empty, deleted, or was copied to the next table, which we
+                // visit next.
+                if entry.ptr.is_null() || entry.tag() & Entry::COPIED != 0 {
+                    continue;
+                }
 
-                if meta == h2 {
-                    // Load the full entry.
-                    //
-                    // Safety: `probe.i` is always in-bounds for the table length.
-                    let entry = unsafe { table.entry(probe.i).load(Ordering::Acquire).unpack() };
+                // Safety: We performed an `Acquire` load of a non-null entry pointer.
+                if unsafe { Entry::visible(entry) }.is_some() {
+                    live += 1;
 
-                    // The entry was deleted, keep probing.
-                    if entry.ptr.is_null() {
-                        probe.next(table.mask);
-                        continue 'probe;
+                    if live >= limit {
+                        return live;
                     }
+                }
+            }
 
-                    // Safety: We performed a protected load of the pointer using a verified guard with
-                    // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
-                    // as we hold the guard.
-                    let entry_ref = unsafe { &(*entry.ptr) };
+            match table.next_table() {
+                Some(next) => table = next,
+                None => return live,
+            }
+        }
+    }
 
-                    // Check for a full match.
-                    if key.equivalent(&entry_ref.key) {
-                        // The entry was copied to the new table.
-                        //
-                        // In blocking resize mode we do not need to perform self check as all writes block
-                        // until any resizes are complete, making the root table the source of truth for readers.
-                        if entry.tag() & Entry::COPIED != 0 {
-                            break 'probe;
-                        }
+    /// Estimates the number of entries in the table based on the occupancy of the root table.
+    ///
+    /// Note that this may not reflect entries that are only present in a table that is
+    /// being resized into.
+    #[cold]
+    #[inline(never)]
+    fn estimate_len(&self) -> usize {
+        // The maximum number of entries to sample.
+        const SAMPLE: usize = 1024;
 
-                        // Found the correct entry, return the key and value.
-                        return Some((&entry_ref.key, &entry_ref.value));
-                    }
-                }
+        // Load the root table.
+        let table = self.root();
 
-                // The key is not in the table.
-                //
-                // It also cannot be in the next table because we have not went over the probe limit.
-                if meta == meta::EMPTY {
-                    return None;
-                }
+        // The table has not been initialized yet.
+        if table.raw.is_null() {
+            return 0;
+        }
 
-                probe.next(table.mask);
+        // Sample the metadata table, which is cheaper than loading entries. The sampled
+        // slots are spread evenly across the table, so with a well distributed hash
+        // function, the occupancy of the sample is representative of the occupancy of
+        // the entire table. Both lengths are powers of two, so the step is exact.
+        let step = table.len() / table.len().min(SAMPLE);
+        let occupied = (0..table.len())
+            .step_by(step)
+            .filter(|&i| {
+                // Safety: `i` is in bounds for the table length.
+                let meta = unsafe { table.meta(i) }.load(Ordering::Relaxed);
+                !matches!(meta, meta::EMPTY | meta::TOMBSTONE)
+            })
+            .count();
+
+        occupied * step
+    }
+
+    /// Returns the size of an entry allocation, including the epoch stamp if snapshots are
+    /// enabled.
+    #[inline]
+    fn entry_size(&self) -> usize {
+        match self.snapshots {
+            Some(_) => mem::size_of::<Stamped<Entry<K, V>>>(),
+            None => mem::size_of::<Entry<K, V>>(),
+        }
+    }
+
+    /// Returns the number of bytes allocated by the table, including the bytes owned
+    /// by each entry as reported by `entry_size`.
+    pub fn heap_size(&self, entry_size: impl Fn(&K, &V) -> usize) -> usize {
+        let mut size = self.count.heap_size()
+            + self
+                .snapshots
+                .as_ref()
+                .map_or(0, |snapshots| snapshots.epoch.heap_size())
+            + self.watchers.heap_size();
+        if let Some(log) = &self.change_log {
+            size += log.heap_size();
+        }
+        let mut table = Some(self.root());
+
+        while let Some(current) = table {
+            // The table has not been initialized yet.
+            if current.raw.is_null() {
+                break;
             }
 
-            // In incremental resize mode, we have to check the next table if we found
-            // a copied entry or went over the probe limit.
-            if self.is_incremental() {
-                if let Some(next) = table.next_table() {
-                    table = next;
+            size += Table::<Entry<K, V>>::size(current.len());
+            size += current.state().deferred.heap_size();
+
+            for i in 0..current.len() {
+                // Safety: `i` is in bounds for the table length.
+                let entry = unsafe { current.entry(i).load(Ordering::Acquire) }.unpack();
+
+                // Entries that were copied are accounted for in the next table.
+                if entry.ptr.is_null() || entry.tag() & Entry::COPIED != 0 {
                     continue;
                 }
+
+                // Safety: We performed an `Acquire` load of a non-null entry pointer,
+                // and entries are never deallocated while reachable from the map.
+                let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                    continue;
+                };
+
+                // Safety: Visible entries are valid for reads.
+                let entry = unsafe { &*entry };
+                size += self.entry_size() + entry_size(&entry.key, &entry.value);
             }
 
-            // Otherwise, the key is not in the table.
-            return None;
+            table = current.next_table();
         }
+
+        size
+    }
+
+    /// Returns true if incremental resizing is enabled.
+    #[inline]
+    fn is_incremental(&self) -> bool {
+        matches!(self.resize, ResizeMode::Incremental(_))
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns a reference to the entry corresponding to the key.
+    #[inline]
+    pub fn get<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        self.get_with_hash(hash, |k| self.equivalent(hash, key, k))
     }
 
     /// Inserts a key-value pair into the table.
     #[inline]
     pub fn insert<'g>(&self, key: K, value: V, replace: bool) -> InsertResult<'g, V> {
-        // Perform the insert.
-        let raw_result = self.insert_inner(key, value, replace);
+        let hash = self.hasher.hash_one(&key);
+        self.insert_with_hash(hash, key, value, replace, K::eq)
+    }
 
-        let result = match raw_result {
-            // Updated an entry.
-            RawInsertResult::Replaced(value) => InsertResult::Replaced(value),
+    /// Subscribes to changes of the given key.
+    #[inline]
+    pub fn watch(&self, key: K) -> Watcher<K, V> {
+        let hash = self.hasher.hash_one(&key);
+        self.watchers.watch(hash, key)
+    }
 
-            // Inserted a new entry.
-            RawInsertResult::Inserted(value) => {
-                // Increment the table length.
-                self.count.get().fetch_add(1, Ordering::Relaxed);
+    /// Subscribes to changes of every key.
+    #[inline]
+    pub fn watch_all(&self) -> Receiver<Change<K, V>> {
+        self.watchers.watch_all()
+    }
 
-                InsertResult::Inserted(value)
-            }
+    /// Blocks until the given key is present in the table, or the deadline passes.
+    pub fn wait_for<'g, Q>(&self, key: &Q, deadline: Option<Instant>) -> Option<(&'g K, &'g V)>
+    where
+        K: 'g,
+        V: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let eq = |k: &K| self.equivalent(hash, key, k);
 
-            // Failed to insert the entry.
-            RawInsertResult::Error {
-                current,
-                not_inserted,
-            } => {
-                // Safety: We allocated this box above and it was not inserted into the table.
-                let not_inserted = unsafe { Box::from_raw(not_inserted) };
+        // Avoid subscribing if the key is already present.
+        if let Some(entry) = self.get_with_hash(hash, eq) {
+            return Some(entry);
+        }
 
-                InsertResult::Error {
-                    current,
-                    not_inserted: not_inserted.value,
-                }
+        // Register before checking again, so that an insert of the key is either observed
+        // or notifies us.
+        let waiting = self.watchers.wait(hash);
+
+        loop {
+            if let Some(entry) = self.get_with_hash(hash, eq) {
+                return Some(entry);
             }
-        };
 
-        result
+            // Wait for a change to a key with the same hash.
+            if !waiting.wait(deadline) {
+                return None;
+            }
+        }
     }
 
-    /// Inserts an entry into the map.
+    /// Waits asynchronously until the given key is present in the table.
+    pub async fn wait_for_async<'g, Q>(&self, key: &Q) -> (&'g K, &'g V)
+    where
+        K: 'g,
+        V: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let eq = |k: &K| self.equivalent(hash, key, k);
+
+        // Avoid subscribing if the key is already present.
+        if let Some(entry) = self.get_with_hash(hash, eq) {
+            return entry;
+        }
+
+        // Register before checking again, see `wait_for`.
+        let waiting = self.watchers.wait(hash);
+
+        loop {
+            if let Some(entry) = self.get_with_hash(hash, eq) {
+                return entry;
+            }
+
+            // Wait for a change to a key with the same hash.
+            waiting.notified().await;
+        }
+    }
+
+    /// Removes a key from the map, returning the entry for the key if the key was previously in the map.
+    #[inline]
+    pub fn remove<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        #[inline(always)]
+        fn should_remove<K, V>(_key: &K, _value: &V) -> bool {
+            true
+        }
+
+        // Safety: `should_remove` unconditionally returns `true`.
+        unsafe { self.remove_if(key, should_remove).unwrap_unchecked() }
+    }
+
+    /// Removes a key from the map, returning the entry for the key if the key was previously in the map
+    /// and the provided closure returns `true`
+    #[inline]
+    pub fn remove_if<'g, Q, F>(
+        &self,
+        key: &Q,
+        should_remove: F,
+    ) -> Result<Option<(&'g K, &'g V)>, (&'g K, &'g V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+        F: FnMut(&K, &V) -> bool,
+    {
+        let hash = self.hasher.hash_one(key);
+        let result =
+            self.remove_if_with_hash(hash, |k| self.equivalent(hash, key, k), should_remove);
+
+        self.debug_invariants();
+        result
+    }
+
+    /// Returns `true` if the key with the given hash is equivalent to a stored key.
+    #[inline(always)]
+    fn equivalent<Q>(&self, hash: u64, key: &Q, found: &K) -> bool
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let equivalent = key.equivalent(found);
+
+        if equivalent {
+            self.debug_hash(hash, key, found);
+        }
+
+        equivalent
+    }
+
+    /// Verifies that a key with the given hash hashes consistently with an equivalent stored
+    /// key, if the `debug_hash` feature is enabled.
+    #[inline(always)]
+    #[cfg_attr(not(feature = "debug_hash"), allow(unused_variables))]
+    fn debug_hash<Q>(&self, hash: u64, key: &Q, found: &K)
+    where
+        Q: Hash + ?Sized,
+    {
+        #[cfg(feature = "debug_hash")]
+        {
+            assert!(
+                self.hasher.hash_one(key) == hash,
+                "`Hash` implementation is not deterministic: \
+                 the same key produced different hashes"
+            );
+
+            let found_hash = self.hasher.hash_one(found);
+            assert!(
+                self.hasher.hash_one(found) == found_hash,
+                "`Hash` implementation is not deterministic: \
+                 the same stored key produced different hashes"
+            );
+
+            assert!(
+                found_hash == hash,
+                "`Hash` implementation is inconsistent with `Eq` or `Equivalent`: \
+                 equivalent keys produced different hashes ({hash:#x} and {found_hash:#x})"
+            );
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    S: KeyHasher<K>,
+{
+    /// Returns a reference to the entry with the given hash that matches the equality function.
+    #[inline]
+    pub fn get_with_hash<'g, F>(&self, hash: u64, mut eq: F) -> Option<(&'g K, &'g V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        // Load the root table.
+        let mut table = self.root();
+
+        // The table has not been initialized yet.
+        if table.raw.is_null() {
+            return None;
+        }
+
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
+
+        loop {
+            // Initialize the probe state.
+            let mut probe = Probe::start(h1, table.mask);
+
+            // Probe until we reach the limit.
+            'probe: while probe.len <= table.limit {
+                // Load the entry metadata first for cheap searches.
+                //
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let meta = unsafe { table.meta(probe.i) }.load(Ordering::Acquire);
+
+                if meta == h2 {
+                    // Load the full entry.
+                    //
+                    // Safety: `probe.i` is always in-bounds for the table length.
+                    let entry = unsafe { table.entry(probe.i).load(Ordering::Acquire).unpack() };
+
+                    // The entry was deleted, keep probing.
+                    if entry.ptr.is_null() {
+                        probe.next(table.mask);
+                        continue 'probe;
+                    }
+
+                    // Safety: We performed a protected load of the pointer using a verified guard with
+                    // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
+                    // as we hold the guard.
+                    let entry_ref = unsafe { &(*entry.ptr) };
+
+                    // Check for a full match.
+                    if eq(&entry_ref.key) {
+                        // The entry was copied to the new table.
+                        //
+                        // In blocking resize mode we do not need to perform self check as all writes block
+                        // until any resizes are complete, making the root table the source of truth for readers.
+                        if entry.tag() & Entry::COPIED != 0 {
+                            break 'probe;
+                        }
+
+                        // The entry is a marker for a move that did not take effect, keep probing.
+                        //
+                        // Safety: `entry` is a valid non-null entry that we found in the map.
+                        let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                            probe.next(table.mask);
+                            continue 'probe;
+                        };
+
+                        // Found the correct entry, return the key and value.
+                        //
+                        // Safety: Visible entries are valid for reads.
+                        let entry_ref = unsafe { &(*entry) };
+                        return Some((&entry_ref.key, &entry_ref.value));
+                    }
+                }
+
+                // The key is not in the table.
+                //
+                // It also cannot be in the next table because we have not went over the probe limit.
+                if meta == meta::EMPTY {
+                    return None;
+                }
+
+                probe.next(table.mask);
+            }
+
+            // In incremental resize mode, we have to check the next table if we found
+            // a copied entry or went over the probe limit.
+            if self.is_incremental() {
+                if let Some(next) = table.next_table() {
+                    table = next;
+                    continue;
+                }
+            }
+
+            // Otherwise, the key is not in the table.
+            return None;
+        }
+    }
+
+    /// Inserts a key-value pair with the given hash into the table.
+    ///
+    /// `eq` is called with an existing key and the key being inserted.
+    #[inline]
+    pub fn insert_with_hash<'g, E>(
+        &self,
+        hash: u64,
+        key: K,
+        value: V,
+        replace: bool,
+        eq: E,
+    ) -> InsertResult<'g, V>
+    where
+        E: FnMut(&K, &K) -> bool,
+    {
+        match self.insert_entry(hash, key, value, replace, eq) {
+            // Updated an entry.
+            RawInsertResult::Replaced(value) => InsertResult::Replaced(value),
+
+            // Inserted a new entry.
+            //
+            // Safety: The entry was just inserted and is valid for reads.
+            RawInsertResult::Inserted(entry) => InsertResult::Inserted(unsafe { &(*entry).value }),
+
+            // Failed to insert the entry.
+            RawInsertResult::Error {
+                current,
+                not_inserted,
+            } => {
+                // Safety: We allocated this entry above and it was not inserted into the table.
+                let not_inserted =
+                    unsafe { Stamped::dealloc(not_inserted, self.snapshots.is_some()) };
+
+                InsertResult::Error {
+                    // Safety: The current entry was found in the map and is valid for reads.
+                    current: unsafe { &(*current).value },
+                    not_inserted: not_inserted.value,
+                }
+            }
+        }
+    }
+
+    /// Inserts a key-value pair with the given hash into the table, returning the raw entries.
+    ///
+    /// `eq` is called with an existing key and the key being inserted.
+    #[inline]
+    pub fn insert_entry<'g, E>(
+        &self,
+        hash: u64,
+        key: K,
+        value: V,
+        replace: bool,
+        eq: E,
+    ) -> RawInsertResult<'g, K, V>
+    where
+        E: FnMut(&K, &K) -> bool,
+    {
+        // Perform the insert.
+        let result = self.insert_inner(hash, key, value, replace, eq);
+
+        // Increment the table length.
+        if let RawInsertResult::Inserted(_) = result {
+            self.count.increment();
+        }
+
+        self.debug_invariants();
+        result
+    }
+
+    /// Inserts an entry into the map.
     #[inline]
-    fn insert_inner<'g>(
+    fn insert_inner<'g, E>(
         &self,
+        hash: u64,
         key: K,
         value: V,
         should_replace: bool,
-    ) -> RawInsertResult<'g, K, V> {
+        mut eq: E,
+    ) -> RawInsertResult<'g, K, V>
+    where
+        E: FnMut(&K, &K) -> bool,
+    {
         // Allocate the entry to be inserted.
-        let new_entry = untagged(Gc::into_raw(Gc::new(Entry { key, value })) as *mut Entry<K, V>);
+        let new_entry = untagged(Stamped::alloc(
+            Entry::new(key, value),
+            self.snapshots.is_some(),
+        ));
 
         // Safety: We just allocated the entry above.
         let new_ref = unsafe { &(*new_entry.ptr) };
@@ -448,7 +1083,7 @@ where
             table = self.init(None);
         }
 
-        let (h1, h2) = self.hash(&new_ref.key);
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
 
         let mut help_copy = true;
         loop {
@@ -458,6 +1093,7 @@ where
             // Probe until we reach the limit.
             let copying = 'probe: loop {
                 if probe.len > table.limit {
+                    self.observer.probe_limit_exceeded(table.len());
                     break None;
                 }
 
@@ -474,7 +1110,11 @@ where
                     // `new_entry` was allocated above and never shared.
                     match unsafe { self.insert_at(probe.i, h2, new_entry.raw, table) } {
                         // Successfully inserted.
-                        InsertStatus::Inserted => return RawInsertResult::Inserted(&new_ref.value),
+                        InsertStatus::Inserted => {
+                            // Safety: We just inserted the entry.
+                            unsafe { self.notify(hash, ptr::null_mut(), new_entry.ptr) };
+                            return RawInsertResult::Inserted(new_entry.ptr);
+                        }
 
                         // Lost to a concurrent insert.
                         //
@@ -517,7 +1157,7 @@ where
                 let entry_ref = unsafe { &(*entry.ptr) };
 
                 // Check for a full match.
-                if entry_ref.key != new_ref.key {
+                if !eq(&entry_ref.key, &new_ref.key) {
                     probe.next(table.mask);
                     continue 'probe;
                 }
@@ -527,10 +1167,18 @@ where
                     break 'probe Some(probe.i);
                 }
 
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `probe.i` is always in-bounds for the table length, and `entry`
+                    // is a valid non-null entry that we found in the map.
+                    unsafe { self.settle_at(probe.i, entry, table) };
+                    continue 'probe;
+                }
+
                 // Return an error for calls to `try_insert`.
                 if !should_replace {
                     return RawInsertResult::Error {
-                        current: &entry_ref.value,
+                        current: entry.ptr,
                         not_inserted: new_entry.ptr,
                     };
                 }
@@ -543,6 +1191,9 @@ where
                 match unsafe { self.insert_slow(probe.i, entry, new_entry.raw, table) } {
                     // Successfully performed the update.
                     UpdateStatus::Replaced(entry) => {
+                        // Safety: We just replaced the entry.
+                        unsafe { self.notify(hash, entry.ptr, new_entry.ptr) };
+
                         // Safety: `entry` is a valid non-null entry that we found in the map
                         // before replacing it.
                         let value = unsafe { &(*entry.ptr).value };
@@ -592,7 +1243,10 @@ where
                 //
                 // Note that the pointer we find here is a non-null entry that was inserted
                 // into the map.
-                UpdateStatus::Found(EntryStatus::Value(found)) => entry = found,
+                UpdateStatus::Found(EntryStatus::Value(found)) => {
+                    self.observer.cas_retry();
+                    entry = found;
+                }
 
                 status => return status,
             }
@@ -643,11 +1297,12 @@ where
         next_table
     }
 
-    /// Removes a key from the map, returning the entry for the key if the key was previously in the map.
+    /// Removes the entry with the given hash that matches the equality function, returning the
+    /// entry if it was previously in the map.
     #[inline]
-    pub fn remove<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
+    pub fn remove_with_hash<'g, F>(&self, hash: u64, eq: F) -> Option<(&'g K, &'g V)>
     where
-        Q: Equivalent<K> + Hash + ?Sized,
+        F: FnMut(&K) -> bool,
     {
         #[inline(always)]
         fn should_remove<K, V>(_key: &K, _value: &V) -> bool {
@@ -655,19 +1310,26 @@ where
         }
 
         // Safety: `should_remove` unconditionally returns `true`.
-        unsafe { self.remove_if(key, should_remove).unwrap_unchecked() }
+        let result = unsafe {
+            self.remove_if_with_hash(hash, eq, should_remove)
+                .unwrap_unchecked()
+        };
+
+        self.debug_invariants();
+        result
     }
 
-    /// Removes a key from the map, returning the entry for the key if the key was previously in the map
-    /// and the provided closure returns `true`
+    /// Removes the entry with the given hash that matches the equality function, returning the
+    /// entry if it was previously in the map and the provided closure returns `true`.
     #[inline]
-    pub fn remove_if<'g, Q, F>(
+    pub fn remove_if_with_hash<'g, E, F>(
         &self,
-        key: &Q,
+        hash: u64,
+        mut eq: E,
         mut should_remove: F,
     ) -> Result<Option<(&'g K, &'g V)>, (&'g K, &'g V)>
     where
-        Q: Equivalent<K> + Hash + ?Sized,
+        E: FnMut(&K) -> bool,
         F: FnMut(&K, &V) -> bool,
     {
         // Load the root table.
@@ -678,7 +1340,7 @@ where
             return Ok(None);
         }
 
-        let (h1, h2) = self.hash(key);
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
 
         let mut help_copy = true;
         loop {
@@ -724,7 +1386,7 @@ where
                 // Safety: We performed a protected load of the pointer using a verified guard with
                 // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
                 // as we hold the guard.
-                if !key.equivalent(unsafe { &(*entry.ptr).key }) {
+                if !eq(unsafe { &(*entry.ptr).key }) {
                     probe.next(table.mask);
                     continue 'probe;
                 }
@@ -735,6 +1397,14 @@ where
                     break 'probe Some(probe.i);
                 }
 
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `probe.i` is always in-bounds for the table length, and `entry`
+                    // is a valid non-null entry that we found in the map.
+                    unsafe { self.settle_at(probe.i, entry, table) };
+                    continue 'probe;
+                }
+
                 loop {
                     // Safety: `entry` is a valid, non-null, protected entry that we found in the map.
                     let entry_ref = unsafe { &(*entry.ptr) };
@@ -751,7 +1421,7 @@ where
 
                     match status {
                         // Successfully removed the entry.
-                        UpdateStatus::Replaced(_entry) => {
+                        UpdateStatus::Replaced(entry) => {
                             // Mark the entry as a tombstone.
                             //
                             // Note that this might end up being overwritten by the metadata hash
@@ -766,7 +1436,10 @@ where
                             };
 
                             // Decrement the table length.
-                            self.count.get().fetch_sub(1, Ordering::Relaxed);
+                            self.count.decrement();
+
+                            // Safety: We just removed the entry.
+                            unsafe { self.notify(hash, entry.ptr, ptr::null_mut()) };
 
                             // Note that `entry_ref` here is the entry that we just replaced.
                             return Ok(Some((&entry_ref.key, &entry_ref.value)));
@@ -868,13 +1541,36 @@ where
         let entry = unsafe { table.entry(i) };
         let meta_entry = unsafe { table.meta(i) };
 
+        fault::preempt();
+
+        // Inject a concurrent insert and removal that claims the empty entry first.
+        if fault::fail_cas() {
+            let _ = entry.compare_exchange(
+                ptr::null_mut(),
+                Entry::TOMBSTONE,
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+        }
+
+        // Safety: The caller guarantees that `new_entry` is a valid owned pointer.
+        let writer = unsafe { self.begin_write(new_entry) };
+
         // Try to claim the empty entry.
-        let found = match entry.compare_exchange(
+        //
+        // Note that `SeqCst` is necessary for the insert to be observed by concurrent
+        // watchers, see `Watchers::subscribe`.
+        let result = entry.compare_exchange(
             ptr::null_mut(),
             new_entry,
-            Ordering::Release,
+            Ordering::SeqCst,
             Ordering::Acquire,
-        ) {
+        );
+
+        // Safety: We inserted into an empty entry if the CAS succeeded.
+        unsafe { self.end_write(writer, result.is_ok().then(ptr::null_mut)) };
+
+        let found = match result {
             // Successfully claimed the entry.
             Ok(_) => {
                 // Update the metadata table.
@@ -899,7 +1595,7 @@ where
                 //
                 // The logic is the same for copied entries here as we have to
                 // check if the key matches and continue the update in the new table.
-                let hash = self.hasher.hash_one(key);
+                let hash = self.hasher.hash_key(key);
                 (meta::h2(hash), EntryStatus::Value(found))
             }
 
@@ -915,47 +1611,216 @@ where
         InsertStatus::Found(status)
     }
 
-    /// Attempts to replace the value of an existing entry at the given index.
+    /// Announces a write to the table, returning a writer that must be passed to
+    /// `end_write` once the write is attempted.
     ///
-    /// In the case of an error, the returned pointer is guaranteed to be
-    /// protected and valid for reads.
+    /// The new entry is stamped with the current epoch. If a snapshot is being taken,
+    /// this waits for any writers that are part of the snapshot to complete. If the
+    /// change log is enabled, a sequence number is reserved for the write, so this must
+    /// be called after loading the entry being replaced.
     ///
     /// # Safety
     ///
-    /// - The index must be in-bounds for the table.
-    /// - `current` must be a valid non-null entry that was inserted into the map.
-    /// - `new_entry` must be a valid sentinel or owned pointer to insert into the map.
+    /// `new_entry` must be a valid sentinel or owned pointer to insert into the map.
     #[inline]
-    unsafe fn update_at(
-        &self,
-        i: usize,
-        current: Tagged<Entry<K, V>>,
-        new_entry: *mut Entry<K, V>,
-        table: Table<Entry<K, V>>,
-    ) -> UpdateStatus<K, V> {
-        // Safety: The caller guarantees that `i` is in-bounds.
-        let entry = unsafe { table.entry(i) };
+    unsafe fn begin_write(&self, new_entry: *mut Entry<K, V>) -> Writer<'_, K, V> {
+        // Safety: Guaranteed by caller.
+        unsafe { self.begin_write_in(self.enter_write(), new_entry) }
+    }
 
-        // Try to perform the update.
-        let found = match entry.compare_exchange_weak(
-            current.raw,
-            new_entry,
-            Ordering::Release,
-            Ordering::Acquire,
-        ) {
-            // Successfully updated.
-            Ok(_) => {
-                // Safety: The caller guarantees that `current` is a valid non-null entry that was
-                // inserted into the map. Additionally, it is now unreachable from this table due
-                // to the CAS above.
+    /// Enters the current epoch for a write, waiting for any writers that are part of a
+    /// snapshot being taken.
+    ///
+    /// Returns `None` if snapshots are not enabled.
+    #[inline]
+    fn enter_write(&self) -> Option<EpochGuard<'_>> {
+        let snapshots = self.snapshots.as_ref()?;
+        let guard = snapshots.epoch.enter();
 
-                return UpdateStatus::Replaced(current);
-            }
+        // Odd epochs are capture epochs.
+        if guard.epoch & 1 == 1 {
+            snapshots.wait_for_capture(guard.epoch);
+        }
 
-            // Lost to a concurrent update.
+        Some(guard)
+    }
+
+    /// Announces a write to the table in an epoch entered with `enter_write`.
+    ///
+    /// See `begin_write` for details.
+    ///
+    /// # Safety
+    ///
+    /// `new_entry` must be a valid sentinel or owned pointer to insert into the map, or
+    /// a marker for the new entry of a move that has not yet taken effect.
+    #[inline]
+    unsafe fn begin_write_in<'a>(
+        &'a self,
+        guard: Option<EpochGuard<'a>>,
+        new_entry: *mut Entry<K, V>,
+    ) -> Writer<'a, K, V> {
+        let new_entry = new_entry.unpack().ptr;
+        if let Some(guard) = &guard {
+            if !new_entry.is_null() {
+                // Safety: The entry is owned by us and not yet shared, or is not yet visible
+                // to readers that load its epoch.
+                unsafe { Entry::epoch(new_entry).store(guard.epoch, Ordering::Relaxed) };
+            }
+        }
+
+        let seq = match &self.change_log {
+            Some(log) => log.reserve(),
+            None => 0,
+        };
+
+        Writer {
+            guard,
+            new_entry,
+            seq,
+        }
+    }
+
+    /// Completes a write to the table, capturing the entry that was replaced or removed,
+    /// if any, for a snapshot being taken, and recording the change in the change log.
+    ///
+    /// `replaced` is the entry that was replaced or removed by the write, null if the write
+    /// inserted into an empty entry, or `None` if the write failed.
+    ///
+    /// # Safety
+    ///
+    /// `replaced` must be null or a valid entry that was removed from the map by the write.
+    #[inline]
+    unsafe fn end_write(&self, writer: Writer<'_, K, V>, replaced: Option<*mut Entry<K, V>>) {
+        let Writer {
+            guard,
+            new_entry,
+            seq,
+        } = writer;
+
+        if let (Some(snapshots), Some(guard), Some(replaced)) = (&self.snapshots, &guard, replaced)
+        {
+            // Odd epochs are capture epochs.
+            if guard.epoch & 1 == 1 && !replaced.is_null() {
+                // Safety: Guaranteed by caller.
+                unsafe { snapshots.capture(replaced, guard.epoch) };
+            }
+        }
+
+        if let Some(log) = &self.change_log {
+            // Safety: The new entry was written to the map if the write succeeded, and the
+            // caller guarantees the replaced entry is valid.
+            let change = replaced.map(|replaced| unsafe { Change::new(replaced, new_entry) });
+            log.record(seq, change);
+        }
+
+        drop(guard);
+    }
+
+    /// Notifies any watchers of a change to the key with the given hash.
+    ///
+    /// # Safety
+    ///
+    /// `old` and `new` must be null or valid entries for the same key with the given
+    /// hash, and at least one of them must be non-null.
+    #[inline]
+    unsafe fn notify(&self, hash: u64, old: *mut Entry<K, V>, new: *mut Entry<K, V>) {
+        if self.watchers.is_watched(hash) {
+            // Safety: Guaranteed by caller.
+            unsafe { self.watchers.notify(hash, old, new) }
+        }
+    }
+
+    /// Notifies any watchers of the removal of the given entry.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must be a valid entry that was removed from the map.
+    #[inline]
+    unsafe fn notify_removed(&self, entry: *mut Entry<K, V>) {
+        // Avoid hashing the key if there are no watchers.
+        if self.watchers.is_active() {
+            // Safety: Guaranteed by caller.
+            let hash = self.hasher.hash_key(unsafe { &(*entry).key });
+
+            // Safety: Guaranteed by caller.
+            unsafe { self.notify(hash, entry, ptr::null_mut()) };
+        }
+    }
+
+    /// Attempts to replace the value of an existing entry at the given index.
+    ///
+    /// In the case of an error, the returned pointer is guaranteed to be
+    /// protected and valid for reads.
+    ///
+    /// # Safety
+    ///
+    /// - The index must be in-bounds for the table.
+    /// - `current` must be a valid non-null entry that was inserted into the map.
+    /// - `new_entry` must be a valid sentinel or owned pointer to insert into the map.
+    #[inline]
+    unsafe fn update_at(
+        &self,
+        i: usize,
+        current: Tagged<Entry<K, V>>,
+        new_entry: *mut Entry<K, V>,
+        table: Table<Entry<K, V>>,
+    ) -> UpdateStatus<K, V> {
+        // The entry is being moved, settle the move first.
+        //
+        // Note that the entries returned to the caller are never markers, as callers may
+        // read them in the place of the entry they found.
+        if current.tag() & Entry::MOVING != 0 {
+            // Safety: Guaranteed by caller.
+            let found = unsafe { self.settled_at(i, current, table) };
+            return UpdateStatus::Found(EntryStatus::from(found));
+        }
+
+        // Safety: The caller guarantees that `i` is in-bounds.
+        let entry = unsafe { table.entry(i) };
+
+        // Inject a spurious failure of the weak CAS below.
+        if fault::fail_cas() {
+            let found = entry.load(Ordering::Acquire).unpack();
+
+            // Safety: Guaranteed by caller.
+            let found = unsafe { self.settled_at(i, found, table) };
+            return UpdateStatus::Found(EntryStatus::from(found));
+        }
+
+        // Safety: The caller guarantees that `new_entry` is a valid sentinel or owned pointer.
+        let writer = unsafe { self.begin_write(new_entry) };
+
+        // Try to perform the update.
+        //
+        // Note that `SeqCst` is necessary for the update to be observed by concurrent
+        // watchers, see `Watchers::subscribe`.
+        let result = entry.compare_exchange_weak(
+            current.raw,
+            new_entry,
+            Ordering::SeqCst,
+            Ordering::Acquire,
+        );
+
+        // Safety: The caller guarantees that `current` is a valid entry, which we replaced
+        // if the update succeeded.
+        unsafe { self.end_write(writer, result.is_ok().then_some(current.ptr)) };
+
+        let found = match result {
+            // Successfully updated.
+            Ok(_) => {
+                // Safety: The caller guarantees that `current` is a valid non-null entry that was
+                // inserted into the map. Additionally, it is now unreachable from this table due
+                // to the CAS above.
+
+                return UpdateStatus::Replaced(current);
+            }
+
+            // Lost to a concurrent update.
             Err(found) => found.unpack(),
         };
 
+        // Safety: Guaranteed by caller.
+        let found = unsafe { self.settled_at(i, found, table) };
         UpdateStatus::Found(EntryStatus::from(found))
     }
 
@@ -970,7 +1835,7 @@ where
         }
 
         loop {
-            let capacity = probe::entries_for(self.count.sum().checked_add(additional).unwrap());
+            let capacity = probe::entries_for(self.len().checked_add(additional).unwrap());
 
             // We have enough capacity.
             if table.len() >= capacity {
@@ -1025,18 +1890,41 @@ where
                         continue 'probe;
                     }
 
+                    // The entry is being moved, settle the move and check the entry again.
+                    if entry.tag() & Entry::MOVING != 0 {
+                        // Safety: `i` is in bounds for the table length, and `entry` is a valid
+                        // non-null entry that we found in the map.
+                        entry = unsafe { self.settle_at(i, entry, table) };
+                        continue;
+                    }
+
+                    // Safety: A tombstone is a valid sentinel.
+                    let writer = unsafe { self.begin_write(Entry::TOMBSTONE) };
+
                     // Try to delete the entry.
                     //
+                    // Note that spurious failures are injected here, as with a weak CAS.
+                    //
                     // Safety: `i` is in bounds for the table length.
-                    let result = unsafe {
-                        table.entry(i).compare_exchange(
-                            entry.raw,
-                            Entry::TOMBSTONE,
-                            Ordering::Release,
-                            Ordering::Acquire,
-                        )
+                    let result = if fault::fail_cas() {
+                        Err(entry.raw)
+                    } else {
+                        unsafe {
+                            table.entry(i).compare_exchange(
+                                entry.raw,
+                                Entry::TOMBSTONE,
+                                // Note that `SeqCst` is necessary for the removal to be observed
+                                // by concurrent watchers, see `Watchers::subscribe`.
+                                Ordering::SeqCst,
+                                Ordering::Acquire,
+                            )
+                        }
                     };
 
+                    // Safety: `entry` is a valid non-null entry, which we removed if the
+                    // deletion succeeded.
+                    unsafe { self.end_write(writer, result.is_ok().then_some(entry.ptr)) };
+
                     match result {
                         // Successfully deleted the entry.
                         Ok(_) => {
@@ -1046,7 +1934,10 @@ where
                             unsafe { table.meta(i).store(meta::TOMBSTONE, Ordering::Release) };
 
                             // Decrement the table length.
-                            self.count.get().fetch_sub(1, Ordering::Relaxed);
+                            self.count.decrement();
+
+                            // Safety: We just removed the entry.
+                            unsafe { self.notify_removed(entry.ptr) };
 
                             // Safety: The caller guarantees that `current` is a valid non-null entry that was
                             // inserted into the map. Additionally, it is now unreachable from this table due
@@ -1075,142 +1966,878 @@ where
     /// Retains only the elements specified by the predicate.
     #[inline]
     pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.extract_if(|key, value| !f(key, value)).for_each(drop);
+    }
+
+    /// Returns an iterator that removes the entries matching the predicate as it advances,
+    /// returning the removed keys and values.
+    #[inline]
+    pub fn extract_if<F>(&self, f: F) -> ExtractIf<'_, K, V, S, F>
     where
         F: FnMut(&K, &V) -> bool,
     {
         // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet, return a dummy iterator.
+        if root.raw.is_null() {
+            return ExtractIf {
+                map: self,
+                table: root,
+                i: 0,
+                copying: false,
+                f,
+            };
+        }
+
+        // Get a clean copy of the table to delete from.
+        let table = self.linearize(root);
+
+        ExtractIf {
+            map: self,
+            table,
+            i: 0,
+            copying: false,
+            f,
+        }
+    }
+
+    /// Removes an entry chosen at random from the table, returning the removed key and value.
+    ///
+    /// Returns `None` if the table is empty.
+    #[inline]
+    pub fn pop_any<'g>(&self) -> Option<(&'g K, &'g V)> {
+        // Load the root table.
+        let mut table = self.root();
+
+        // The table has not been initialized yet.
+        if table.raw.is_null() {
+            return None;
+        }
+
+        'pick: loop {
+            // Get a clean copy of the table to remove from.
+            table = self.linearize(table);
+
+            // Pick an entry to remove.
+            let mut found = None;
+            self.random_slots(table, RANDOM_PROBES, |i, entry, _| {
+                found = Some((i, entry));
+                false
+            });
+
+            // The table is empty.
+            let (i, mut entry) = found?;
+
+            loop {
+                // The entry was removed concurrently, pick another one.
+                if entry.ptr.is_null() {
+                    continue 'pick;
+                }
+
+                // Found a non-empty entry being copied.
+                if entry.tag() & Entry::COPYING != 0 {
+                    // Complete the resize and retry in the new table.
+                    table = self.help_copy(true, &table);
+                    continue 'pick;
+                }
+
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `i` is in bounds for the table length, and `entry` is a valid
+                    // non-null entry that we found in the map.
+                    entry = unsafe { self.settle_at(i, entry, table) };
+                    continue;
+                }
+
+                // Safety: A tombstone is a valid sentinel.
+                let writer = unsafe { self.begin_write(Entry::TOMBSTONE) };
+
+                // Try to delete the entry.
+                //
+                // Note that spurious failures are injected here, as with a weak CAS.
+                //
+                // Safety: `i` is in bounds for the table length.
+                let result = if fault::fail_cas() {
+                    Err(entry.raw)
+                } else {
+                    unsafe {
+                        table.entry(i).compare_exchange(
+                            entry.raw,
+                            Entry::TOMBSTONE,
+                            // Note that `SeqCst` is necessary for the removal to be observed
+                            // by concurrent watchers, see `Watchers::subscribe`.
+                            Ordering::SeqCst,
+                            Ordering::Acquire,
+                        )
+                    }
+                };
+
+                // Safety: `entry` is a valid non-null entry, which we removed if the
+                // deletion succeeded.
+                unsafe { self.end_write(writer, result.is_ok().then_some(entry.ptr)) };
+
+                match result {
+                    // Successfully deleted the entry.
+                    Ok(_) => {
+                        // Update the metadata table.
+                        //
+                        // Safety: `i` is in bounds for the table length.
+                        unsafe { table.meta(i).store(meta::TOMBSTONE, Ordering::Release) };
+
+                        // Decrement the table length.
+                        self.count.decrement();
+
+                        // Safety: We just removed the entry.
+                        unsafe { self.notify_removed(entry.ptr) };
+
+                        // Safety: We performed a protected load of the pointer and ensured that
+                        // it is non-null, and entries are never deallocated while reachable.
+                        let entry_ref = unsafe { &*entry.ptr };
+                        return Some((&entry_ref.key, &entry_ref.value));
+                    }
+
+                    // Lost to a concurrent update, retry.
+                    Err(found) => entry = found.unpack(),
+                }
+            }
+        }
+    }
+
+    /// Verifies the internal invariants of the table, panicking if any are violated.
+    ///
+    /// The length of the table is only verified if `quiescent` is `true`, as it cannot
+    /// be observed consistently with concurrent operations.
+    pub fn check_invariants(&self, quiescent: bool) {
+        let mut live = 0;
+        let mut visited = Vec::new();
         let mut table = self.root();
 
         // The table has not been initialized yet.
         if table.raw.is_null() {
+            if quiescent {
+                assert_eq!(
+                    self.count.sum().unwrap_or(0),
+                    0,
+                    "uninitialized table is not empty"
+                );
+            }
+
             return;
         }
 
         loop {
-            // Get a clean copy of the table to delete from.
-            table = self.linearize(table);
+            // Ensure the `next` chain is acyclic.
+            assert!(
+                !visited.contains(&table.raw),
+                "table {:p} is linked to itself in the `next` chain",
+                table.raw
+            );
+            visited.push(table.raw);
+
+            let mut copied = 0;
+
+            for i in 0..table.len() {
+                // Safety: `i` is in bounds for the table length.
+                let meta = unsafe { table.meta(i) }.load(Ordering::Acquire);
+
+                // Safety: `i` is in bounds for the table length.
+                let entry = unsafe { table.entry(i).load(Ordering::Acquire) }.unpack();
+
+                // The entry was copied to the next table.
+                if entry.tag() & Entry::COPIED != 0 {
+                    if !entry.ptr.is_null() {
+                        copied += 1;
+                    }
+
+                    continue;
+                }
+
+                // The entry was deleted, or its metadata has not been written yet.
+                if entry.ptr.is_null() || matches!(meta, meta::EMPTY | meta::TOMBSTONE) {
+                    continue;
+                }
+
+                // Safety: We performed an `Acquire` load of a non-null entry pointer,
+                // and entries are never deallocated while reachable from the map.
+                let hash = self.hasher.hash_key(unsafe { &(*entry.ptr).key });
+
+                assert_eq!(
+                    meta,
+                    meta::h2(hash),
+                    "metadata at index {i} does not match the hash of its entry"
+                );
+
+                // Ensure the entry is reachable from its initial probe position.
+                let mut probe = Probe::start(meta::h1(hash), table.mask);
+                while probe.i != i && probe.len < table.limit {
+                    probe.next(table.mask);
+                }
+
+                assert_eq!(
+                    probe.i, i,
+                    "entry at index {i} is not reachable within {} probes",
+                    table.limit
+                );
+
+                // Markers for moves that did not take effect are not counted.
+                //
+                // Safety: We performed an `Acquire` load of a non-null entry pointer.
+                if unsafe { Entry::visible(entry) }.is_some() {
+                    live += 1;
+                }
+            }
+
+            match table.next_table() {
+                Some(next) => table = next,
+                None => {
+                    // Entries are only copied out of a table once the next table is allocated.
+                    assert_eq!(
+                        copied, 0,
+                        "entries are marked as copied in a table without a resize"
+                    );
+
+                    break;
+                }
+            }
+        }
+
+        if quiescent {
+            if let Some(len) = self.count.sum() {
+                assert_eq!(len, live, "length does not match the number of entries");
+            }
+        }
+    }
+
+    /// Verifies the internal invariants of the table after an operation, if the
+    /// `debug_invariants` feature is enabled.
+    #[inline(always)]
+    fn debug_invariants(&self) {
+        #[cfg(feature = "debug_invariants")]
+        self.check_invariants(false);
+    }
+
+    /// Returns statistics about the state of the table.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            capacity: 0,
+            live: 0,
+            tombstones: 0,
+            probe_limit: 0,
+            probe_lengths: Vec::new(),
+            tables: 0,
+            resize: None,
+            copied: 0,
+            claimed: 0,
+            bytes: 0,
+        };
+
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet.
+        if root.raw.is_null() {
+            return stats;
+        }
+
+        stats.capacity = root.len();
+        stats.probe_limit = root.limit;
+        stats.probe_lengths = vec![0; root.limit + 1];
+
+        for i in 0..root.len() {
+            // Safety: `i` is in bounds for the table length.
+            let meta = unsafe { root.meta(i) }.load(Ordering::Acquire);
+
+            match meta {
+                meta::EMPTY => continue,
+                meta::TOMBSTONE => {
+                    stats.tombstones += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            // Safety: `i` is in bounds for the table length.
+            let entry = unsafe { root.entry(i).load(Ordering::Acquire) }.unpack();
+
+            // The entry was deleted or copied to the next table.
+            if entry.ptr.is_null() || entry.tag() & Entry::COPIED != 0 {
+                continue;
+            }
+
+            // The entry is a marker for a move that did not take effect.
+            //
+            // Safety: We performed an `Acquire` load of a non-null entry pointer.
+            if unsafe { Entry::visible(entry) }.is_none() {
+                continue;
+            }
+
+            stats.live += 1;
+
+            // Safety: We performed a protected load of the pointer using a verified guard with
+            // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
+            // as we hold the guard.
+            let hash = self.hasher.hash_key(unsafe { &(*entry.ptr).key });
+
+            // Find the probe length at which this entry was inserted.
+            let mut probe = Probe::start(meta::h1(hash), root.mask);
+            while probe.i != i && probe.len < root.limit {
+                probe.next(root.mask);
+            }
+
+            stats.probe_lengths[probe.len] += 1;
+        }
+
+        if let Some(next) = root.next_table() {
+            // The state of a resize is stored in the table being resized into.
+            let state = next.state();
+            stats.resize = Some(match state.status.load(Ordering::Acquire) {
+                State::ABORTED => ResizeStatus::Aborted,
+                State::PROMOTED => ResizeStatus::Promoted,
+                _ => ResizeStatus::Pending,
+            });
+            stats.copied = state.copied.load(Ordering::Acquire);
+            stats.claimed = state.claim.load(Ordering::Acquire).min(root.len());
+
+            // Count the tables being resized into.
+            let mut table = Some(next);
+            while let Some(next) = table {
+                stats.tables += 1;
+                stats.bytes += Table::<Entry<K, V>>::size(next.len());
+                table = next.next_table();
+            }
+        }
+
+        stats.tables += 1;
+        stats.bytes += Table::<Entry<K, V>>::size(root.len());
+        stats.bytes += stats.live * self.entry_size();
+
+        stats
+    }
+
+    /// Returns an iterator over the keys and values of this table.
+    #[inline]
+    pub fn iter<'g>(&self) -> Iter<'g, K, V> {
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet, return a dummy iterator.
+        if root.raw.is_null() {
+            return Iter {
+                i: 0,
+                table: root,
+                marker: PhantomData,
+            };
+        }
+
+        // Get a clean copy of the table to iterate over.
+        let table = self.linearize(root);
+
+        Iter {
+            i: 0,
+            table,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the entries whose hash metadata matches the given hash.
+    ///
+    /// Note that this may include entries with a different hash, as only the h2 tag is compared.
+    #[inline]
+    pub fn iter_with_hash<'g>(&self, hash: u64) -> HashIter<'g, K, V> {
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet, return a dummy iterator.
+        if root.raw.is_null() {
+            return HashIter {
+                h2: meta::h2(hash),
+                probe: Probe::start(meta::h1(hash), root.mask),
+                table: root,
+                marker: PhantomData,
+            };
+        }
+
+        // Get a clean copy of the table to iterate over.
+        let table = self.linearize(root);
+
+        HashIter {
+            h2: meta::h2(hash),
+            probe: Probe::start(meta::h1(hash), table.mask),
+            table,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the entries in the buckets starting at the given cursor, along with the cursor
+    /// to resume from.
+    ///
+    /// Entries are grouped by their home bucket, i.e. the start of their probe sequence, and
+    /// buckets are visited in reverse-binary order. Because the table length is always a power
+    /// of two, this order remains consistent when the table is resized between calls.
+    pub fn scan<'g>(&self, mut cursor: usize, count: usize) -> (Vec<(&'g K, &'g V)>, usize) {
+        let mut entries = Vec::new();
+
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet.
+        if root.raw.is_null() {
+            return (entries, 0);
+        }
+
+        // Get a clean copy of the table to scan.
+        let table = self.linearize(root);
+
+        loop {
+            let bucket = cursor & table.mask;
+
+            // Probe through the entries that may belong to the bucket.
+            let mut probe = Probe::start(bucket, table.mask);
+            while probe.len <= table.limit {
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let meta = unsafe { table.meta(probe.i) }.load(Ordering::Acquire);
+
+                // There are no more entries in this probe sequence.
+                if meta == meta::EMPTY {
+                    break;
+                }
+
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let entry = unsafe { table.entry(probe.i).load(Ordering::Acquire) }.unpack();
+                probe.next(table.mask);
+
+                // The entry was deleted.
+                if meta == meta::TOMBSTONE || entry.ptr.is_null() {
+                    continue;
+                }
+
+                // Safety: We performed a protected load of the pointer using a verified guard with
+                // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
+                // as we hold the guard.
+                let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                    continue;
+                };
+
+                // Safety: Visible entries are valid for reads.
+                let entry_ref = unsafe { &(*entry) };
+
+                // Only include entries that start their probe sequence at this bucket, as other
+                // entries may be moved to unrelated buckets by a resize.
+                let hash = self.hasher.hash_key(&entry_ref.key);
+                if meta::h1(hash) & table.mask == bucket {
+                    entries.push((&entry_ref.key, &entry_ref.value));
+                }
+            }
+
+            // Increment the reversed bits of the cursor, ignoring any bits that are
+            // outside of the table mask.
+            cursor |= !table.mask;
+            cursor = cursor.reverse_bits().wrapping_add(1).reverse_bits();
+
+            // We visited every bucket, or returned enough entries.
+            if cursor == 0 || entries.len() >= count {
+                return (entries, cursor);
+            }
+        }
+    }
+
+    /// Returns an entry chosen at random, or `None` if the table is empty.
+    #[inline]
+    pub fn random_entry<'g>(&self) -> Option<(&'g K, &'g V)> {
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet.
+        if root.raw.is_null() {
+            return None;
+        }
+
+        // Get a clean copy of the table to pick from.
+        let table = self.linearize(root);
+
+        let mut found = None;
+        self.random_slots(table, RANDOM_PROBES, |_, _, entry| {
+            found = Some(entry);
+            false
+        });
+
+        // Safety: Visible entries are valid for reads.
+        let entry_ref = unsafe { &*found? };
+        Some((&entry_ref.key, &entry_ref.value))
+    }
+
+    /// Returns up to `n` distinct entries chosen at random.
+    pub fn sample<'g>(&self, n: usize) -> Vec<(&'g K, &'g V)> {
+        let mut entries = Vec::new();
+
+        // Load the root table.
+        let root = self.root();
+
+        // The table has not been initialized yet.
+        if root.raw.is_null() || n == 0 {
+            return entries;
+        }
+
+        // Get a clean copy of the table to pick from.
+        let table = self.linearize(root);
+
+        // The slots that were already picked.
+        let mut picked = HashSet::new();
+
+        // Probe a few times more than the number of entries requested, to account for any
+        // slots that are picked more than once.
+        let probes = n.saturating_mul(4).max(RANDOM_PROBES);
+
+        self.random_slots(table, probes, |i, _, entry| {
+            if picked.insert(i) {
+                // Safety: Visible entries are valid for reads.
+                let entry_ref = unsafe { &*entry };
+                entries.push((&entry_ref.key, &entry_ref.value));
+            }
+
+            entries.len() < n
+        });
+
+        entries
+    }
+
+    /// Calls `f` with the index, entry, and visible entry of occupied slots of the table,
+    /// chosen at random, until it returns `false`.
+    ///
+    /// Slots are first picked by probing `probes` random indices, which picks each occupied
+    /// slot with equal probability. If `f` does not return `false` before then, such as if
+    /// the table is sparse, every slot is visited in order from a random index, so that every
+    /// occupied slot is visited at least once. Note that a slot may be visited more than once.
+    fn random_slots<F>(&self, table: Table<Entry<K, V>>, probes: usize, mut f: F)
+    where
+        F: FnMut(usize, Tagged<Entry<K, V>>, *mut Entry<K, V>) -> bool,
+    {
+        let mut visit = |i: usize| {
+            // Load the entry metadata first to skip empty slots cheaply.
+            //
+            // Safety: `i` is in bounds for the table length.
+            let meta = unsafe { table.meta(i) }.load(Ordering::Acquire);
+
+            // The entry is empty or deleted.
+            if matches!(meta, meta::EMPTY | meta::TOMBSTONE) {
+                return true;
+            }
+
+            // Safety: `i` is in bounds for the table length.
+            let entry = unsafe { table.entry(i).load(Ordering::Acquire) }.unpack();
+
+            // The entry was deleted.
+            if entry.ptr.is_null() {
+                return true;
+            }
+
+            // The entry is a marker for a move that did not take effect.
+            //
+            // Safety: We performed an `Acquire` load of a non-null entry pointer.
+            match unsafe { Entry::visible(entry) } {
+                Some(visible) => f(i, entry, visible),
+                None => true,
+            }
+        };
+
+        for _ in 0..probes {
+            if !visit(random::next() as usize & table.mask) {
+                return;
+            }
+        }
+
+        let start = random::next() as usize;
+        for i in 0..table.len() {
+            if !visit(start.wrapping_add(i) & table.mask) {
+                return;
+            }
+        }
+    }
+
+    /// Returns the entries in the table at a single point in time.
+    ///
+    /// Concurrent writers are not blocked while the table is scanned. Instead, writers that
+    /// replace or remove an entry that was present when the snapshot was taken capture it
+    /// for the snapshot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if snapshots are not enabled.
+    pub fn snapshot(&self) -> Vec<*mut Entry<K, V>> {
+        let snapshots = self.snapshots.as_ref().expect("snapshots are not enabled");
+        let _lock = snapshots.lock.lock().unwrap();
+
+        // Advance to the capture epoch.
+        //
+        // Any entries written before this point are part of the snapshot, and any entries
+        // written after are not. However, we have to wait for writers of the previous epoch,
+        // who do not capture the entries they replace.
+        let epoch = snapshots.epoch.advance();
+        snapshots.epoch.synchronize(epoch);
+
+        // Allow writers in the capture epoch to proceed.
+        snapshots.ready.store(epoch, Ordering::Release);
+
+        let mut entries = Vec::new();
+        let mut table = Some(self.root());
+
+        // Collect every entry that has not yet been replaced.
+        while let Some(current) = table {
+            // The table has not been initialized yet.
+            if current.raw.is_null() {
+                break;
+            }
+
+            for i in 0..current.len() {
+                // Safety: `i` is in bounds for the table length.
+                let entry = unsafe { current.entry(i).load(Ordering::Acquire) }.unpack();
+
+                // The entry is empty or deleted. Entries that were copied are
+                // collected from the next table.
+                if entry.ptr.is_null() || entry.tag() & Entry::COPIED != 0 {
+                    continue;
+                }
+
+                // Note that a marker for a pending move is collected as the entry being
+                // moved, which any writer that replaces it captures.
+                //
+                // Safety: We performed an `Acquire` load of a non-null entry pointer,
+                // and entries are never deallocated while reachable from the map.
+                let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                    continue;
+                };
+
+                // Safety: Visible entries are valid for reads.
+                if unsafe { Entry::inserted_before(entry, epoch) } {
+                    entries.push(entry);
+                }
+            }
+
+            table = current.next_table();
+        }
+
+        // Exit the capture epoch, and wait for any writers that may be capturing entries.
+        let next = snapshots.epoch.advance();
+        snapshots.epoch.synchronize(next);
+
+        // Collect the entries that were replaced while we were scanning the table.
+        entries.append(&mut snapshots.captured.lock().unwrap());
+
+        // An entry may have been found in both the table and the next table during a
+        // resize, or replaced after it was found.
+        entries.sort_unstable();
+        entries.dedup();
+        entries
+    }
+
+    /// Creates a table that shares the entries of this table.
+    ///
+    /// The root table is copied directly, without rehashing keys. The new table has the
+    /// same resize and counter modes as this table.
+    ///
+    /// Keys are compared with `eq` to remove any key that was copied twice because it
+    /// was removed and reinserted while the table was being copied.
+    pub fn share_clone<E>(&self, mut eq: E) -> HashMap<K, V, S>
+    where
+        S: Clone,
+        E: FnMut(&K, &K) -> bool,
+    {
+        let mut map = HashMap::new(
+            0,
+            self.hasher.clone(),
+            self.resize,
+            self.count.mode(),
+            None,
+            None,
+            self.snapshots.is_some(),
+        );
+        map.initial_capacity = self.initial_capacity;
+
+        // The shared entries were stamped with epochs of this table, so the new table must
+        // start after them to include them in its own snapshots.
+        if let (Some(snapshots), Some(copy)) = (&self.snapshots, &mut map.snapshots) {
+            copy.epoch.start_after(snapshots.epoch.current());
+        }
+
+        let table = self.root();
+
+        // The table has not been initialized yet.
+        if table.raw.is_null() {
+            return map;
+        }
+
+        // Complete any in-progress resize, so that every entry is in the root table.
+        let table = self.linearize(table);
+
+        let mut copy = Table::alloc(table.len());
+        copy.state_mut().status.store_mut(State::PROMOTED);
+
+        for i in 0..table.len() {
+            // Load the entry before its metadata, which lags behind the insertion or removal
+            // of the entry.
+            //
+            // Safety: `i` is in bounds for the table length.
+            let (entry, meta) = unsafe {
+                let entry = table.entry(i).load(Ordering::Acquire).unpack();
+                (entry, table.meta(i).load(Ordering::Acquire))
+            };
+
+            // Note that entries copied to a new table by a concurrent resize are shared
+            // as they were when copied.
+            let visible = if entry.ptr.is_null() {
+                None
+            } else {
+                // Safety: We performed an `Acquire` load of a non-null entry pointer,
+                // and entries are never deallocated while reachable from the map.
+                unsafe { Entry::visible(entry) }
+            };
 
-            let mut copying = false;
-            'probe: for i in 0..table.len() {
-                // Load the entry metadata first to ensure consistency with calls to `get`
-                // for entries that are retained.
-                //
-                // Safety: `i` is in bounds for the table length.
-                let meta = unsafe { table.meta(i) }.load(Ordering::Acquire);
+            // Safety: `i` is in bounds for the length of both tables, and the new table
+            // is not yet shared.
+            let (new_entry, new_meta) = unsafe { (copy.entry(i), copy.meta(i)) };
+
+            match visible {
+                Some(entry) => {
+                    // The metadata is not yet written, or the entry was removed after we found it.
+                    let meta = if meta == meta::EMPTY || meta == meta::TOMBSTONE {
+                        // Safety: Visible entries are valid for reads.
+                        meta::h2(self.hasher.hash_key(unsafe { &(*entry).key }))
+                    } else {
+                        meta
+                    };
 
-                // The entry is empty or deleted.
-                if matches!(meta, meta::EMPTY | meta::TOMBSTONE) {
-                    continue 'probe;
+                    new_entry.store(entry, Ordering::Relaxed);
+                    new_meta.store(meta, Ordering::Relaxed);
+                    map.count.increment();
                 }
 
-                // Load the entry to delete.
-                //
-                // Safety: `i` is in bounds for the table length.
-                let mut entry = unsafe { table.entry(i).load(Ordering::Acquire) }.unpack();
-
-                loop {
-                    // The entry is empty or already deleted.
-                    if entry.ptr.is_null() {
-                        continue 'probe;
-                    }
+                // Keep the probe chains of deleted entries intact.
+                None if meta != meta::EMPTY => {
+                    new_entry.store(Entry::TOMBSTONE, Ordering::Relaxed);
+                    new_meta.store(meta::TOMBSTONE, Ordering::Relaxed);
+                }
 
-                    // Found a non-empty entry being copied.
-                    if entry.tag() & Entry::COPYING != 0 {
-                        // Clear every entry in this table that we can, then deal with the copy.
-                        copying = true;
-                        continue 'probe;
-                    }
+                None => {}
+            }
+        }
 
-                    // Safety: We performed a protected load of the pointer using a verified guard with
-                    // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
-                    // as we hold the guard.
-                    let entry_ref = unsafe { &*entry.raw };
+        // Inserts never reuse deleted entries, so a key that was removed and reinserted
+        // while we were copying the table may have been copied from both its old and new
+        // entry. The old entry must have been removed before we copied the new one, so any
+        // entry that is no longer present in the table may be stale.
+        for i in 0..table.len() {
+            // Safety: `i` is in bounds for the length of both tables, and the new table
+            // is not yet shared.
+            let (entry, copied) = unsafe {
+                let entry = table.entry(i).load(Ordering::Acquire).unpack();
+                (entry, copy.entry(i).load(Ordering::Relaxed))
+            };
 
-                    // Should we retain this entry?
-                    if f(&entry_ref.key, &entry_ref.value) {
-                        continue 'probe;
-                    }
+            if copied.is_null() || copied == Entry::TOMBSTONE {
+                continue;
+            }
 
-                    // Try to delete the entry.
-                    //
-                    // Safety: `i` is in bounds for the table length.
-                    let result = unsafe {
-                        table.entry(i).compare_exchange(
-                            entry.raw,
-                            Entry::TOMBSTONE,
-                            Ordering::Release,
-                            Ordering::Acquire,
-                        )
-                    };
+            let current = if entry.ptr.is_null() {
+                None
+            } else {
+                // Safety: We performed an `Acquire` load of a non-null entry pointer,
+                // and entries are never deallocated while reachable from the map.
+                unsafe { Entry::visible(entry) }
+            };
 
-                    match result {
-                        // Successfully deleted the entry.
-                        Ok(_) => {
-                            // Update the metadata table.
-                            //
-                            // Safety: `i` is in bounds for the table length.
-                            unsafe { table.meta(i).store(meta::TOMBSTONE, Ordering::Release) };
+            // The entry we copied is still present.
+            if current == Some(copied) {
+                continue;
+            }
 
-                            // Decrement the table length.
-                            self.count.get().fetch_sub(1, Ordering::Relaxed);
+            // Safety: Copied entries are valid for reads.
+            let key = unsafe { &(*copied).key };
+            let hash = self.hasher.hash_key(key);
 
-                            // Safety: The caller guarantees that `current` is a valid non-null entry that was
-                            // inserted into the map. Additionally, it is now unreachable from this table due
-                            // to the CAS above.
-                            continue 'probe;
-                        }
+            // Search for a newer copy of the key, which was copied from a later entry.
+            //
+            // Note that empty entries do not end the search, as they may have been filled
+            // after we copied them but before the key was reinserted.
+            let mut probe = Probe::start(meta::h1(hash), copy.mask);
+            while probe.len <= copy.limit {
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let other = unsafe { copy.entry(probe.i).load(Ordering::Relaxed) };
 
-                        // Lost to a concurrent update, retry.
-                        Err(found) => entry = found.unpack(),
+                // Safety: Copied entries are valid for reads.
+                if probe.i > i
+                    && !other.is_null()
+                    && other != Entry::TOMBSTONE
+                    && eq(key, unsafe { &(*other).key })
+                {
+                    // Safety: `i` is in bounds for the table length.
+                    unsafe {
+                        copy.entry(i).store(Entry::TOMBSTONE, Ordering::Relaxed);
+                        copy.meta(i).store(meta::TOMBSTONE, Ordering::Relaxed);
                     }
+
+                    map.count.decrement();
+                    break;
                 }
-            }
 
-            // We cleared every entry in this table.
-            if !copying {
-                break;
+                probe.next(copy.mask);
             }
-
-            // A resize prevented us from deleting all the entries in this table.
-            //
-            // Complete the resize and retry in the new table.
-            table = self.help_copy(true, &table);
         }
+
+        map.table = AtomicPtr::new(copy.raw);
+        map
     }
 
-    /// Returns an iterator over the keys and values of this table.
-    #[inline]
-    pub fn iter<'g>(&self) -> Iter<'g, K, V> {
-        // Load the root table.
-        let root = self.root();
+    /// Returns the changes to the table after the given sequence number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the change log is not enabled.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes<'_, K, V>, LaggedError> {
+        let log = self
+            .change_log
+            .as_ref()
+            .expect("the change log is not enabled");
+
+        log.changes_since(seq)
+    }
 
-        // The table has not been initialized yet, return a dummy iterator.
-        if root.raw.is_null() {
-            return Iter {
-                i: 0,
-                table: root,
-                marker: PhantomData,
-            };
+    /// Creates a table containing the given entries.
+    ///
+    /// The entries may be shared with another table, and must have unique keys.
+    ///
+    /// # Safety
+    ///
+    /// The entries must be valid for reads, and are never deallocated by the table.
+    pub unsafe fn from_entries(
+        entries: Vec<*mut Entry<K, V>>,
+        hasher: S,
+        resize: ResizeMode,
+        counter: CounterMode,
+    ) -> HashMap<K, V, S> {
+        let map = HashMap::new(entries.len(), hasher, resize, counter, None, None, false);
+
+        // Nothing to insert.
+        if entries.is_empty() {
+            return map;
         }
 
-        // Get a clean copy of the table to iterate over.
-        let table = self.linearize(root);
+        let root = map.root();
+        for entry in entries {
+            // Safety: The entry is valid for reads and has a unique key.
+            unsafe { map.insert_copy(untagged(entry), true, &root) };
+            map.count.increment();
+        }
 
-        Iter {
-            i: 0,
-            table,
-            marker: PhantomData,
+        // Complete any resize caused by a poor hash distribution.
+        if root.next_table().is_some() {
+            map.help_copy(true, &root);
         }
-    }
 
-    /// Returns the h1 and h2 hash for the given key.
-    #[inline]
-    fn hash<Q>(&self, key: &Q) -> (usize, u8)
-    where
-        Q: Hash + ?Sized,
-    {
-        let hash = self.hasher.hash_one(key);
-        (meta::h1(hash), meta::h2(hash))
+        map
     }
 }
 
@@ -1325,8 +2952,10 @@ impl<K, V> LazyEntry<K, V> {
 
     /// Initializes the entry if it has not already been initialized, returning the pointer
     /// to the entry allocation.
+    ///
+    /// The entry is stamped with an epoch if `stamped` is `true`, see `Stamped` for details.
     #[inline]
-    fn init(&mut self) -> *mut Entry<K, MaybeUninit<V>> {
+    fn init(&mut self, stamped: bool) -> *mut Entry<K, MaybeUninit<V>> {
         match self {
             LazyEntry::Init(entry) => *entry,
             LazyEntry::Uninit(key) => {
@@ -1336,10 +2965,7 @@ impl<K, V> LazyEntry<K, V> {
                 unsafe {
                     let key = ptr::read(key);
                     let entry = panic::catch_unwind(panic::AssertUnwindSafe(|| {
-                        Gc::into_raw(Gc::new(Entry {
-                            value: MaybeUninit::<V>::uninit(),
-                            key,
-                        })) as *mut Entry<K, MaybeUninit<V>>
+                        Stamped::alloc(Entry::new(key, MaybeUninit::<V>::uninit()), stamped)
                     }))
                     .unwrap_or_else(|_| std::process::abort());
                     ptr::write(self, LazyEntry::Init(entry));
@@ -1359,26 +2985,248 @@ where
     /// Tries to insert a key and value computed from a closure into the map,
     /// and returns a reference to the value that was inserted.
     #[inline]
-    pub fn try_insert_with<'g, F>(&self, key: K, f: F) -> Result<&'g V, &'g V>
+    pub fn try_insert_with<'g, F>(&self, key: K, f: F) -> Result<&'g V, &'g V>
+    where
+        F: FnOnce() -> V,
+        K: 'g,
+    {
+        let mut f = Some(f);
+        let compute = |entry| match entry {
+            // There is already an existing value.
+            Some((_, current)) => Operation::Abort(current),
+
+            // Insert the initial value.
+            //
+            // Note that this case is guaranteed to be executed at most
+            // once as insert values are cached, so this can never panic.
+            None => Operation::Insert((f.take().unwrap())()),
+        };
+
+        match self.compute(key, compute) {
+            // Failed to insert, return the existing value.
+            Compute::Aborted(current) => Err(current),
+
+            // Successfully inserted.
+            Compute::Inserted(_, value) => Ok(value),
+
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a closure.
+    #[inline]
+    pub fn get_or_insert_with<'g, F>(&self, key: K, f: F) -> &'g V
+    where
+        F: FnOnce() -> V,
+        K: 'g,
+    {
+        match self.try_insert_with(key, f) {
+            Ok(value) => value,
+            Err(value) => value,
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a value
+    /// computed from a fallible closure.
+    ///
+    /// The closure is run by at most one thread at a time for a given key, and any other
+    /// threads wait for it to complete. If the closure fails or panics, another thread
+    /// may run its closure.
+    pub fn get_or_try_insert_with_once<'g, F, E>(&self, key: K, f: F) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        K: 'g,
+    {
+        let hash = self.hasher.hash_one(&key);
+        let eq = |k: &K| self.equivalent(hash, &key, k);
+
+        loop {
+            if let Some((_, value)) = self.get_with_hash(hash, eq) {
+                return Ok(value);
+            }
+
+            let claim = match self.initializers.claim(hash) {
+                Ok(claim) => claim,
+                Err(claimed) => {
+                    // Wait for the thread initializing the key, then try again.
+                    claimed.wait();
+                    continue;
+                }
+            };
+
+            // The key may have been inserted by the previous claim before we claimed it.
+            if let Some((_, value)) = self.get_with_hash(hash, eq) {
+                return Ok(value);
+            }
+
+            // Note that the claim is released if the initializer fails or panics.
+            let value = f()?;
+
+            // The key may still have been inserted without a claim.
+            let value = match self.insert_with_hash(hash, key, value, false, K::eq) {
+                InsertResult::Inserted(value) => value,
+                InsertResult::Error { current, .. } => current,
+                InsertResult::Replaced(_) => unreachable!(),
+            };
+
+            // Release the claim after the key was inserted, so waiting threads observe it.
+            drop(claim);
+
+            return Ok(value);
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a value
+    /// computed from a fallible future.
+    ///
+    /// The future is run by at most one task at a time for a given key, and any other tasks
+    /// wait for it to complete. If the future fails, its error is shared with the waiting
+    /// tasks. If it panics or is cancelled, another waiting task may run its own future.
+    pub async fn get_or_try_insert_async<'g, F, Fut, E>(&self, key: K, f: F) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+        E: Clone + Send + 'static,
+        K: 'g,
+        V: 'g,
+    {
+        let hash = self.hasher.hash_one(&key);
+        let eq = |k: &K| self.equivalent(hash, &key, k);
+
+        loop {
+            if let Some((_, value)) = self.get_with_hash(hash, eq) {
+                return Ok(value);
+            }
+
+            let claim = match self.initializers.claim_async(hash) {
+                Ok(claim) => claim,
+                Err(claimed) => {
+                    // Wait for the task initializing the key, and return its error if it
+                    // failed. Otherwise, try again.
+                    match claimed.wait_async::<E>().await {
+                        Some(err) => return Err(err),
+                        None => continue,
+                    }
+                }
+            };
+
+            // The key may have been inserted by the previous claim before we claimed it.
+            if let Some((_, value)) = self.get_with_hash(hash, eq) {
+                return Ok(value);
+            }
+
+            // Note that the claim is released if the future panics or is cancelled while we
+            // are suspended here.
+            let value = match f().await {
+                Ok(value) => value,
+                Err(err) => {
+                    // Release the claim, sharing the error with the waiting tasks.
+                    claim.fail(err.clone());
+                    return Err(err);
+                }
+            };
+
+            // The key may still have been inserted without a claim.
+            let value = match self.insert_with_hash(hash, key, value, false, K::eq) {
+                InsertResult::Inserted(value) => value,
+                InsertResult::Error { current, .. } => current,
+                InsertResult::Replaced(_) => unreachable!(),
+            };
+
+            // Release the claim after the key was inserted, so waiting tasks observe it.
+            drop(claim);
+
+            return Ok(value);
+        }
+    }
+
+    /// Updates an existing entry atomically, returning the value that was inserted.
+    #[inline]
+    pub fn update<'g, F>(&self, key: K, mut update: F) -> Option<&'g V>
+    where
+        F: FnMut(&V) -> V,
+        K: 'g,
+    {
+        let compute = |entry| match entry {
+            // There is nothing to update.
+            None => Operation::Abort(()),
+            // Perform the update.
+            Some((_, value)) => Operation::Insert(update(value)),
+        };
+
+        match self.compute(key, compute) {
+            // Return the updated value.
+            Compute::Updated {
+                new: (_, value), ..
+            } => Some(value),
+
+            // There was nothing to update.
+            Compute::Aborted(_) => None,
+
+            _ => unreachable!(),
+        }
+    }
+
+    /// Updates an existing entry or inserts a default value computed from a closure.
+    #[inline]
+    pub fn update_or_insert_with<'g, U, F>(&self, key: K, update: U, f: F) -> &'g V
+    where
+        F: FnOnce() -> V,
+        U: Fn(&V) -> V,
+        K: 'g,
+    {
+        let mut f = Some(f);
+        let compute = |entry| match entry {
+            // Perform the update.
+            Some((_, value)) => Operation::Insert::<_, ()>(update(value)),
+
+            // Insert the initial value.
+            //
+            // Note that this case is guaranteed to be executed at most
+            // once as insert values are cached, so this can never panic.
+            None => Operation::Insert((f.take().unwrap())()),
+        };
+
+        match self.compute(key, compute) {
+            // Return the updated value.
+            Compute::Updated {
+                new: (_, value), ..
+            } => value,
+
+            // Return the value we inserted.
+            Compute::Inserted(_, value) => value,
+
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a value
+    /// computed from a fallible closure.
+    #[inline]
+    pub fn get_or_try_insert_with<'g, F, E>(&self, key: K, f: F) -> Result<&'g V, E>
     where
-        F: FnOnce() -> V,
+        F: FnOnce() -> Result<V, E>,
         K: 'g,
     {
         let mut f = Some(f);
         let compute = |entry| match entry {
-            // There is already an existing value.
-            Some((_, current)) => Operation::Abort(current),
+            // Return the existing value.
+            Some((_, current)) => Operation::Abort(Ok(current)),
 
-            // Insert the initial value.
+            // Insert the initial value, or abort with the error.
             //
             // Note that this case is guaranteed to be executed at most
             // once as insert values are cached, so this can never panic.
-            None => Operation::Insert((f.take().unwrap())()),
+            None => match (f.take().unwrap())() {
+                Ok(value) => Operation::Insert(value),
+                Err(err) => Operation::Abort(Err(err)),
+            },
         };
 
         match self.compute(key, compute) {
-            // Failed to insert, return the existing value.
-            Compute::Aborted(current) => Err(current),
+            // The key was present, or the closure failed.
+            Compute::Aborted(result) => result,
 
             // Successfully inserted.
             Compute::Inserted(_, value) => Ok(value),
@@ -1387,75 +3235,85 @@ where
         }
     }
 
-    /// Returns a reference to the value corresponding to the key, or inserts a default value
-    /// computed from a closure.
-    #[inline]
-    pub fn get_or_insert_with<'g, F>(&self, key: K, f: F) -> &'g V
-    where
-        F: FnOnce() -> V,
-        K: 'g,
-    {
-        match self.try_insert_with(key, f) {
-            Ok(value) => value,
-            Err(value) => value,
-        }
-    }
-
-    /// Updates an existing entry atomically, returning the value that was inserted.
+    /// Updates an existing entry atomically with a fallible closure, returning the value
+    /// that was inserted.
     #[inline]
-    pub fn update<'g, F>(&self, key: K, mut update: F) -> Option<&'g V>
+    pub fn try_update<'g, F, E>(&self, key: K, mut update: F) -> Result<Option<&'g V>, E>
     where
-        F: FnMut(&V) -> V,
+        F: FnMut(&V) -> Result<V, E>,
         K: 'g,
     {
         let compute = |entry| match entry {
             // There is nothing to update.
-            None => Operation::Abort(()),
-            // Perform the update.
-            Some((_, value)) => Operation::Insert(update(value)),
+            None => Operation::Abort(Ok(())),
+
+            // Perform the update, or abort with the error.
+            Some((_, value)) => match update(value) {
+                Ok(value) => Operation::Insert(value),
+                Err(err) => Operation::Abort(Err(err)),
+            },
         };
 
         match self.compute(key, compute) {
             // Return the updated value.
             Compute::Updated {
                 new: (_, value), ..
-            } => Some(value),
+            } => Ok(Some(value)),
 
             // There was nothing to update.
-            Compute::Aborted(_) => None,
+            Compute::Aborted(Ok(())) => Ok(None),
+
+            // The update failed.
+            Compute::Aborted(Err(err)) => Err(err),
 
             _ => unreachable!(),
         }
     }
 
-    /// Updates an existing entry or inserts a default value computed from a closure.
+    /// Updates an existing entry or inserts a value computed from a closure, where either
+    /// closure may fail.
     #[inline]
-    pub fn update_or_insert_with<'g, U, F>(&self, key: K, update: U, f: F) -> &'g V
+    pub fn try_update_or_insert_with<'g, U, F, E>(
+        &self,
+        key: K,
+        update: U,
+        f: F,
+    ) -> Result<&'g V, E>
     where
-        F: FnOnce() -> V,
-        U: Fn(&V) -> V,
+        F: FnOnce() -> Result<V, E>,
+        U: Fn(&V) -> Result<V, E>,
         K: 'g,
     {
         let mut f = Some(f);
-        let compute = |entry| match entry {
-            // Perform the update.
-            Some((_, value)) => Operation::Insert::<_, ()>(update(value)),
+        let compute = |entry| {
+            let value = match entry {
+                // Perform the update.
+                Some((_, value)) => update(value),
 
-            // Insert the initial value.
-            //
-            // Note that this case is guaranteed to be executed at most
-            // once as insert values are cached, so this can never panic.
-            None => Operation::Insert((f.take().unwrap())()),
+                // Compute the initial value.
+                //
+                // Note that this case is guaranteed to be executed at most
+                // once as insert values are cached, so this can never panic.
+                None => (f.take().unwrap())(),
+            };
+
+            match value {
+                Ok(value) => Operation::Insert(value),
+                Err(err) => Operation::Abort(err),
+            }
         };
 
         match self.compute(key, compute) {
             // Return the updated value.
             Compute::Updated {
                 new: (_, value), ..
-            } => value,
+            } => Ok(value),
 
             // Return the value we inserted.
-            Compute::Inserted(_, value) => value,
+            Compute::Inserted(_, value) => Ok(value),
+
+            // One of the closures failed.
+            Compute::Aborted(err) => Err(err),
 
             _ => unreachable!(),
         }
@@ -1482,10 +3340,11 @@ where
         if matches!(result, Compute::Removed(..) | Compute::Aborted(_)) {
             if let LazyEntry::Init(entry) = entry {
                 // Safety: The entry was allocated but not inserted into the map.
-                let _ = unsafe { Box::from_raw(entry) };
+                let _ = unsafe { Stamped::dealloc(entry, self.snapshots.is_some()) };
             }
         }
 
+        self.debug_invariants();
         result
     }
 
@@ -1521,7 +3380,8 @@ where
             table = self.init(None);
         }
 
-        let (h1, h2) = self.hash(new_entry.key());
+        let hash = self.hasher.hash_one(new_entry.key());
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
         let mut help_copy = false;
 
         loop {
@@ -1531,6 +3391,7 @@ where
             // Probe until we reach the limit.
             let copying = 'probe: loop {
                 if probe.len > table.limit {
+                    self.observer.probe_limit_exceeded(table.len());
                     break 'probe None;
                 }
 
@@ -1550,7 +3411,7 @@ where
                         Operation::Abort(value) => return Compute::Aborted(value),
                     };
 
-                    let new_entry = new_entry.init();
+                    let new_entry = new_entry.init(self.snapshots.is_some());
                     // Safety: `new_entry` was just allocated above and is valid for writes.
                     unsafe { (*new_entry).value = MaybeUninit::new(value) }
 
@@ -1562,10 +3423,15 @@ where
                         // Successfully inserted.
                         InsertStatus::Inserted => {
                             // Increment the table length.
-                            self.count.get().fetch_add(1, Ordering::Relaxed);
+                            self.count.increment();
+
+                            let new_entry = new_entry.cast::<Entry<K, V>>();
+
+                            // Safety: We just inserted the entry.
+                            unsafe { self.notify(hash, ptr::null_mut(), new_entry) };
 
                             // Safety: `new_entry` was initialized above.
-                            let new_ref = unsafe { &*new_entry.cast::<Entry<K, V>>() };
+                            let new_ref = unsafe { &*new_entry };
                             return Compute::Inserted(&new_ref.key, &new_ref.value);
                         }
 
@@ -1631,11 +3497,22 @@ where
                     continue 'probe;
                 }
 
+                // Safety: `entry` is valid for reads.
+                self.debug_hash(hash, new_entry.key(), unsafe { &(*entry.ptr).key });
+
                 // The entry is being copied to the new table.
                 if entry.tag() & Entry::COPYING != 0 {
                     break 'probe Some(probe.i);
                 }
 
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `probe.i` is always in-bounds for the table length, and `entry`
+                    // is a valid non-null entry that we found in the map.
+                    unsafe { self.settle_at(probe.i, entry, table) };
+                    continue 'probe;
+                }
+
                 loop {
                     // Compute the value to insert.
                     //
@@ -1646,7 +3523,7 @@ where
 
                         // Update the value.
                         Operation::Insert(value) => {
-                            let new_entry = new_entry.init();
+                            let new_entry = new_entry.init(self.snapshots.is_some());
 
                             // Safety: `new_entry` was just allocated above and is valid for writes.
                             unsafe { (*new_entry).value = MaybeUninit::new(value) }
@@ -1663,12 +3540,17 @@ where
                             match status {
                                 // Successfully updated.
                                 UpdateStatus::Replaced(entry) => {
+                                    let new_entry = new_entry.cast::<Entry<K, V>>();
+
+                                    // Safety: We just replaced the entry.
+                                    unsafe { self.notify(hash, entry.ptr, new_entry) };
+
                                     // Safety: `entry` is a valid non-null entry that we found in the map
                                     // before replacing it.
                                     let entry_ref = unsafe { &(*entry.ptr) };
 
                                     // Safety: `new_entry` was initialized above.
-                                    let new_ref = unsafe { &*new_entry.cast::<Entry<K, V>>() };
+                                    let new_ref = unsafe { &*new_entry };
 
                                     return Compute::Updated {
                                         old: (&entry_ref.key, &entry_ref.value),
@@ -1715,7 +3597,10 @@ where
                                     };
 
                                     // Decrement the table length.
-                                    self.count.get().fetch_sub(1, Ordering::Relaxed);
+                                    self.count.decrement();
+
+                                    // Safety: We just removed the entry.
+                                    unsafe { self.notify(hash, entry.ptr, ptr::null_mut()) };
 
                                     // Safety: `entry` is a valid non-null entry that we found in the map
                                     // before replacing it.
@@ -1760,7 +3645,10 @@ where
                         }
 
                         // Someone else beat us to the update, retry.
-                        UpdateStatus::Found(EntryStatus::Value(found)) => entry = found,
+                        UpdateStatus::Found(EntryStatus::Value(found)) => {
+                            self.observer.cas_retry();
+                            entry = found;
+                        }
 
                         _ => unreachable!(),
                     }
@@ -1793,8 +3681,7 @@ where
 /// Resize operations.
 impl<K, V, S> HashMap<K, V, S>
 where
-    K: Hash + Eq,
-    S: BuildHasher,
+    S: KeyHasher<K>,
 {
     /// Allocate the initial table.
     #[cold]
@@ -1804,7 +3691,7 @@ where
 
         // Allocate the table and mark it as the root.
         let mut new = Table::alloc(capacity.unwrap_or(CAPACITY));
-        *new.state_mut().status.get_mut() = State::PROMOTED;
+        new.state_mut().status.store_mut(State::PROMOTED);
 
         // Race to write the initial table.
         match self.table.compare_exchange(
@@ -1886,7 +3773,14 @@ where
 
         // Loading the length here is quite expensive, we may want to consider
         // a probabilistic counter to detect high-deletion workloads.
-        let active_entries = self.len();
+        //
+        // If the counter is disabled, count the live entries rather than estimating the
+        // length, to avoid shrinking a populated table. The resize will visit every entry
+        // regardless.
+        let active_entries = match self.count.sum() {
+            Some(len) => len,
+            None => self.count_live(usize::MAX),
+        };
 
         let next_capacity = match cfg!(papaya_stress) {
             // Never grow the table to stress the incremental resizing algorithm.
@@ -1924,6 +3818,9 @@ where
         state.next.store(next.raw, Ordering::Release);
         drop(_allocating);
 
+        self.observer
+            .resize_started(current_capacity, next_capacity);
+
         next
     }
 
@@ -1934,6 +3831,9 @@ where
     #[cold]
     #[inline(never)]
     fn help_copy(&self, copy_all: bool, table: &Table<Entry<K, V>>) -> Table<Entry<K, V>> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("help_copy", capacity = table.len()).entered();
+
         match self.resize {
             ResizeMode::Blocking => self.help_copy_blocking(table),
             ResizeMode::Incremental(chunk) => {
@@ -1963,6 +3863,8 @@ where
                 next = self.get_or_alloc_next(None, next);
             }
 
+            self.observer.copy_joined(next.raw.cast(), table.len());
+
             // The copy already completed
             if self.try_promote(table, &next, 0) {
                 return next;
@@ -1999,12 +3901,15 @@ where
                         // Note that the `SeqCst` is necessary to make the store visible
                         // to threads that are unparked.
                         next.state().status.store(State::ABORTED, Ordering::SeqCst);
+                        self.observer.resize_aborted(next.len());
 
                         // Allocate the next table.
                         let allocated = self.get_or_alloc_next(None, next);
 
                         // Wake anyone waiting for us to finish.
-                        let state = table.state();
+                        //
+                        // Note that waiters are parked on the status of the aborted table.
+                        let state = next.state();
                         state.parker.unpark(&state.status);
 
                         // Retry in a new table.
@@ -2065,6 +3970,7 @@ where
                 }
 
                 // Park until the table is promoted.
+                self.observer.parked();
                 state
                     .parker
                     .park(&state.status, |status| status == State::PENDING);
@@ -2096,6 +4002,14 @@ where
             .fetch_or(Entry::COPYING, Ordering::AcqRel)
             .unpack();
 
+        fault::delay();
+
+        // Settle any move of the entry, copying the entry it resolves to.
+        //
+        // Safety: The caller guarantees that the index is in-bounds, and we marked the
+        // entry as `COPYING`.
+        let entry = unsafe { self.settle_copying(i, entry, table) };
+
         // The entry is a tombstone.
         if entry.raw == Entry::TOMBSTONE {
             return true;
@@ -2110,6 +4024,11 @@ where
             return true;
         }
 
+        // Act as if the new table is full, aborting the resize.
+        if fault::abort_resize(table.len()) {
+            return false;
+        }
+
         // Copy the value to the new table.
         //
         // Safety: We marked the entry as `COPYING`, ensuring that any updates
@@ -2150,6 +4069,12 @@ where
                 // Claim a chunk to copy.
                 let copy_start = next.state().claim.fetch_add(chunk, Ordering::Relaxed);
 
+                fault::preempt();
+
+                if copy_start < table.len() {
+                    self.observer.copy_joined(next.raw.cast(), table.len());
+                }
+
                 // Copy our chunk of entries.
                 let mut copied = 0;
                 for i in 0..chunk {
@@ -2213,6 +4138,7 @@ where
                 }
 
                 // Park until the table is promoted.
+                self.observer.parked();
                 state
                     .parker
                     .park(&state.status, |status| status == State::PENDING);
@@ -2237,6 +4163,12 @@ where
         // Mark the entry as copying.
         let found = entry.fetch_or(Entry::COPYING, Ordering::AcqRel).unpack();
 
+        // Settle any move of the entry, copying the entry it resolves to.
+        //
+        // Safety: The caller guarantees that the index is in-bounds, and we marked the
+        // entry as `COPYING`.
+        let found = unsafe { self.settle_copying(i, found, table) };
+
         // The entry is a tombstone.
         if found.raw == Entry::TOMBSTONE {
             return;
@@ -2251,9 +4183,6 @@ where
             return;
         }
 
-        // Mark the entry as borrowed so writers in the new table know it was copied.
-        let new_entry = found.map_tag(|addr| addr | Entry::BORROWED);
-
         // Copy the value to the new table.
         //
         // Safety: We marked the entry as `COPYING`, ensuring that any updates
@@ -2261,9 +4190,11 @@ where
         // away without a protected load. Additionally, we verified that the
         // entry is non-null, meaning that it is valid for reads.
         unsafe {
-            self.insert_copy(new_entry, true, next_table).unwrap();
+            self.insert_copy(found, true, next_table).unwrap();
         }
 
+        fault::delay();
+
         // Mark the entry as copied.
         let copied = found
             .raw
@@ -2298,7 +4229,8 @@ where
         let key = unsafe { &(*new_entry.ptr).key };
 
         let mut table = *table;
-        let (h1, h2) = self.hash(key);
+        let hash = self.hasher.hash_key(key);
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
 
         loop {
             // Initialize the probe state.
@@ -2317,6 +4249,18 @@ where
                     // Safety: `probe.i` is always in-bounds for the table length.
                     let entry = unsafe { table.entry(probe.i) };
 
+                    // Inject a concurrent insert and removal that claims the empty entry first.
+                    //
+                    // Note that only incremental resizes share the new table with writers.
+                    if resize && fault::fail_cas() {
+                        let _ = entry.compare_exchange(
+                            ptr::null_mut(),
+                            Entry::TOMBSTONE,
+                            Ordering::Release,
+                            Ordering::Relaxed,
+                        );
+                    }
+
                     // Try to claim the entry.
                     match entry.compare_exchange(
                         ptr::null_mut(),
@@ -2343,7 +4287,7 @@ where
                                 let found_ref = unsafe { &(*found.ptr) };
 
                                 // Ensure the meta table is updated to avoid breaking the probe chain.
-                                let hash = self.hasher.hash_one(&found_ref.key);
+                                let hash = self.hasher.hash_key(&found_ref.key);
                                 meta::h2(hash)
                             };
 
@@ -2405,6 +4349,7 @@ where
                     // Note that the `SeqCst` is necessary to make the store visible to threads
                     // that are unparked.
                     state.status.store(State::PROMOTED, Ordering::SeqCst);
+                    self.observer.resize_promoted(next.len());
 
                     // Retire the old table.
                     //
@@ -2477,6 +4422,7 @@ where
         }
 
         // Park until the copy completes.
+        self.observer.parked();
         let parker = &table.state().parker;
         parker.park(entry, |entry| entry.addr() & Entry::COPIED == 0);
     }
@@ -2501,15 +4447,9 @@ where
             ResizeMode::Blocking => unsafe {
                 guard.defer_retire(entry.ptr, seize::reclaim::boxed);
             },
-            // In incremental resize mode, the entry may be accessible in previous tables.
+            // In incremental resize mode, the entry may be accessible in previous tables, as
+            // entries are not marked when copied.
             ResizeMode::Incremental(_) => {
-                if entry.tag() & Entry::BORROWED == 0 {
-                    // Safety: If the entry is not borrowed, meaning it is not in any previous tables,
-                    // it is inaccessible even if the current table is not root. Thus we can safely retire.
-                    unsafe { guard.defer_retire(entry.ptr, seize::reclaim::boxed) };
-                    return;
-                }
-
                 let root = self.root();
 
                 // Check if our table, or any subsequent table, is the root.
@@ -2585,18 +4525,22 @@ impl<'g, K: 'g, V: 'g> Iterator for Iter<'g, K, V> {
             // Safety: We verified that `self.i` is in-bounds above.
             let entry = unsafe { self.table.entry(self.i).load(Ordering::Acquire) }.unpack();
 
+            self.i += 1;
+
             // The entry was deleted.
             if entry.ptr.is_null() {
-                self.i += 1;
                 continue;
             }
 
             // Safety: We performed a protected load of the pointer using a verified guard with
             // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
             // as we hold the guard.
-            let entry_ref = unsafe { &(*entry.ptr) };
+            let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                continue;
+            };
 
-            self.i += 1;
+            // Safety: Visible entries are valid for reads.
+            let entry_ref = unsafe { &(*entry) };
             return Some((&entry_ref.key, &entry_ref.value));
         }
     }
@@ -2634,9 +4578,268 @@ impl<K, V> Clone for Iter<'_, K, V> {
     }
 }
 
+// An iterator over the entries in the probe sequence of a given hash.
+pub struct HashIter<'g, K, V> {
+    h2: u8,
+    probe: Probe,
+    table: Table<Entry<K, V>>,
+    marker: PhantomData<(&'g K, &'g V)>,
+}
+
+impl<'g, K: 'g, V: 'g> Iterator for HashIter<'g, K, V> {
+    type Item = (&'g K, &'g V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        // The table has not yet been allocated.
+        if self.table.raw.is_null() {
+            return None;
+        }
+
+        // Probe until we reach the limit.
+        while self.probe.len <= self.table.limit {
+            let i = self.probe.i;
+            self.probe.next(self.table.mask);
+
+            // Load the entry metadata first for cheap searches.
+            //
+            // Safety: `i` is always in-bounds for the table length.
+            let meta = unsafe { self.table.meta(i) }.load(Ordering::Acquire);
+
+            // There are no more entries in this probe sequence.
+            if meta == meta::EMPTY {
+                break;
+            }
+
+            if meta != self.h2 {
+                continue;
+            }
+
+            // Load the full entry.
+            //
+            // Safety: `i` is always in-bounds for the table length.
+            let entry = unsafe { self.table.entry(i).load(Ordering::Acquire) }.unpack();
+
+            // The entry was deleted.
+            if entry.ptr.is_null() {
+                continue;
+            }
+
+            // Safety: We performed a protected load of the pointer using a verified guard with
+            // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
+            // as we hold the guard.
+            let Some(entry) = (unsafe { Entry::visible(entry) }) else {
+                continue;
+            };
+
+            // Safety: Visible entries are valid for reads.
+            let entry_ref = unsafe { &(*entry) };
+            return Some((&entry_ref.key, &entry_ref.value));
+        }
+
+        None
+    }
+}
+
+// Safety: See the implementations for `Iter`.
+unsafe impl<K, V> Send for HashIter<'_, K, V>
+where
+    K: Sync,
+    V: Sync,
+{
+}
+
+unsafe impl<K, V> Sync for HashIter<'_, K, V>
+where
+    K: Sync,
+    V: Sync,
+{
+}
+
+impl<K, V> Clone for HashIter<'_, K, V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        HashIter {
+            h2: self.h2,
+            probe: self.probe,
+            table: self.table,
+            marker: PhantomData,
+        }
+    }
+}
+
+// An iterator that removes the entries of the table matching a predicate.
+//
+// Entries are only removed as the iterator advances, so entries after the last one returned
+// are left in the table if the iterator is dropped.
+pub struct ExtractIf<'g, K, V, S, F> {
+    map: &'g HashMap<K, V, S>,
+    table: Table<Entry<K, V>>,
+    i: usize,
+    // Whether we skipped any entries that were being copied to a new table.
+    copying: bool,
+    f: F,
+}
+
+impl<'g, K, V, S, F> Iterator for ExtractIf<'g, K, V, S, F>
+where
+    S: KeyHasher<K>,
+    F: FnMut(&K, &V) -> bool,
+{
+    type Item = (&'g K, &'g V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        // The table has not yet been allocated.
+        if self.table.raw.is_null() {
+            return None;
+        }
+
+        'probe: loop {
+            // Visited every entry in the table.
+            if self.i >= self.table.len() {
+                // We removed every matching entry in this table.
+                if !self.copying {
+                    return None;
+                }
+
+                // A resize prevented us from removing every matching entry in this table.
+                //
+                // Complete the resize and continue in the new table.
+                let next = self.map.help_copy(true, &self.table);
+                self.table = self.map.linearize(next);
+                self.i = 0;
+                self.copying = false;
+            }
+
+            let (i, table) = (self.i, self.table);
+            self.i += 1;
+
+            // Load the entry metadata first to ensure consistency with calls to `get`
+            // for entries that are retained.
+            //
+            // Safety: `i` is in bounds for the table length.
+            let meta = unsafe { table.meta(i) }.load(Ordering::Acquire);
+
+            // The entry is empty or deleted.
+            if matches!(meta, meta::EMPTY | meta::TOMBSTONE) {
+                continue 'probe;
+            }
+
+            // Load the entry to delete.
+            //
+            // Safety: `i` is in bounds for the table length.
+            let mut entry = unsafe { table.entry(i).load(Ordering::Acquire) }.unpack();
+
+            loop {
+                // The entry is empty or already deleted.
+                if entry.ptr.is_null() {
+                    continue 'probe;
+                }
+
+                // Found a non-empty entry being copied.
+                if entry.tag() & Entry::COPYING != 0 {
+                    // Remove every entry in this table that we can, then deal with the copy.
+                    self.copying = true;
+                    continue 'probe;
+                }
+
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `i` is in bounds for the table length, and `entry` is a valid
+                    // non-null entry that we found in the map.
+                    entry = unsafe { self.map.settle_at(i, entry, table) };
+                    continue;
+                }
+
+                // Safety: We performed a protected load of the pointer using a verified guard with
+                // `Acquire` and ensured that it is non-null, meaning it is valid for reads as long
+                // as we hold the guard.
+                let entry_ref = unsafe { &*entry.ptr };
+
+                // Should we remove this entry?
+                if !(self.f)(&entry_ref.key, &entry_ref.value) {
+                    continue 'probe;
+                }
+
+                // Safety: A tombstone is a valid sentinel.
+                let writer = unsafe { self.map.begin_write(Entry::TOMBSTONE) };
+
+                // Try to delete the entry.
+                //
+                // Note that spurious failures are injected here, as with a weak CAS.
+                //
+                // Safety: `i` is in bounds for the table length.
+                let result = if fault::fail_cas() {
+                    Err(entry.raw)
+                } else {
+                    unsafe {
+                        table.entry(i).compare_exchange(
+                            entry.raw,
+                            Entry::TOMBSTONE,
+                            // Note that `SeqCst` is necessary for the removal to be observed
+                            // by concurrent watchers, see `Watchers::subscribe`.
+                            Ordering::SeqCst,
+                            Ordering::Acquire,
+                        )
+                    }
+                };
+
+                // Safety: `entry` is a valid non-null entry, which we removed if the
+                // deletion succeeded.
+                unsafe {
+                    self.map
+                        .end_write(writer, result.is_ok().then_some(entry.ptr))
+                };
+
+                match result {
+                    // Successfully deleted the entry.
+                    Ok(_) => {
+                        // Update the metadata table.
+                        //
+                        // Safety: `i` is in bounds for the table length.
+                        unsafe { table.meta(i).store(meta::TOMBSTONE, Ordering::Release) };
+
+                        // Decrement the table length.
+                        self.map.count.decrement();
+
+                        // Safety: We just removed the entry.
+                        unsafe { self.map.notify_removed(entry.ptr) };
+
+                        return Some((&entry_ref.key, &entry_ref.value));
+                    }
+
+                    // Lost to a concurrent update, retry.
+                    Err(found) => entry = found.unpack(),
+                }
+            }
+        }
+    }
+}
+
+// Safety: The iterator accesses the map through a shared reference, and otherwise only
+// holds shared references to keys and values, see the implementations for `Iter`.
+unsafe impl<K, V, S, F> Send for ExtractIf<'_, K, V, S, F>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Sync,
+    F: Send,
+{
+}
+
+unsafe impl<K, V, S, F> Sync for ExtractIf<'_, K, V, S, F>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Sync,
+    F: Sync,
+{
+}
+
 impl<K, V, S> Drop for HashMap<K, V, S> {
     fn drop(&mut self) {
-        let mut raw = *self.table.get_mut();
+        let mut raw = self.table.load_mut();
 
         // Make sure all objects are reclaimed before the collector is dropped.
         //
@@ -2653,7 +4856,7 @@ impl<K, V, S> Drop for HashMap<K, V, S> {
             let mut table = unsafe { Table::from_raw(raw) };
 
             // Read the next table pointer before dropping the current one.
-            let next = *table.state_mut().next.get_mut();
+            let next = table.state_mut().next.load_mut();
 
             // Safety: We have unique access to the table and do
             // not access the entries after this call.
@@ -2676,8 +4879,10 @@ impl<K, V, S> Drop for HashMap<K, V, S> {
 // The table entries must not be accessed after this call.
 unsafe fn drop_entries<K, V>(table: Table<Entry<K, V>>) {
     for i in 0..table.len() {
-        // Safety: `i` is in-bounds and we have unique access to the table.
-        let mut entry = unsafe { (*table.entry(i).as_ptr()).unpack() };
+        // Safety: `i` is in-bounds for the table length.
+        //
+        // `Relaxed` is sufficient as we have unique access to the table.
+        let mut entry = unsafe { table.entry(i).load(Ordering::Relaxed).unpack() };
 
         // The entry was copied, or there is nothing to deallocate.
         if entry.ptr.is_null() || entry.tag() & Entry::COPYING != 0 {
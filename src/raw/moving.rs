@@ -0,0 +1,681 @@
+use std::gc::Gc;
+use std::hash::{BuildHasher, Hash};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use super::alloc::Table;
+use super::probe::Probe;
+use super::utils::{EpochGuard, StrictProvenance, Tagged};
+use super::{meta, Entry, HashMap, KeyHasher, Stamped};
+use crate::map::MoveError;
+use crate::sync::atomic::{AtomicU8, Ordering};
+use crate::sync::thread;
+use crate::Equivalent;
+
+// A marker for an entry that is being moved to another key or table.
+//
+// A move installs two markers, tagged as `MOVING`: one in place of the entry being moved,
+// and one holding the new entry at the target key. The markers share a status, which
+// decides which of them is present. Until the move is committed, the source marker is
+// present as the entry being moved, and the target marker is not present. Once the status
+// is committed, the target marker is present as the new entry, and the source marker is not.
+// The move thus takes effect in a single atomic step, and the entry is never present at
+// both keys or at neither.
+//
+// Any thread that finds a marker in the way of its write settles it by replacing the marker
+// with the entry it resolves to, aborting the move if it is still pending.
+#[repr(C)]
+struct Marker<K, V> {
+    // The entry header.
+    //
+    // The value of a source marker is uninitialized, as it is read through the entry being
+    // moved.
+    entry: Entry<K, V>,
+
+    // The status of the move.
+    status: *const AtomicU8,
+
+    // The entry being moved, as it was found in the table, or null for the target marker.
+    source: *mut Entry<K, V>,
+}
+
+// An entry in a table, along with its index.
+struct Slot<K, V> {
+    table: Table<Entry<K, V>>,
+    i: usize,
+    entry: Tagged<Entry<K, V>>,
+}
+
+impl Marker<(), ()> {
+    // The move has not yet taken effect.
+    const PENDING: u8 = 0;
+
+    // The move took effect.
+    const COMMITTED: u8 = 1;
+
+    // The move was aborted and will never take effect.
+    const ABORTED: u8 = 2;
+}
+
+impl<K, V> Marker<K, V> {
+    // Returns the marker that an entry tagged as `MOVING` points to.
+    //
+    // Note that the value of the marker may be uninitialized.
+    //
+    // # Safety
+    //
+    // `entry` must be a valid, untagged pointer to a marker.
+    #[inline]
+    unsafe fn from_entry<'a>(entry: *mut Entry<K, V>) -> &'a Marker<K, MaybeUninit<V>> {
+        // Safety: Markers are prefixed with an entry, and `MaybeUninit<V>` has the same
+        // layout as `V`.
+        unsafe { &*entry.cast::<Marker<K, MaybeUninit<V>>>() }
+    }
+}
+
+impl<K, V> Marker<K, MaybeUninit<V>> {
+    // Returns the status of the move.
+    #[inline]
+    fn status(&self) -> &AtomicU8 {
+        // Safety: The status is allocated before the markers that share it, and is
+        // never deallocated while they are reachable.
+        unsafe { &*self.status }
+    }
+
+    // Returns the entry being moved, or null for the target marker.
+    #[inline]
+    fn source(&self) -> Tagged<Entry<K, V>> {
+        self.source.cast::<Entry<K, V>>().unpack()
+    }
+
+    // Returns the final status of the move, aborting it if it is still pending.
+    #[inline]
+    fn abort(&self) -> u8 {
+        match self.status().compare_exchange(
+            Marker::PENDING,
+            Marker::ABORTED,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Marker::ABORTED,
+            Err(status) => status,
+        }
+    }
+}
+
+impl<K, V> Entry<K, V> {
+    /// Returns the entry that is present in place of the given entry, or `None` if it is
+    /// a marker for a move that did not take effect.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must be a valid non-null entry that was loaded from the map.
+    #[inline]
+    pub(super) unsafe fn visible(entry: Tagged<Entry<K, V>>) -> Option<*mut Entry<K, V>> {
+        if entry.tag() & Entry::MOVING == 0 {
+            return Some(entry.ptr);
+        }
+
+        // Safety: Guaranteed by caller.
+        let marker = unsafe { Marker::from_entry(entry.ptr) };
+        let committed = marker.status().load(Ordering::Acquire) == Marker::COMMITTED;
+
+        let source = marker.source();
+        if source.ptr.is_null() {
+            // The new entry is present once the move is committed.
+            committed.then_some(entry.ptr)
+        } else {
+            // The entry being moved is present until the move is committed.
+            (!committed).then_some(source.ptr)
+        }
+    }
+
+    /// Returns the entry that the given marker resolves to, aborting its move if it is
+    /// still pending.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must be a valid marker that was loaded from the map.
+    #[inline]
+    unsafe fn settle(entry: Tagged<Entry<K, V>>) -> *mut Entry<K, V> {
+        // Safety: Guaranteed by caller.
+        let marker = unsafe { Marker::from_entry(entry.ptr) };
+        let committed = marker.abort() == Marker::COMMITTED;
+
+        let source = marker.source();
+        match (source.ptr.is_null(), committed) {
+            // The new entry took effect.
+            (true, true) => entry.ptr,
+
+            // The entry was moved away.
+            (false, true) => Entry::TOMBSTONE,
+
+            // The move was aborted, restore the entry being moved.
+            (false, false) => source.raw,
+
+            // The move was aborted, remove the new entry.
+            (true, false) => Entry::TOMBSTONE,
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Replaces a marker at the given index with the entry it resolves to, aborting its
+    /// move if it is still pending.
+    ///
+    /// Returns the entry that is now at the index, which may be another marker. Entries
+    /// being copied are left untouched, as the copy settles them.
+    ///
+    /// # Safety
+    ///
+    /// The index must be in-bounds for the table, and `entry` must be a valid entry that
+    /// was loaded from it.
+    #[cold]
+    #[inline(never)]
+    pub(super) unsafe fn settle_at(
+        &self,
+        i: usize,
+        entry: Tagged<Entry<K, V>>,
+        table: Table<Entry<K, V>>,
+    ) -> Tagged<Entry<K, V>> {
+        if entry.tag() & (Entry::MOVING | Entry::COPYING) != Entry::MOVING {
+            return entry;
+        }
+
+        // Safety: Guaranteed by caller.
+        let settled = unsafe { Entry::settle(entry) };
+
+        // Safety: Guaranteed by caller.
+        let result = unsafe { table.entry(i) }.compare_exchange(
+            entry.raw,
+            settled,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+
+        match result {
+            Ok(_) => {
+                if settled == Entry::TOMBSTONE {
+                    // Safety: Guaranteed by caller.
+                    unsafe { table.meta(i).store(meta::TOMBSTONE, Ordering::Release) };
+                }
+
+                settled.unpack()
+            }
+
+            // The marker was settled by someone else.
+            Err(found) => found.unpack(),
+        }
+    }
+
+    /// Settles any markers at the given index until the entry is not being moved.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of `HashMap::settle_at` apply.
+    #[inline]
+    pub(super) unsafe fn settled_at(
+        &self,
+        i: usize,
+        mut entry: Tagged<Entry<K, V>>,
+        table: Table<Entry<K, V>>,
+    ) -> Tagged<Entry<K, V>> {
+        while entry.tag() & (Entry::MOVING | Entry::COPYING) == Entry::MOVING {
+            // Safety: Guaranteed by caller.
+            entry = unsafe { self.settle_at(i, entry, table) };
+        }
+
+        entry
+    }
+
+    /// Settles an entry that was marked as `COPYING`, returning the entry to copy.
+    ///
+    /// # Safety
+    ///
+    /// The index must be in-bounds for the table, and `entry` must be the valid entry that
+    /// was marked as `COPYING` at that index by the caller.
+    #[inline]
+    pub(super) unsafe fn settle_copying(
+        &self,
+        i: usize,
+        entry: Tagged<Entry<K, V>>,
+        table: &Table<Entry<K, V>>,
+    ) -> Tagged<Entry<K, V>> {
+        if entry.tag() & Entry::MOVING == 0 {
+            return entry;
+        }
+
+        // Safety: Guaranteed by caller.
+        let settled = unsafe { Entry::settle(entry) };
+
+        // No one else writes to an entry after it is marked as `COPYING`, so we can
+        // replace the marker directly.
+        //
+        // Safety: Guaranteed by caller.
+        unsafe {
+            let copying = settled.map_addr(|addr| addr | Entry::COPYING);
+            table.entry(i).store(copying, Ordering::Release);
+
+            if settled == Entry::TOMBSTONE {
+                table.meta(i).store(meta::TOMBSTONE, Ordering::Release);
+            }
+        }
+
+        settled.unpack()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Atomically moves the entry for a key to a new key in the target table, which may
+    /// be this table.
+    ///
+    /// If `new_key` is `None`, the entry keeps its key. The key and value of the entry are
+    /// cloned into the new entry.
+    pub fn move_entry<'g, Q>(
+        &self,
+        key: &Q,
+        target: &HashMap<K, V, S>,
+        new_key: Option<K>,
+    ) -> Result<&'g V, MoveError<'g, V>>
+    where
+        K: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+
+        // Moving an entry to its own key has no effect.
+        let same_key = match &new_key {
+            Some(new_key) => key.equivalent(new_key),
+            None => true,
+        };
+
+        if ptr::eq(self, target) && same_key {
+            return match self.get_with_hash(hash, |k| self.equivalent(hash, key, k)) {
+                Some((_, value)) => Ok(value),
+                None => Err(MoveError::NotFound),
+            };
+        }
+
+        loop {
+            // Find the entry to move.
+            let Some(Slot { table, i, entry }) = self.find_moving(hash, key) else {
+                return Err(MoveError::NotFound);
+            };
+
+            // Safety: `find_moving` returns valid entries that are not markers.
+            let entry_ref = unsafe { &*entry.ptr };
+
+            let status = Gc::into_raw(Gc::new(AtomicU8::new(Marker::PENDING)));
+
+            // Allocate the new entry at the target key.
+            //
+            // Markers are stamped like the entries of the table they are installed in.
+            let new_key = match &new_key {
+                Some(new_key) => new_key.clone(),
+                None => entry_ref.key.clone(),
+            };
+            let new_hash = target.hasher.hash_one(&new_key);
+            let new_entry = Stamped::alloc(
+                Marker {
+                    entry: Entry::new(new_key, entry_ref.value.clone()),
+                    status,
+                    source: ptr::null_mut(),
+                },
+                target.snapshots.is_some(),
+            ) as *mut Entry<K, V>;
+            let new_entry = new_entry.map_addr(|addr| addr | Entry::MOVING).unpack();
+
+            // Allocate the marker for the entry being moved.
+            let source = Stamped::alloc(
+                Marker {
+                    entry: Entry::<K, MaybeUninit<V>>::new(
+                        entry_ref.key.clone(),
+                        MaybeUninit::uninit(),
+                    ),
+                    status,
+                    source: entry.raw.cast::<Entry<K, MaybeUninit<V>>>(),
+                },
+                self.snapshots.is_some(),
+            ) as *mut Entry<K, V>;
+            let source = source.map_addr(|addr| addr | Entry::MOVING).unpack();
+
+            // Mark the entry as moving.
+            //
+            // Note that the entry is still present as long as the move is pending, so
+            // this does not need to be observed by concurrent watchers.
+            //
+            // Safety: `i` is in-bounds for the table.
+            let marked = unsafe { table.entry(i) }.compare_exchange(
+                entry.raw,
+                source.raw,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+
+            // Lost to a concurrent update, retry.
+            if marked.is_err() {
+                self.observer.cas_retry();
+                continue;
+            }
+
+            // Insert the marker for the new entry.
+            //
+            // Safety: The marker was allocated above and is valid for reads.
+            let inserted = match unsafe { target.insert_marker(new_hash, new_entry) } {
+                Ok(inserted) => inserted,
+
+                // The target key is occupied.
+                Err(current) => {
+                    // Safety: `i` is in-bounds for the table and `source` is the marker
+                    // we just installed.
+                    unsafe { self.settle_at(i, source, table) };
+
+                    // Safety: `insert_marker` returns valid entries that are present.
+                    return Err(MoveError::Occupied(unsafe { &(*current).value }));
+                }
+            };
+
+            // Announce the removal and insertion, entering the epochs of both tables in a
+            // consistent order, as a writer waiting for a snapshot of one table must not
+            // block a snapshot of the other.
+            let (source_guard, target_guard) = if ptr::eq(self, target) {
+                let guard = self.enter_write();
+                let reentered = guard.as_ref().map(EpochGuard::reenter);
+                (guard, reentered)
+            } else if (self as *const Self) < (target as *const Self) {
+                let source_guard = self.enter_write();
+                (source_guard, target.enter_write())
+            } else {
+                let target_guard = target.enter_write();
+                (self.enter_write(), target_guard)
+            };
+
+            // Safety: A tombstone is a valid sentinel, and the new entry is a marker for
+            // a move that has not yet taken effect.
+            let (source_writer, target_writer) = unsafe {
+                (
+                    self.begin_write_in(source_guard, Entry::TOMBSTONE),
+                    target.begin_write_in(target_guard, new_entry.ptr),
+                )
+            };
+
+            // Commit the move, unless it was aborted by a concurrent writer.
+            //
+            // Note that `SeqCst` is necessary for the move to be observed by concurrent
+            // watchers, see `Watchers::subscribe`.
+            //
+            // Safety: The status was allocated above and is never deallocated.
+            let committed = unsafe { &*status }
+                .compare_exchange(
+                    Marker::PENDING,
+                    Marker::COMMITTED,
+                    Ordering::SeqCst,
+                    Ordering::Acquire,
+                )
+                .is_ok();
+
+            // Safety: The commit inserted the new entry into an empty entry, and removed the
+            // entry being moved.
+            unsafe {
+                target.end_write(target_writer, committed.then(ptr::null_mut));
+                self.end_write(source_writer, committed.then_some(entry.ptr));
+            }
+
+            // Replace both markers with the entries they resolve to.
+            //
+            // Safety: Both indices are in-bounds for their tables, and the markers were
+            // installed by us.
+            unsafe {
+                self.settle_at(i, source, table);
+                target.settle_at(inserted.i, inserted.entry, inserted.table);
+            }
+
+            // The move was aborted by a concurrent writer, retry.
+            if !committed {
+                self.observer.cas_retry();
+                thread::yield_now();
+                continue;
+            }
+
+            self.count.decrement();
+            target.count.increment();
+
+            // Safety: We just moved the entry.
+            unsafe {
+                self.notify(hash, entry.ptr, ptr::null_mut());
+                target.notify(new_hash, ptr::null_mut(), new_entry.ptr);
+            }
+
+            self.debug_invariants();
+            target.debug_invariants();
+
+            // Safety: The new entry was just inserted.
+            return Ok(unsafe { &(*new_entry.ptr).value });
+        }
+    }
+
+    /// Finds the entry for a key in order to move it.
+    ///
+    /// The returned entry is a valid, non-null entry that is not a marker.
+    fn find_moving<Q>(&self, hash: u64, key: &Q) -> Option<Slot<K, V>>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        // Load the root table.
+        let mut table = self.root();
+
+        // The table has not been initialized yet.
+        if table.raw.is_null() {
+            return None;
+        }
+
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
+
+        let mut help_copy = true;
+        loop {
+            // Initialize the probe state.
+            let mut probe = Probe::start(h1, table.mask);
+
+            // Probe until we reach the limit.
+            let copying = 'probe: loop {
+                if probe.len > table.limit {
+                    break None;
+                }
+
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let meta = unsafe { table.meta(probe.i).load(Ordering::Acquire) };
+
+                // The key is not in the table.
+                if meta == meta::EMPTY {
+                    return None;
+                }
+
+                // Check for a potential match.
+                if meta != h2 {
+                    probe.next(table.mask);
+                    continue 'probe;
+                }
+
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let entry = unsafe { table.entry(probe.i).load(Ordering::Acquire) }.unpack();
+
+                // The entry was deleted, keep probing.
+                if entry.ptr.is_null() {
+                    probe.next(table.mask);
+                    continue 'probe;
+                }
+
+                // Check for a full match.
+                //
+                // Safety: We performed an `Acquire` load of a non-null entry pointer.
+                if !self.equivalent(hash, key, unsafe { &(*entry.ptr).key }) {
+                    probe.next(table.mask);
+                    continue 'probe;
+                }
+
+                // The entry is being copied to the new table.
+                if entry.tag() & Entry::COPYING != 0 {
+                    break 'probe Some(probe.i);
+                }
+
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `probe.i` is always in-bounds for the table length, and `entry`
+                    // is a valid non-null entry that we found in the map.
+                    unsafe { self.settle_at(probe.i, entry, table) };
+                    continue 'probe;
+                }
+
+                return Some(Slot {
+                    table,
+                    i: probe.i,
+                    entry,
+                });
+            };
+
+            // Prepare to retry in the next table.
+            table = self.prepare_retry(copying, &mut help_copy, table)?;
+        }
+    }
+
+    /// Inserts the marker for the new entry of a move, returning the slot it was inserted
+    /// at, or the entry that is present at its key.
+    ///
+    /// # Safety
+    ///
+    /// `marker` must be a valid marker for the new entry of a pending move.
+    unsafe fn insert_marker(
+        &self,
+        hash: u64,
+        marker: Tagged<Entry<K, V>>,
+    ) -> Result<Slot<K, V>, *mut Entry<K, V>> {
+        // Safety: Guaranteed by caller.
+        let key = unsafe { &(*marker.ptr).key };
+
+        // Load the root table.
+        let mut table = self.root();
+
+        // Allocate the table if it has not been initialized yet.
+        if table.raw.is_null() {
+            table = self.init(None);
+        }
+
+        let (h1, h2) = (meta::h1(hash), meta::h2(hash));
+
+        let mut help_copy = true;
+        loop {
+            // Initialize the probe state.
+            let mut probe = Probe::start(h1, table.mask);
+
+            // Probe until we reach the limit.
+            let copying = 'probe: loop {
+                if probe.len > table.limit {
+                    self.observer.probe_limit_exceeded(table.len());
+                    break None;
+                }
+
+                // Safety: `probe.i` is always in-bounds for the table length.
+                let meta_entry = unsafe { table.meta(probe.i) };
+                let meta = meta_entry.load(Ordering::Acquire);
+
+                let entry = if meta == meta::EMPTY {
+                    // Try to claim the empty entry.
+                    //
+                    // Note that the marker is not present until the move is committed, so
+                    // this is not a write to the table.
+                    //
+                    // Safety: `probe.i` is always in-bounds for the table length.
+                    let result = unsafe { table.entry(probe.i) }.compare_exchange(
+                        ptr::null_mut(),
+                        marker.raw,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    );
+
+                    match result {
+                        Ok(_) => {
+                            meta_entry.store(h2, Ordering::Release);
+                            return Ok(Slot {
+                                table,
+                                i: probe.i,
+                                entry: marker,
+                            });
+                        }
+
+                        Err(found) => {
+                            let found = found.unpack();
+
+                            // Ensure the meta table is updated to keep the probe chain alive
+                            // for readers.
+                            let meta = if found.ptr.is_null() {
+                                meta::TOMBSTONE
+                            } else {
+                                // Safety: We performed an `Acquire` load of a non-null
+                                // entry pointer.
+                                meta::h2(self.hasher.hash_key(unsafe { &(*found.ptr).key }))
+                            };
+
+                            if meta_entry.load(Ordering::Relaxed) == meta::EMPTY {
+                                meta_entry.store(meta, Ordering::Release);
+                            }
+
+                            // The entry was deleted or null copied, continue probing.
+                            if found.ptr.is_null() {
+                                probe.next(table.mask);
+                                continue 'probe;
+                            }
+
+                            found
+                        }
+                    }
+                } else if meta == h2 {
+                    // Safety: `probe.i` is always in-bounds for the table length.
+                    let entry = unsafe { table.entry(probe.i).load(Ordering::Acquire) }.unpack();
+
+                    // The entry was deleted, keep probing.
+                    if entry.ptr.is_null() {
+                        probe.next(table.mask);
+                        continue 'probe;
+                    }
+
+                    entry
+                } else {
+                    probe.next(table.mask);
+                    continue 'probe;
+                };
+
+                // Check for a full match.
+                //
+                // Safety: We performed an `Acquire` load of a non-null entry pointer.
+                if unsafe { (*entry.ptr).key != *key } {
+                    probe.next(table.mask);
+                    continue 'probe;
+                }
+
+                // The entry is being copied to the new table.
+                if entry.tag() & Entry::COPYING != 0 {
+                    break 'probe Some(probe.i);
+                }
+
+                // The entry is being moved, settle the move and check the entry again.
+                if entry.tag() & Entry::MOVING != 0 {
+                    // Safety: `probe.i` is always in-bounds for the table length, and `entry`
+                    // is a valid non-null entry that we found in the map.
+                    unsafe { self.settle_at(probe.i, entry, table) };
+                    continue 'probe;
+                }
+
+                // The key is occupied.
+                return Err(entry.ptr);
+            };
+
+            // Prepare to retry in the next table.
+            table = self.prepare_retry_insert(copying, &mut help_copy, table);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 // A quadratic probe sequence.
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Probe {
     // The current index in the probe sequence.
     pub i: usize,
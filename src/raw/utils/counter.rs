@@ -1,56 +1,140 @@
-use std::sync::atomic::{AtomicIsize, Ordering};
+use std::mem;
 
 use super::CachePadded;
+use crate::map::CounterMode;
+use crate::sync::atomic::{AtomicIsize, Ordering};
 
-// A sharded atomic counter.
+// An atomic counter of the number of entries in a `HashMap`.
 //
 // Sharding the length counter of `HashMap` is extremely important,
 // as a single point of contention for insertions/deletions significantly
-// degrades concurrent performance.
-//
-// We can take advantage of the fact that `seize::Guard`
-pub struct Counter(Box<[CachePadded<AtomicIsize>]>);
+// degrades concurrent performance. However, summing all the shards makes
+// `len` more expensive, so the layout is configurable through `CounterMode`.
+pub enum Counter {
+    // A single atomic counter.
+    Exact(CachePadded<AtomicIsize>),
 
-impl Default for Counter {
-    /// Create a new `Counter`.
-    fn default() -> Counter {
-        let num_cpus = std::thread::available_parallelism()
-            .map(usize::from)
-            .unwrap_or(1);
+    // A counter sharded by thread.
+    Sharded(Box<[CachePadded<AtomicIsize>]>),
 
-        // Round up to the next power-of-two for fast modulo.
-        let shards = (0..num_cpus.next_power_of_two())
-            .map(|_| Default::default())
-            .collect();
+    // No counter is maintained.
+    Disabled,
+}
 
-        Counter(shards)
+impl Default for Counter {
+    /// Create a new sharded `Counter`.
+    fn default() -> Counter {
+        Counter::new(CounterMode::default())
     }
 }
 
 impl Counter {
-    // Return the shard for the given thread ID.
+    // Create a new `Counter` with the given mode.
+    pub fn new(mode: CounterMode) -> Counter {
+        match mode {
+            CounterMode::Exact => Counter::Exact(Default::default()),
+            CounterMode::Sharded(shards) => {
+                // Round up to the next power-of-two for fast modulo.
+                let shards = (0..shards.max(1).next_power_of_two())
+                    .map(|_| Default::default())
+                    .collect();
+
+                Counter::Sharded(shards)
+            }
+            CounterMode::Disabled => Counter::Disabled,
+        }
+    }
+
+    // Returns the mode of this counter.
+    pub fn mode(&self) -> CounterMode {
+        match self {
+            Counter::Exact(_) => CounterMode::Exact,
+            Counter::Sharded(shards) => CounterMode::Sharded(shards.len()),
+            Counter::Disabled => CounterMode::Disabled,
+        }
+    }
+
+    // Increment the counter.
     #[inline]
-    pub fn get(&self) -> &AtomicIsize {
-        // Guard thread IDs are essentially perfectly sharded due to
-        // the internal thread ID allocator, which makes contention
-        // very unlikely even with the exact number of shards as CPUs.
-        // FIXME
-        //let shard = guard.thread_id() & (self.0.len() - 1);
-        let shard = thread_id::get() & (self.0.len() - 1);
+    pub fn increment(&self) {
+        if let Some(shard) = self.get() {
+            shard.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 
-        &self.0[shard].value
+    // Decrement the counter.
+    #[inline]
+    pub fn decrement(&self) {
+        if let Some(shard) = self.get() {
+            shard.fetch_sub(1, Ordering::Relaxed);
+        }
     }
 
-    // Returns the sum of all counter shards.
+    // Return the shard for the current thread.
     #[inline]
-    pub fn sum(&self) -> usize {
-        self.0
-            .iter()
-            .map(|x| x.value.load(Ordering::Relaxed))
-            .sum::<isize>()
-            .try_into()
-            // Depending on the order of deletion/insertions this might be negative,
-            // in which case we assume the map is empty.
-            .unwrap_or(0)
+    fn get(&self) -> Option<&AtomicIsize> {
+        match self {
+            Counter::Exact(counter) => Some(&counter.value),
+            Counter::Sharded(shards) => {
+                // Thread slots are allocated sequentially, which makes contention
+                // very unlikely even with the exact number of shards as CPUs.
+                let shard = thread_slot() & (shards.len() - 1);
+                Some(&shards[shard].value)
+            }
+            Counter::Disabled => None,
+        }
     }
+
+    // Returns the sum of all counter shards, or `None` if the counter is disabled.
+    #[inline]
+    pub fn sum(&self) -> Option<usize> {
+        let sum = match self {
+            Counter::Exact(counter) => counter.value.load(Ordering::Relaxed),
+            Counter::Sharded(shards) => shards
+                .iter()
+                .map(|x| x.value.load(Ordering::Relaxed))
+                .sum::<isize>(),
+            Counter::Disabled => return None,
+        };
+
+        // Depending on the order of deletion/insertions this might be negative,
+        // in which case we assume the map is empty.
+        Some(sum.try_into().unwrap_or(0))
+    }
+
+    // Returns the number of bytes allocated for counter shards.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Counter::Sharded(shards) => mem::size_of_val::<[_]>(shards),
+            Counter::Exact(_) | Counter::Disabled => 0,
+        }
+    }
+}
+
+// Returns a stable slot for the current thread.
+//
+// Unlike OS thread identifiers, which are often aligned pointers with
+// constant low bits, slots are handed out sequentially and so spread
+// evenly across any power-of-two number of shards.
+#[cfg(not(papaya_loom))]
+#[inline]
+pub fn thread_slot() -> usize {
+    use std::sync::atomic::AtomicUsize;
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static SLOT: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    SLOT.with(|slot| *slot)
+}
+
+// Slots are not reset between `loom` executions, which would make the model
+// nondeterministic. The slot only affects contention, not correctness.
+#[cfg(papaya_loom)]
+#[inline]
+pub fn thread_slot() -> usize {
+    0
 }
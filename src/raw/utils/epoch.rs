@@ -0,0 +1,228 @@
+use std::{mem, ptr};
+
+use super::counter::thread_slot;
+use super::CachePadded;
+use crate::sync::atomic::{self, AtomicIsize, AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::{hint, thread, AtomicMut};
+
+// A global epoch that allows waiting for every writer that entered a previous epoch.
+//
+// Writers enter the epoch for the duration of a single atomic update, announcing themselves
+// on a counter sharded by thread. Counters are kept separately for even and odd epochs, so
+// after the epoch is advanced, writers of the previous epoch can be waited for without
+// being confused with writers entering the new one.
+//
+// The counters are allocated when the epoch is first entered.
+pub struct Epoch {
+    // The current epoch.
+    current: CachePadded<AtomicUsize>,
+
+    // The number of active writers in even and odd epochs, or null if not yet allocated.
+    active: AtomicPtr<[Box<[CachePadded<AtomicIsize>]>; 2]>,
+}
+
+impl Epoch {
+    // Create a new `Epoch`.
+    pub fn new() -> Epoch {
+        Epoch {
+            current: Default::default(),
+            active: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    // Returns the writer counters, allocating them if necessary.
+    #[inline]
+    fn active(&self) -> &[Box<[CachePadded<AtomicIsize>]>; 2] {
+        let active = self.active.load(Ordering::Acquire);
+
+        if active.is_null() {
+            return self.init_active();
+        }
+
+        // Safety: The counters are initialized and never deallocated until the epoch is dropped.
+        unsafe { &*active }
+    }
+
+    // Allocate the writer counters, with a shard for each available CPU.
+    #[cold]
+    #[inline(never)]
+    fn init_active(&self) -> &[Box<[CachePadded<AtomicIsize>]>; 2] {
+        let shards = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1);
+
+        let shards = || {
+            // Round up to the next power-of-two for fast modulo.
+            (0..shards.next_power_of_two())
+                .map(|_| Default::default())
+                .collect()
+        };
+
+        let new = Box::into_raw(Box::new([shards(), shards()]));
+
+        let active = match self.active.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+
+            // Lost the race to allocate the counters.
+            Err(found) => {
+                // Safety: Our allocation was never shared.
+                drop(unsafe { Box::from_raw(new) });
+                found
+            }
+        };
+
+        // Safety: The counters are initialized and never deallocated until the epoch is dropped.
+        unsafe { &*active }
+    }
+
+    // Enter the current epoch, returning a guard that exits it when dropped.
+    #[inline]
+    pub fn enter(&self) -> EpochGuard<'_> {
+        let active = self.active();
+
+        loop {
+            let epoch = self.current.value.load(Ordering::Relaxed);
+            let shard = Self::shard(active, epoch);
+            shard.fetch_add(1, Ordering::Relaxed);
+
+            // Ensure the epoch was not advanced before we were announced, in which case
+            // `synchronize` may have missed us.
+            //
+            // This pairs with the `SeqCst` fence in `synchronize`.
+            atomic::fence(Ordering::SeqCst);
+
+            if self.current.value.load(Ordering::Relaxed) == epoch {
+                return EpochGuard { epoch, shard };
+            }
+
+            shard.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    // Returns the current epoch.
+    #[inline]
+    pub fn current(&self) -> usize {
+        self.current.value.load(Ordering::Relaxed)
+    }
+
+    // Start from the first even epoch after the given epoch.
+    pub fn start_after(&mut self, epoch: usize) {
+        self.current.value.store_mut((epoch + 2) & !1);
+    }
+
+    // Advance the epoch, returning the new epoch.
+    #[inline]
+    pub fn advance(&self) -> usize {
+        self.current.value.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // Wait for every writer that entered before `epoch` to exit.
+    //
+    // The epoch must have been advanced by the caller, and not advanced again since.
+    pub fn synchronize(&self, epoch: usize) {
+        let previous = &self.active()[epoch.wrapping_sub(1) & 1];
+
+        // Ensure we observe the announcement of any writer that did not observe the
+        // epoch being advanced.
+        //
+        // This pairs with the `SeqCst` fence in `enter`.
+        atomic::fence(Ordering::SeqCst);
+
+        for spun in 0.. {
+            // `Acquire` synchronizes with the `Release` decrement when a writer exits,
+            // ensuring its update is visible.
+            let active = previous
+                .iter()
+                .map(|shard| shard.value.load(Ordering::Acquire))
+                .sum::<isize>();
+
+            if active == 0 {
+                return;
+            }
+
+            // Writers only hold the epoch for a single update, so spin for a short while
+            // before yielding.
+            if spun < 32 {
+                hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+
+    // Returns the shard for the current thread in the given epoch.
+    #[inline]
+    fn shard(active: &[Box<[CachePadded<AtomicIsize>]>; 2], epoch: usize) -> &AtomicIsize {
+        let shards = &active[epoch & 1];
+        &shards[thread_slot() & (shards.len() - 1)].value
+    }
+
+    // Returns the number of bytes allocated for writer shards.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        let active = self.active.load(Ordering::Acquire);
+
+        if active.is_null() {
+            return 0;
+        }
+
+        // Safety: The counters are initialized and never deallocated until the epoch is dropped.
+        let active = unsafe { &*active };
+
+        mem::size_of_val(active)
+            + active
+                .iter()
+                .map(|shards| mem::size_of_val::<[_]>(shards))
+                .sum::<usize>()
+    }
+}
+
+impl Drop for Epoch {
+    fn drop(&mut self) {
+        let active = self.active.load_mut();
+
+        if !active.is_null() {
+            // Safety: We have unique access to the counters, which were allocated with `Box`.
+            drop(unsafe { Box::from_raw(active) });
+        }
+    }
+}
+
+// A writer that has entered an epoch.
+pub struct EpochGuard<'a> {
+    // The epoch that was entered.
+    pub epoch: usize,
+
+    // The shard the writer was announced on.
+    shard: &'a AtomicIsize,
+}
+
+impl EpochGuard<'_> {
+    // Enter the epoch of this guard again, returning a second guard.
+    //
+    // The epoch cannot be advanced past ours while this guard is held, so the new
+    // guard does not need to validate the epoch.
+    #[inline]
+    pub fn reenter(&self) -> Self {
+        self.shard.fetch_add(1, Ordering::Relaxed);
+
+        EpochGuard {
+            epoch: self.epoch,
+            shard: self.shard,
+        }
+    }
+}
+
+impl Drop for EpochGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // `Release` ensures that any updates performed in this epoch are visible
+        // to `synchronize`.
+        self.shard.fetch_sub(1, Ordering::Release);
+    }
+}
@@ -0,0 +1,140 @@
+// Fault injection for testing rare races, enabled with `cfg(papaya_fault)`.
+//
+// Faults are injected at random from a per-thread generator derived from a global seed.
+// The seed is read from the `PAPAYA_FAULT_SEED` environment variable, or chosen at random
+// and printed if a thread panics, allowing failures to be reproduced. Note that the
+// interleaving of threads is not controlled, so a seed only reproduces the faults injected
+// by each thread.
+//
+// Every fault corresponds to a state that can be reached without fault injection, such
+// as a spurious failure of a weak CAS, or a table that is too full to complete a copy.
+// Without `cfg(papaya_fault)`, all faults compile to no-ops.
+
+#[cfg(papaya_fault)]
+mod imp {
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::OnceLock;
+    use std::{env, panic, thread};
+
+    // Returns the global seed, initializing it if necessary.
+    fn seed() -> u64 {
+        static SEED: OnceLock<u64> = OnceLock::new();
+
+        *SEED.get_or_init(|| {
+            let seed = match env::var("PAPAYA_FAULT_SEED") {
+                Ok(seed) => seed.parse().expect("invalid `PAPAYA_FAULT_SEED`"),
+                Err(_) => RandomState::new().hash_one(0),
+            };
+
+            // Print the seed on failure.
+            let hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                eprintln!("papaya: fault injection failed with `PAPAYA_FAULT_SEED={seed}`");
+                hook(info);
+            }));
+
+            seed
+        })
+    }
+
+    // Returns a random number from the current thread's generator.
+    fn next() -> u64 {
+        // Threads are seeded in the order they first inject a fault.
+        static THREADS: AtomicU64 = AtomicU64::new(0);
+
+        thread_local! {
+            static STATE: Cell<u64> = Cell::new(
+                seed() ^ THREADS.fetch_add(1, Ordering::Relaxed).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            );
+        }
+
+        // SplitMix64.
+        STATE.with(|state| {
+            let mut z = state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+            state.set(z);
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        })
+    }
+
+    // Returns `true` with a probability of `1 / n`.
+    fn one_in(n: u64) -> bool {
+        next() % n == 0
+    }
+
+    // Returns `true` if a CAS should fail, either spuriously or by injecting a concurrent write.
+    #[inline]
+    pub fn fail_cas() -> bool {
+        one_in(8)
+    }
+
+    // Yield to other threads before an operation, widening the window for races.
+    #[inline]
+    pub fn preempt() {
+        if one_in(16) {
+            thread::yield_now();
+        }
+    }
+
+    // Delay the current thread for a random amount of time.
+    #[inline]
+    pub fn delay() {
+        if one_in(4) {
+            for _ in 0..next() % 8 {
+                thread::yield_now();
+            }
+        }
+    }
+
+    // Returns the probe limit for a new table, shrinking it at random to force resizes.
+    #[inline]
+    pub fn probe_limit(limit: usize) -> usize {
+        if one_in(2) {
+            let min = (limit / 2).max(1);
+            return min + (next() as usize % (limit - min + 1));
+        }
+
+        limit
+    }
+
+    // Returns `true` if a blocking copy out of a table of length `len` should act as if
+    // the new table is full, aborting the resize.
+    //
+    // This is called for every entry that is copied, so roughly one in four resizes
+    // will be aborted. Incremental resizes cannot be aborted without probing the new
+    // table, but are forced to overflow into a nested table by `probe_limit` instead.
+    #[inline]
+    pub fn abort_resize(len: usize) -> bool {
+        one_in(4 * len as u64)
+    }
+}
+
+#[cfg(not(papaya_fault))]
+mod imp {
+    #[inline(always)]
+    pub fn fail_cas() -> bool {
+        false
+    }
+
+    #[inline(always)]
+    pub fn preempt() {}
+
+    #[inline(always)]
+    pub fn delay() {}
+
+    #[inline(always)]
+    pub fn probe_limit(limit: usize) -> usize {
+        limit
+    }
+
+    #[inline(always)]
+    pub fn abort_resize(_len: usize) -> bool {
+        false
+    }
+}
+
+pub use imp::*;
@@ -1,9 +1,17 @@
 mod counter;
+mod epoch;
+pub mod fault;
+mod observer;
+mod once;
 mod parker;
+pub mod random;
 mod stack;
 mod tagged;
 
 pub use counter::Counter;
+pub use epoch::{Epoch, EpochGuard};
+pub use observer::Observer;
+pub use once::Initializers;
 pub use parker::Parker;
 pub use stack::Stack;
 pub use tagged::{untagged, AtomicPtrFetchOps, StrictProvenance, Tagged, Unpack};
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use crate::MapObserver;
+
+// Dispatches internal events to an optional user `MapObserver`, and to
+// `tracing` if the feature is enabled.
+#[derive(Default)]
+pub struct Observer(Option<Arc<dyn MapObserver>>);
+
+impl Observer {
+    // Create a new `Observer`.
+    pub fn new(observer: Option<Arc<dyn MapObserver>>) -> Observer {
+        Observer(observer)
+    }
+
+    #[inline]
+    pub fn resize_started(&self, old_capacity: usize, new_capacity: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(old_capacity, new_capacity, "resize started");
+
+        if let Some(observer) = &self.0 {
+            observer.resize_started(old_capacity, new_capacity);
+        }
+    }
+
+    #[inline]
+    pub fn resize_promoted(&self, capacity: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(capacity, "resize promoted");
+
+        if let Some(observer) = &self.0 {
+            observer.resize_promoted(capacity);
+        }
+    }
+
+    #[inline]
+    pub fn resize_aborted(&self, capacity: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(capacity, "resize aborted");
+
+        if let Some(observer) = &self.0 {
+            observer.resize_aborted(capacity);
+        }
+    }
+
+    // Report that the current thread is helping with the copy into the table at the given
+    // address. Threads help with a copy many times over the course of a resize, but the event
+    // is only reported the first time.
+    #[inline]
+    pub fn copy_joined(&self, next: *const u8, capacity: usize) {
+        if !cfg!(feature = "tracing") && self.0.is_none() {
+            return;
+        }
+
+        if !first_join(next) {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(capacity, "joined copy");
+
+        if let Some(observer) = &self.0 {
+            observer.copy_joined(capacity);
+        }
+    }
+
+    #[inline]
+    pub fn probe_limit_exceeded(&self, capacity: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(capacity, "probe limit exceeded");
+
+        if let Some(observer) = &self.0 {
+            observer.probe_limit_exceeded(capacity);
+        }
+    }
+
+    #[inline]
+    pub fn cas_retry(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("cas retry");
+
+        if let Some(observer) = &self.0 {
+            observer.cas_retry();
+        }
+    }
+
+    #[inline]
+    pub fn parked(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("parked");
+
+        if let Some(observer) = &self.0 {
+            observer.parked();
+        }
+    }
+}
+
+// Returns `true` if this is the first time the current thread joined the copy into the
+// table at the given address.
+//
+// Only the most recent copy is remembered. Copies within a map complete in order, so a
+// thread never returns to an older one, but a thread alternating between the copies of
+// several maps may join the same copy more than once.
+fn first_join(next: *const u8) -> bool {
+    use std::cell::Cell;
+
+    thread_local! {
+        static JOINED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    JOINED.with(|joined| joined.replace(next as usize) != next as usize)
+}
@@ -0,0 +1,225 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use crate::sync::thread::{self, ThreadId};
+use crate::sync::{Condvar, Mutex};
+
+// A registry of keys being initialized.
+//
+// A thread initializing a missing key claims its hash, and other threads initializing a key
+// with the same hash wait until the claim is released, either after the key was inserted or
+// the initializer failed. An initializer that fails with an error may record it on the claim,
+// to be shared with the waiting tasks. Every claim has its own wait state, so releasing a claim only wakes
+// the threads waiting on its hash. The registry itself is only locked to claim or release a
+// hash.
+pub struct Initializers {
+    // The claims of keys being initialized, keyed by hash.
+    claims: Mutex<HashMap<u64, Arc<Pending>>>,
+}
+
+// The wait state of a claimed hash.
+struct Pending {
+    // The thread that claimed the hash, or `None` if it was claimed by a task.
+    owner: Option<ThreadId>,
+
+    // The state of the claim.
+    state: Mutex<PendingState>,
+
+    // Notifies blocked threads that the claim was released.
+    released: Condvar,
+}
+
+struct PendingState {
+    // Whether the claim was released.
+    released: bool,
+
+    // The wakers of asynchronous waiters.
+    wakers: Vec<Waker>,
+
+    // The error the initializer failed with, if it was recorded.
+    error: Option<Box<dyn Any + Send>>,
+}
+
+impl Initializers {
+    // Create a new `Initializers`.
+    pub fn new() -> Initializers {
+        Initializers {
+            claims: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Claim the initialization of keys with the given hash for the current thread, or
+    // return the claim of another thread if the hash is already claimed.
+    pub fn claim(&self, hash: u64) -> Result<Claim<'_>, Claimed> {
+        self.claim_for(hash, Some(thread::current().id()))
+    }
+
+    // Claim the initialization of keys with the given hash for the current task, or
+    // return the claim of another task if the hash is already claimed.
+    pub fn claim_async(&self, hash: u64) -> Result<Claim<'_>, Claimed> {
+        self.claim_for(hash, None)
+    }
+
+    // Claim the initialization of keys with the given hash.
+    fn claim_for(&self, hash: u64, owner: Option<ThreadId>) -> Result<Claim<'_>, Claimed> {
+        let mut claims = self.claims.lock().unwrap();
+
+        if let Some(pending) = claims.get(&hash) {
+            return Err(Claimed {
+                pending: pending.clone(),
+            });
+        }
+
+        let pending = Pending {
+            owner,
+            state: Mutex::new(PendingState {
+                released: false,
+                wakers: Vec::new(),
+                error: None,
+            }),
+            released: Condvar::new(),
+        };
+
+        claims.insert(hash, Arc::new(pending));
+
+        Ok(Claim {
+            initializers: self,
+            hash,
+            error: None,
+        })
+    }
+}
+
+// A claim on the initialization of keys with a given hash.
+//
+// The claim is released when dropped, including if the initializer panics or its future
+// is cancelled.
+pub struct Claim<'a> {
+    initializers: &'a Initializers,
+    hash: u64,
+
+    // The error the initializer failed with, published when the claim is released.
+    error: Option<Box<dyn Any + Send>>,
+}
+
+impl Claim<'_> {
+    // Release the claim after the initializer failed with the given error.
+    //
+    // The error is shared with the tasks waiting on the claim through `wait_async`.
+    pub fn fail<E>(mut self, error: E)
+    where
+        E: Send + 'static,
+    {
+        self.error = Some(Box::new(error));
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        let pending = {
+            let mut claims = self.initializers.claims.lock().unwrap();
+            claims.remove(&self.hash).unwrap()
+        };
+
+        let wakers = {
+            let mut state = pending.state.lock().unwrap();
+            state.released = true;
+            state.error = self.error.take();
+            mem::take(&mut state.wakers)
+        };
+
+        pending.released.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+// The claim of another thread or task on the initialization of keys with a given hash.
+pub struct Claimed {
+    pending: Arc<Pending>,
+}
+
+impl Claimed {
+    // Block the current thread until the claim is released.
+    //
+    // The release of the claim happens-before this method returns.
+    //
+    // # Panics
+    //
+    // Panics if the claim is held by the current thread, which would otherwise deadlock.
+    pub fn wait(self) {
+        self.check_reentrant();
+
+        let mut state = self.pending.state.lock().unwrap();
+        while !state.released {
+            state = self.pending.released.wait(state).unwrap();
+        }
+    }
+
+    // Wait asynchronously until the claim is released.
+    //
+    // The returned future resolves to the error the initializer failed with, if it was
+    // recorded with `Claim::fail` and is of type `E`. The release of the claim happens-before
+    // the returned future completes.
+    //
+    // # Panics
+    //
+    // Panics if the claim is held by the current thread, see `wait`.
+    pub fn wait_async<E>(self) -> WaitRelease<E> {
+        self.check_reentrant();
+        WaitRelease {
+            pending: self.pending,
+            _error: PhantomData,
+        }
+    }
+
+    // Panics if the claim is held by the current thread.
+    //
+    // A thread holding a claim is running the initializer, so it can only be waiting on its
+    // own claim if the initializer attempted to initialize a key with the same hash.
+    fn check_reentrant(&self) {
+        if self.pending.owner == Some(thread::current().id()) {
+            panic!("attempted to initialize a key from within its own initializer");
+        }
+    }
+}
+
+// A future that completes once a claim is released, with the error recorded by the
+// initializer, if any.
+pub struct WaitRelease<E> {
+    pending: Arc<Pending>,
+    _error: PhantomData<fn() -> E>,
+}
+
+impl<E> Future for WaitRelease<E>
+where
+    E: Clone + 'static,
+{
+    type Output = Option<E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<E>> {
+        let mut state = self.pending.state.lock().unwrap();
+        if state.released {
+            // Every waiter receives its own copy of the error.
+            let error = state
+                .error
+                .as_ref()
+                .and_then(|error| error.downcast_ref::<E>());
+            return Poll::Ready(error.cloned());
+        }
+
+        // Register to be woken when the claim is released, unless we already are.
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
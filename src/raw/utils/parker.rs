@@ -1,7 +1,8 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicU8, Ordering};
-use std::sync::Mutex;
-use std::thread::{self, Thread};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::sync::atomic::{AtomicPtr, AtomicU64, AtomicU8, Ordering};
+use crate::sync::thread::{self, Thread};
+use crate::sync::Mutex;
 
 // A simple thread parker.
 //
@@ -21,7 +22,7 @@ pub struct Parker {
 #[derive(Default)]
 struct State {
     count: u64,
-    threads: HashMap<usize, HashMap<u64, Thread>>,
+    threads: HashMap<usize, BTreeMap<u64, Thread>>,
 }
 
 impl Parker {
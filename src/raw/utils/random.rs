@@ -0,0 +1,34 @@
+// A fast, non-cryptographic source of randomness for picking table slots.
+//
+// Each thread has its own generator, seeded from the randomly keyed `RandomState`,
+// so concurrent callers pick independent slots rather than contending on the same ones.
+
+// Returns a random number from the current thread's generator.
+#[cfg(not(papaya_loom))]
+#[inline]
+pub fn next() -> u64 {
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(0));
+    }
+
+    // SplitMix64.
+    STATE.with(|state| {
+        let mut z = state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state.set(z);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    })
+}
+
+// The generator is not reset between `loom` executions, which would make the model
+// nondeterministic. The choice of slot only affects contention, not correctness.
+#[cfg(papaya_loom)]
+#[inline]
+pub fn next() -> u64 {
+    0
+}
@@ -1,6 +1,8 @@
 use std::gc::Gc;
-use std::ptr;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::{mem, ptr};
+
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use crate::sync::AtomicMut;
 
 /// A simple lock-free, append-only, stack of pointers.
 ///
@@ -46,10 +48,11 @@ impl<T> Stack<T> {
 
             // Attempt to push the node.
             //
-            // `Relaxed` is similarly sufficient here.
+            // `Release` synchronizes with `heap_size`, which traverses the stack
+            // through `&self`.
             if self
                 .head
-                .compare_exchange(head, node, Ordering::Relaxed, Ordering::Relaxed)
+                .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
                 .is_ok()
             {
                 break;
@@ -57,9 +60,25 @@ impl<T> Stack<T> {
         }
     }
 
+    /// Returns the number of bytes allocated for nodes in the stack.
+    pub fn heap_size(&self) -> usize {
+        let mut size = 0;
+        let mut head = self.head.load(Ordering::Acquire);
+
+        while !head.is_null() {
+            size += mem::size_of::<Node<T>>();
+
+            // Safety: Nodes are only deallocated through `&mut self`, and the
+            // node is non-null.
+            head = unsafe { (*head).next };
+        }
+
+        size
+    }
+
     /// Drain all elements from the stack.
     pub fn drain(&mut self, mut f: impl FnMut(T)) {
-        let mut head = *self.head.get_mut();
+        let mut head = self.head.load_mut();
 
         while !head.is_null() {
             // Safety: We have `&mut self` and the node is non-null.
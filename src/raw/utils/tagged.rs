@@ -1,6 +1,5 @@
-use std::sync::atomic::{AtomicPtr, Ordering};
-
 use crate::reclaim::Atomic;
+use crate::sync::atomic::{AtomicPtr, Ordering};
 
 // Polyfill for the unstable strict-provenance APIs.
 #[allow(clippy::missing_safety_doc)]
@@ -69,15 +68,6 @@ where
     pub fn tag(self) -> usize {
         self.raw.addr() & !T::MASK
     }
-
-    // Maps the tag of this pointer.
-    #[inline]
-    pub fn map_tag(self, f: impl FnOnce(usize) -> usize) -> Self {
-        Tagged {
-            raw: self.raw.map_addr(f),
-            ptr: self.ptr,
-        }
-    }
 }
 
 impl<T> Copy for Tagged<T> {}
@@ -96,7 +86,7 @@ pub trait AtomicPtrFetchOps<T> {
 impl<T> AtomicPtrFetchOps<T> for AtomicPtr<T> {
     #[inline]
     fn fetch_or(&self, value: usize, ordering: Ordering) -> *mut T {
-        #[cfg(not(miri))]
+        #[cfg(not(any(miri, papaya_loom)))]
         {
             use std::sync::atomic::AtomicUsize;
 
@@ -107,8 +97,8 @@ impl<T> AtomicPtrFetchOps<T> for AtomicPtr<T> {
                 .fetch_or(value, ordering) as *mut T
         }
 
-        // Avoid ptr2int under Miri.
-        #[cfg(miri)]
+        // Avoid ptr2int under Miri, and type punning of `loom` atomics.
+        #[cfg(any(miri, papaya_loom))]
         {
             // Returns the ordering for the read in an RMW operation.
             const fn read_ordering(ordering: Ordering) -> Ordering {
@@ -130,7 +120,7 @@ impl<T> AtomicPtrFetchOps<T> for AtomicPtr<T> {
 impl<T> AtomicPtrFetchOps<T> for Atomic<T> {
     #[inline]
     fn fetch_or(&self, value: usize, ordering: Ordering) -> *mut T {
-        #[cfg(not(miri))]
+        #[cfg(not(any(miri, papaya_loom)))]
         {
             use std::sync::atomic::AtomicUsize;
 
@@ -141,8 +131,8 @@ impl<T> AtomicPtrFetchOps<T> for Atomic<T> {
                 .fetch_or(value, ordering) as *mut T
         }
 
-        // Avoid ptr2int under Miri.
-        #[cfg(miri)]
+        // Avoid ptr2int under Miri, and type punning of `loom` atomics.
+        #[cfg(any(miri, papaya_loom))]
         {
             // Returns the ordering for the read in an RMW operation.
             const fn read_ordering(ordering: Ordering) -> Ordering {
@@ -153,10 +143,11 @@ impl<T> AtomicPtrFetchOps<T> for Atomic<T> {
                 }
             }
 
-            self.fetch_update(ordering, read_ordering(ordering), |ptr| {
-                Some(ptr.map_addr(|addr| addr | value))
-            })
-            .unwrap()
+            self.0
+                .fetch_update(ordering, read_ordering(ordering), |ptr| {
+                    Some(ptr.map_addr(|addr| addr | value))
+                })
+                .unwrap()
         }
     }
 }
@@ -1,6 +1,6 @@
+use crate::sync::atomic::{AtomicPtr, Ordering};
 use std::gc::Gc;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicPtr, Ordering};
 use std::{fmt, ptr};
 
 pub(crate) struct Atomic<T>(pub(crate) AtomicPtr<T>);
@@ -1,12 +1,13 @@
 use crate::raw::{self, InsertResult};
-use crate::Equivalent;
+use crate::{Equivalent, MapObserver};
 use seize::{Collector, LocalGuard, OwnedGuard};
 
-use crate::map::ResizeMode;
+use crate::map::{CounterMode, ResizeMode, Stats};
 use std::collections::hash_map::RandomState;
 use std::fmt;
 use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// A concurrent hash set.
 ///
@@ -49,6 +50,8 @@ pub struct HashSetBuilder<K, S = RandomState> {
     capacity: usize,
     collector: Collector,
     resize_mode: ResizeMode,
+    counter_mode: CounterMode,
+    observer: Option<Arc<dyn MapObserver>>,
     _kv: PhantomData<K>,
 }
 
@@ -68,6 +71,8 @@ impl<K> HashSetBuilder<K> {
             capacity: self.capacity,
             collector: self.collector,
             resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
             _kv: PhantomData,
         }
     }
@@ -85,6 +90,8 @@ impl<K, S> HashSetBuilder<K, S> {
             hasher: self.hasher,
             collector: self.collector,
             resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
             _kv: PhantomData,
         }
     }
@@ -96,6 +103,34 @@ impl<K, S> HashSetBuilder<K, S> {
             hasher: self.hasher,
             capacity: self.capacity,
             collector: self.collector,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set the length counter mode of the set. See [`CounterMode`] for details.
+    pub fn counter_mode(self, counter_mode: CounterMode) -> Self {
+        HashSetBuilder {
+            counter_mode,
+            hasher: self.hasher,
+            capacity: self.capacity,
+            collector: self.collector,
+            resize_mode: self.resize_mode,
+            observer: self.observer,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set an observer for internal events of the set. See [`MapObserver`] for details.
+    pub fn observer(self, observer: Arc<dyn MapObserver>) -> Self {
+        HashSetBuilder {
+            observer: Some(observer),
+            hasher: self.hasher,
+            capacity: self.capacity,
+            collector: self.collector,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
             _kv: PhantomData,
         }
     }
@@ -112,6 +147,8 @@ impl<K, S> HashSetBuilder<K, S> {
             hasher: self.hasher,
             capacity: self.capacity,
             resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            observer: self.observer,
             _kv: PhantomData,
         }
     }
@@ -119,7 +156,15 @@ impl<K, S> HashSetBuilder<K, S> {
     /// Construct a [`HashSet`] from the builder, using the configured options.
     pub fn build(self) -> HashSet<K, S> {
         HashSet {
-            raw: raw::HashMap::new(self.capacity, self.hasher, self.resize_mode),
+            raw: raw::HashMap::new(
+                self.capacity,
+                self.hasher,
+                self.resize_mode,
+                self.counter_mode,
+                self.observer,
+                None,
+                false,
+            ),
         }
     }
 }
@@ -130,6 +175,8 @@ impl<K, S> fmt::Debug for HashSetBuilder<K, S> {
             .field("capacity", &self.capacity)
             .field("collector", &self.collector)
             .field("resize_mode", &self.resize_mode)
+            .field("counter_mode", &self.counter_mode)
+            .field("observer", &self.observer.is_some())
             .finish()
     }
 }
@@ -176,6 +223,8 @@ impl<K> HashSet<K> {
             hasher: RandomState::default(),
             collector: Collector::new(),
             resize_mode: ResizeMode::default(),
+            counter_mode: CounterMode::default(),
+            observer: None,
             _kv: PhantomData,
         }
     }
@@ -243,7 +292,15 @@ impl<K, S> HashSet<K, S> {
     /// ```
     pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> HashSet<K, S> {
         HashSet {
-            raw: raw::HashMap::new(capacity, hash_builder, ResizeMode::default()),
+            raw: raw::HashMap::new(
+                capacity,
+                hash_builder,
+                ResizeMode::default(),
+                CounterMode::default(),
+                None,
+                None,
+                false,
+            ),
         }
     }
 
@@ -303,6 +360,10 @@ where
 {
     /// Returns the number of entries in the set.
     ///
+    /// If the set was built with [`CounterMode::Disabled`], this returns an estimate
+    /// based on the occupancy of a sample of slots spread across the table, which may be
+    /// inaccurate for small sets or while the table is being resized.
+    ///
     /// # Examples
     ///
     /// ```
@@ -321,6 +382,9 @@ where
 
     /// Returns `true` if the set is empty. Otherwise returns `false`.
     ///
+    /// Unlike [`len`](HashSet::len), this is exact even if the set was built with
+    /// [`CounterMode::Disabled`], in which case it searches the table for an entry.
+    ///
     /// # Examples
     ///
     /// ```
@@ -333,7 +397,15 @@ where
     /// ```
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.raw.is_empty()
+    }
+
+    /// Returns statistics about the internal state of the set.
+    ///
+    /// See [`HashMap::stats`](crate::HashMap::stats) for details.
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.raw.stats()
     }
 
     /// Returns `true` if the set contains a value for the specified key.
@@ -722,7 +794,7 @@ where
     /// See [`HashSet::is_empty`] for details.
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.set.raw.is_empty()
     }
 
     /// Returns `true` if the set contains a value for the specified key.
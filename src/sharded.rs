@@ -0,0 +1,1164 @@
+use crate::map::{Compute, CounterMode, MoveError, OccupiedError, Operation, ResizeMode};
+use crate::raw::{self, InsertResult};
+use crate::Equivalent;
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+/// A concurrent hash table split into independent shards.
+///
+/// Keys are hashed to one of a fixed number of shards, each of which is a separate hash table
+/// that resizes on its own. This means that a resize only stalls writers to a fraction of the
+/// keyspace, making `ShardedHashMap` better suited to update-heavy workloads than [`HashMap`](crate::HashMap).
+/// Operations on a single key have the same semantics as they do on a [`HashMap`](crate::HashMap),
+/// while aggregate operations such as [`len`](ShardedHashMap::len), [`iter`](ShardedHashMap::iter),
+/// [`clear`](ShardedHashMap::clear), and [`retain`](ShardedHashMap::retain) span every shard.
+///
+/// Note that aggregate operations visit shards one at a time, and so are not atomic across shards.
+/// See the [crate-level documentation](crate#consistency) for details.
+pub struct ShardedHashMap<K, V, S = RandomState> {
+    /// The shards of the map.
+    shards: Box<[raw::HashMap<K, V, S>]>,
+
+    /// The hasher used to route keys to shards.
+    hasher: S,
+}
+
+// Safety: `ShardedHashMap` is a collection of `raw::HashMap`s, see the implementations
+// for `HashMap` for details.
+unsafe impl<K: Send, V: Send, S: Send> Send for ShardedHashMap<K, V, S> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Sync> Sync for ShardedHashMap<K, V, S> {}
+
+/// A builder for a [`ShardedHashMap`].
+///
+/// # Examples
+///
+/// ```rust
+/// use papaya_alloy::{ResizeMode, ShardedHashMap};
+/// use std::collections::hash_map::RandomState;
+///
+/// let map: ShardedHashMap<i32, i32> = ShardedHashMap::builder()
+///     // Set the number of shards.
+///     .shards(16)
+///     // Set the initial capacity.
+///     .capacity(2048)
+///     // Set the hasher.
+///     .hasher(RandomState::new())
+///     // Set the resize mode of each shard.
+///     .resize_mode(ResizeMode::Blocking)
+///     // Construct the hash map.
+///     .build();
+/// ```
+pub struct ShardedHashMapBuilder<K, V, S = RandomState> {
+    hasher: S,
+    shards: usize,
+    capacity: usize,
+    resize_mode: ResizeMode,
+    counter_mode: CounterMode,
+    _kv: PhantomData<(K, V)>,
+}
+
+impl<K, V> ShardedHashMapBuilder<K, V> {
+    /// Set the hash builder used to hash keys.
+    ///
+    /// Warning: `hash_builder` is normally randomly generated, and is designed
+    /// to allow HashMaps to be resistant to attacks that cause many collisions
+    /// and very poor performance. Setting it manually using this function can
+    /// expose a DoS attack vector.
+    ///
+    /// The `hash_builder` passed should implement the [`BuildHasher`] trait for
+    /// the HashMap to be useful, see its documentation for details.
+    pub fn hasher<S>(self, hasher: S) -> ShardedHashMapBuilder<K, V, S> {
+        ShardedHashMapBuilder {
+            hasher,
+            shards: self.shards,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            _kv: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> ShardedHashMapBuilder<K, V, S> {
+    /// Set the number of shards.
+    ///
+    /// The number of shards is rounded up to the next power of two. By default, the map
+    /// uses four shards per available CPU.
+    pub fn shards(self, shards: usize) -> ShardedHashMapBuilder<K, V, S> {
+        ShardedHashMapBuilder {
+            shards,
+            hasher: self.hasher,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set the initial capacity of the map.
+    ///
+    /// The capacity is split evenly between shards. Note that the map may prematurely
+    /// resize if keys are not evenly distributed across shards. If `capacity` is 0, the
+    /// hash map will not allocate.
+    pub fn capacity(self, capacity: usize) -> ShardedHashMapBuilder<K, V, S> {
+        ShardedHashMapBuilder {
+            capacity,
+            hasher: self.hasher,
+            shards: self.shards,
+            resize_mode: self.resize_mode,
+            counter_mode: self.counter_mode,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set the resizing mode of each shard. See [`ResizeMode`] for details.
+    pub fn resize_mode(self, resize_mode: ResizeMode) -> Self {
+        ShardedHashMapBuilder {
+            resize_mode,
+            hasher: self.hasher,
+            shards: self.shards,
+            capacity: self.capacity,
+            counter_mode: self.counter_mode,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Set the length counter mode of each shard. See [`CounterMode`] for details.
+    ///
+    /// Defaults to [`CounterMode::Exact`], as writers are already spread across the shards'
+    /// counters. Sharding the counter of every shard would instead allocate a number of slots
+    /// quadratic in the number of CPUs.
+    pub fn counter_mode(self, counter_mode: CounterMode) -> Self {
+        ShardedHashMapBuilder {
+            counter_mode,
+            hasher: self.hasher,
+            shards: self.shards,
+            capacity: self.capacity,
+            resize_mode: self.resize_mode,
+            _kv: PhantomData,
+        }
+    }
+
+    /// Construct a [`ShardedHashMap`] from the builder, using the configured options.
+    pub fn build(self) -> ShardedHashMap<K, V, S>
+    where
+        S: Clone,
+    {
+        ShardedHashMap::from_options(
+            self.shards,
+            self.capacity,
+            self.hasher,
+            self.resize_mode,
+            self.counter_mode,
+        )
+    }
+}
+
+impl<K, V, S> fmt::Debug for ShardedHashMapBuilder<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedHashMapBuilder")
+            .field("shards", &self.shards)
+            .field("capacity", &self.capacity)
+            .field("resize_mode", &self.resize_mode)
+            .field("counter_mode", &self.counter_mode)
+            .finish()
+    }
+}
+
+/// Returns the default number of shards.
+fn default_shards() -> usize {
+    let num_cpus = std::thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1);
+
+    num_cpus * 4
+}
+
+impl<K, V> ShardedHashMap<K, V> {
+    /// Creates an empty `ShardedHashMap`.
+    ///
+    /// The hash map is initially created with a capacity of 0, so it will not allocate
+    /// until it is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::ShardedHashMap;
+    /// let map: ShardedHashMap<&str, i32> = ShardedHashMap::new();
+    /// ```
+    pub fn new() -> ShardedHashMap<K, V> {
+        ShardedHashMap::with_capacity_and_hasher(0, RandomState::new())
+    }
+
+    /// Creates an empty `ShardedHashMap` with the specified capacity.
+    ///
+    /// The capacity is split evenly between shards. See [`ShardedHashMapBuilder::capacity`]
+    /// for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::ShardedHashMap;
+    /// let map: ShardedHashMap<&str, i32> = ShardedHashMap::with_capacity(10);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> ShardedHashMap<K, V> {
+        ShardedHashMap::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+
+    /// Returns a builder for a `ShardedHashMap`.
+    ///
+    /// The builder can be used for more complex configuration, such as the number
+    /// of shards, or the [`ResizeMode`] of each shard.
+    pub fn builder() -> ShardedHashMapBuilder<K, V> {
+        ShardedHashMapBuilder {
+            shards: default_shards(),
+            capacity: 0,
+            hasher: RandomState::default(),
+            resize_mode: ResizeMode::default(),
+            counter_mode: CounterMode::Exact,
+            _kv: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> Default for ShardedHashMap<K, V, S>
+where
+    S: Default + Clone,
+{
+    fn default() -> Self {
+        ShardedHashMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> ShardedHashMap<K, V, S>
+where
+    S: Clone,
+{
+    /// Creates an empty `ShardedHashMap` which will use the given hash builder to hash
+    /// keys.
+    ///
+    /// Warning: `hash_builder` is normally randomly generated, and is designed
+    /// to allow HashMaps to be resistant to attacks that cause many collisions
+    /// and very poor performance. Setting it manually using this function can
+    /// expose a DoS attack vector.
+    ///
+    /// The `hash_builder` passed should implement the [`BuildHasher`] trait for
+    /// the HashMap to be useful, see its documentation for details.
+    pub fn with_hasher(hash_builder: S) -> ShardedHashMap<K, V, S> {
+        ShardedHashMap::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates an empty `ShardedHashMap` with at least the specified capacity, using
+    /// `hash_builder` to hash the keys.
+    ///
+    /// The capacity is split evenly between shards. See [`ShardedHashMapBuilder::capacity`]
+    /// for details.
+    ///
+    /// Warning: `hash_builder` is normally randomly generated, and is designed
+    /// to allow HashMaps to be resistant to attacks that cause many collisions
+    /// and very poor performance. Setting it manually using this function can
+    /// expose a DoS attack vector.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> ShardedHashMap<K, V, S> {
+        ShardedHashMap::from_options(
+            default_shards(),
+            capacity,
+            hash_builder,
+            ResizeMode::default(),
+            CounterMode::Exact,
+        )
+    }
+
+    /// Creates a new map with the given options.
+    fn from_options(
+        shards: usize,
+        capacity: usize,
+        hasher: S,
+        resize_mode: ResizeMode,
+        counter_mode: CounterMode,
+    ) -> ShardedHashMap<K, V, S> {
+        // Round up to the next power-of-two for fast modulo.
+        let shards = shards.max(1).next_power_of_two();
+        let capacity = capacity.div_ceil(shards);
+
+        let shards = (0..shards)
+            .map(|_| {
+                raw::HashMap::new(
+                    capacity,
+                    hasher.clone(),
+                    resize_mode,
+                    counter_mode,
+                    None,
+                    None,
+                    false,
+                )
+            })
+            .collect();
+
+        ShardedHashMap { shards, hasher }
+    }
+}
+
+impl<K, V, S> ShardedHashMap<K, V, S> {
+    /// Returns a pinned reference to the map.
+    ///
+    /// The returned reference manages a guard internally, preventing garbage collection
+    /// for as long as it is held. See the [crate-level documentation](crate#usage) for details.
+    #[inline]
+    pub fn pin(&self) -> ShardedHashMapRef<'_, K, V, S> {
+        ShardedHashMapRef { map: self }
+    }
+
+    /// Returns the number of shards in the map.
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+impl<K, V, S> ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the shard that the given key belongs to.
+    #[inline]
+    fn shard<Q>(&self, key: &Q) -> &raw::HashMap<K, V, S>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+
+        // Shards use the low bits of the hash to index their table, and the top 7 bits for
+        // entry metadata. Multiplying by a large odd constant and using the high half mixes
+        // the hash into bits that are independent of both.
+        let mixed = (hash.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize;
+        &self.shards[mixed & (self.shards.len() - 1)]
+    }
+
+    /// Returns the number of entries in the map.
+    ///
+    /// This sums the length of every shard, and so may not reflect a single point in time
+    /// if the map is being concurrently modified.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    /// Returns `true` if the map is empty. Otherwise returns `false`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    ///
+    /// See [`HashMap::contains_key`](crate::HashMap::contains_key) for details.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// See [`HashMap::get`](crate::HashMap::get) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::ShardedHashMap;
+    ///
+    /// let map = ShardedHashMap::new();
+    /// map.pin().insert(1, "a");
+    /// assert_eq!(map.pin().get(&1), Some(&"a"));
+    /// assert_eq!(map.pin().get(&2), None);
+    /// ```
+    #[inline]
+    pub fn get<'g, Q>(&self, key: &Q) -> Option<&'g V>
+    where
+        K: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        match self.shard(key).get(key) {
+            Some((_, v)) => Some(v),
+            None => None,
+        }
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    ///
+    /// See [`HashMap::get_key_value`](crate::HashMap::get_key_value) for details.
+    #[inline]
+    pub fn get_key_value<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.shard(key).get(key)
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// See [`HashMap::insert`](crate::HashMap::insert) for details.
+    #[inline]
+    pub fn insert<'g>(&self, key: K, value: V) -> Option<&'g V> {
+        match self.shard(&key).insert(key, value, true) {
+            InsertResult::Inserted(_) => None,
+            InsertResult::Replaced(value) => Some(value),
+            InsertResult::Error { .. } => unreachable!(),
+        }
+    }
+
+    /// Tries to insert a key-value pair into the map, and returns
+    /// a reference to the value that was inserted.
+    ///
+    /// See [`HashMap::try_insert`](crate::HashMap::try_insert) for details.
+    #[inline]
+    pub fn try_insert<'g>(&self, key: K, value: V) -> Result<&'g V, OccupiedError<'g, V>> {
+        match self.shard(&key).insert(key, value, false) {
+            InsertResult::Inserted(value) => Ok(value),
+            InsertResult::Error {
+                current,
+                not_inserted,
+            } => Err(OccupiedError {
+                current,
+                not_inserted,
+            }),
+            InsertResult::Replaced(_) => unreachable!(),
+        }
+    }
+
+    /// Tries to insert a key and value computed from a closure into the map,
+    /// and returns a reference to the value that was inserted.
+    ///
+    /// See [`HashMap::try_insert_with`](crate::HashMap::try_insert_with) for details.
+    #[inline]
+    pub fn try_insert_with<'g, F>(&self, key: K, f: F) -> Result<&'g V, &'g V>
+    where
+        F: FnOnce() -> V,
+        K: 'g,
+    {
+        self.shard(&key).try_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value.
+    ///
+    /// See [`HashMap::get_or_insert`](crate::HashMap::get_or_insert) for details.
+    #[inline]
+    pub fn get_or_insert<'g>(&self, key: K, value: V) -> &'g V {
+        match self.try_insert(key, value) {
+            Ok(inserted) => inserted,
+            Err(OccupiedError { current, .. }) => current,
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a closure.
+    ///
+    /// See [`HashMap::get_or_insert_with`](crate::HashMap::get_or_insert_with) for details.
+    #[inline]
+    pub fn get_or_insert_with<'g, F>(&self, key: K, f: F) -> &'g V
+    where
+        F: FnOnce() -> V,
+        K: 'g,
+    {
+        self.shard(&key).get_or_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a fallible closure.
+    ///
+    /// See [`HashMap::get_or_try_insert_with`](crate::HashMap::get_or_try_insert_with) for
+    /// details.
+    #[inline]
+    pub fn get_or_try_insert_with<'g, F, E>(&self, key: K, f: F) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        K: 'g,
+    {
+        self.shard(&key).get_or_try_insert_with(key, f)
+    }
+
+    /// Updates an existing entry atomically.
+    ///
+    /// See [`HashMap::update`](crate::HashMap::update) for details.
+    #[inline]
+    pub fn update<'g, F>(&self, key: K, update: F) -> Option<&'g V>
+    where
+        F: Fn(&V) -> V,
+        K: 'g,
+    {
+        self.shard(&key).update(key, update)
+    }
+
+    /// Updates an existing entry atomically with a fallible update function.
+    ///
+    /// See [`HashMap::try_update`](crate::HashMap::try_update) for details.
+    #[inline]
+    pub fn try_update<'g, F, E>(&self, key: K, update: F) -> Result<Option<&'g V>, E>
+    where
+        F: Fn(&V) -> Result<V, E>,
+        K: 'g,
+    {
+        self.shard(&key).try_update(key, update)
+    }
+
+    /// Updates an existing entry or inserts a default value.
+    ///
+    /// See [`HashMap::update_or_insert`](crate::HashMap::update_or_insert) for details.
+    #[inline]
+    pub fn update_or_insert<'g, F>(&self, key: K, update: F, value: V) -> &'g V
+    where
+        F: Fn(&V) -> V,
+        K: 'g,
+    {
+        self.update_or_insert_with(key, update, || value)
+    }
+
+    /// Updates an existing entry or inserts a default value computed from a closure.
+    ///
+    /// See [`HashMap::update_or_insert_with`](crate::HashMap::update_or_insert_with) for details.
+    #[inline]
+    pub fn update_or_insert_with<'g, U, F>(&self, key: K, update: U, f: F) -> &'g V
+    where
+        F: FnOnce() -> V,
+        U: Fn(&V) -> V,
+        K: 'g,
+    {
+        self.shard(&key).update_or_insert_with(key, update, f)
+    }
+
+    /// Updates an existing entry or inserts a default value computed from a closure, where
+    /// either closure may fail.
+    ///
+    /// See [`HashMap::try_update_or_insert_with`](crate::HashMap::try_update_or_insert_with)
+    /// for details.
+    #[inline]
+    pub fn try_update_or_insert_with<'g, U, F, E>(
+        &self,
+        key: K,
+        update: U,
+        f: F,
+    ) -> Result<&'g V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        U: Fn(&V) -> Result<V, E>,
+        K: 'g,
+    {
+        self.shard(&key).try_update_or_insert_with(key, update, f)
+    }
+
+    /// Updates an entry with a compare-and-swap (CAS) function.
+    ///
+    /// See [`HashMap::compute`](crate::HashMap::compute) for details.
+    #[inline]
+    pub fn compute<'g, F, T>(&self, key: K, compute: F) -> Compute<'g, K, V, T>
+    where
+        F: FnMut(Option<(&'g K, &'g V)>) -> Operation<V, T>,
+    {
+        self.shard(&key).compute(key, compute)
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    ///
+    /// See [`HashMap::remove`](crate::HashMap::remove) for details.
+    #[inline]
+    pub fn remove<'g, Q>(&self, key: &Q) -> Option<&'g V>
+    where
+        K: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        match self.shard(key).remove(key) {
+            Some((_, value)) => Some(value),
+            None => None,
+        }
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    ///
+    /// See [`HashMap::remove_entry`](crate::HashMap::remove_entry) for details.
+    #[inline]
+    pub fn remove_entry<'g, Q>(&self, key: &Q) -> Option<(&'g K, &'g V)>
+    where
+        K: 'g,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.shard(key).remove(key)
+    }
+
+    /// Conditionally removes a key from the map based on the provided closure.
+    ///
+    /// See [`HashMap::remove_if`](crate::HashMap::remove_if) for details.
+    #[inline]
+    pub fn remove_if<'g, Q, F>(
+        &self,
+        key: &Q,
+        should_remove: F,
+    ) -> Result<Option<(&'g K, &'g V)>, (&'g K, &'g V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.shard(key).remove_if(key, should_remove)
+    }
+
+    /// Atomically moves the entry at a key to a new key, returning a reference to the value
+    /// at the new key.
+    ///
+    /// The move is atomic even if the keys belong to different shards.
+    ///
+    /// See [`HashMap::rename`](crate::HashMap::rename) for details.
+    #[inline]
+    pub fn rename<'g, Q>(&self, key: &Q, new_key: K) -> Result<&'g V, MoveError<'g, V>>
+    where
+        K: Clone + 'g,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        let target = self.shard(&new_key);
+        self.shard(key).move_entry(key, target, Some(new_key))
+    }
+
+    /// Tries to reserve capacity for `additional` more elements to be inserted
+    /// in the map.
+    ///
+    /// The additional capacity is split evenly between shards.
+    #[inline]
+    pub fn reserve(&self, additional: usize) {
+        let additional = (additional + self.shards.len() - 1) / self.shards.len();
+
+        for shard in self.shards.iter() {
+            shard.reserve(additional);
+        }
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    ///
+    /// Shards are cleared one at a time. Note that this method will block until any
+    /// in-progress resizes are completed before proceeding. See the
+    /// [consistency](crate#consistency) section for details.
+    #[inline]
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.clear();
+        }
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// Shards are visited one at a time. See [`HashMap::retain`](crate::HashMap::retain)
+    /// for details.
+    #[inline]
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        for shard in self.shards.iter() {
+            shard.retain(&mut f);
+        }
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    /// The iterator element type is `(&K, &V)`.
+    ///
+    /// Shards are visited one at a time. Note that this method will block until any
+    /// in-progress resizes are completed before proceeding. See the
+    /// [consistency](crate#consistency) section for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use papaya_alloy::ShardedHashMap;
+    ///
+    /// let map = ShardedHashMap::new();
+    /// map.pin().insert("a", 1);
+    /// map.pin().insert("b", 2);
+    ///
+    /// for (key, val) in map.pin().iter() {
+    ///     println!("key: {key} val: {val}");
+    /// }
+    /// ```
+    #[inline]
+    pub fn iter<'g>(&self) -> Iter<'g, K, V> {
+        Iter {
+            shards: self.shards.iter().map(|shard| shard.iter()).collect(),
+            current: 0,
+        }
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    /// The iterator element type is `&K`.
+    #[inline]
+    pub fn keys<'g>(&self) -> Keys<'g, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&V`.
+    #[inline]
+    pub fn values<'g>(&self) -> Values<'g, K, V> {
+        Values { iter: self.iter() }
+    }
+}
+
+impl<K, V, S> fmt::Debug for ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for ShardedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default + Clone,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+
+        let map = ShardedHashMap::with_capacity_and_hasher(lower, S::default());
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+/// A pinned reference to a [`ShardedHashMap`].
+///
+/// This type is created with [`ShardedHashMap::pin`] and can be used to easily access a
+/// [`ShardedHashMap`] without explicitly managing a guard. See the
+/// [crate-level documentation](crate#usage) for details.
+pub struct ShardedHashMapRef<'map, K, V, S> {
+    map: &'map ShardedHashMap<K, V, S>,
+}
+
+impl<'map, K, V, S> ShardedHashMapRef<'map, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns a reference to the inner [`ShardedHashMap`].
+    #[inline]
+    pub fn map(&self) -> &'map ShardedHashMap<K, V, S> {
+        self.map
+    }
+
+    /// Returns the number of entries in the map.
+    ///
+    /// See [`ShardedHashMap::len`] for details.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the map is empty. Otherwise returns `false`.
+    ///
+    /// See [`ShardedHashMap::is_empty`] for details.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    ///
+    /// See [`ShardedHashMap::contains_key`] for details.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// See [`ShardedHashMap::get`] for details.
+    #[inline]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    ///
+    /// See [`ShardedHashMap::get_key_value`] for details.
+    #[inline]
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.get_key_value(key)
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// See [`ShardedHashMap::insert`] for details.
+    #[inline]
+    pub fn insert(&self, key: K, value: V) -> Option<&V> {
+        self.map.insert(key, value)
+    }
+
+    /// Tries to insert a key-value pair into the map, and returns
+    /// a reference to the value that was inserted.
+    ///
+    /// See [`ShardedHashMap::try_insert`] for details.
+    #[inline]
+    pub fn try_insert(&self, key: K, value: V) -> Result<&V, OccupiedError<'_, V>> {
+        self.map.try_insert(key, value)
+    }
+
+    /// Tries to insert a key and value computed from a closure into the map,
+    /// and returns a reference to the value that was inserted.
+    ///
+    /// See [`ShardedHashMap::try_insert_with`] for details.
+    #[inline]
+    pub fn try_insert_with<F>(&self, key: K, f: F) -> Result<&V, &V>
+    where
+        F: FnOnce() -> V,
+    {
+        self.map.try_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value.
+    ///
+    /// See [`ShardedHashMap::get_or_insert`] for details.
+    #[inline]
+    pub fn get_or_insert(&self, key: K, value: V) -> &V {
+        self.map.get_or_insert(key, value)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a closure.
+    ///
+    /// See [`ShardedHashMap::get_or_insert_with`] for details.
+    #[inline]
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        self.map.get_or_insert_with(key, f)
+    }
+
+    /// Returns a reference to the value corresponding to the key, or inserts a default value
+    /// computed from a fallible closure.
+    ///
+    /// See [`ShardedHashMap::get_or_try_insert_with`] for details.
+    #[inline]
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.map.get_or_try_insert_with(key, f)
+    }
+
+    /// Updates an existing entry atomically.
+    ///
+    /// See [`ShardedHashMap::update`] for details.
+    #[inline]
+    pub fn update<F>(&self, key: K, update: F) -> Option<&V>
+    where
+        F: Fn(&V) -> V,
+    {
+        self.map.update(key, update)
+    }
+
+    /// Updates an existing entry atomically with a fallible update function.
+    ///
+    /// See [`ShardedHashMap::try_update`] for details.
+    #[inline]
+    pub fn try_update<F, E>(&self, key: K, update: F) -> Result<Option<&V>, E>
+    where
+        F: Fn(&V) -> Result<V, E>,
+    {
+        self.map.try_update(key, update)
+    }
+
+    /// Updates an existing entry or inserts a default value.
+    ///
+    /// See [`ShardedHashMap::update_or_insert`] for details.
+    #[inline]
+    pub fn update_or_insert<F>(&self, key: K, update: F, value: V) -> &V
+    where
+        F: Fn(&V) -> V,
+    {
+        self.map.update_or_insert(key, update, value)
+    }
+
+    /// Updates an existing entry or inserts a default value computed from a closure.
+    ///
+    /// See [`ShardedHashMap::update_or_insert_with`] for details.
+    #[inline]
+    pub fn update_or_insert_with<U, F>(&self, key: K, update: U, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+        U: Fn(&V) -> V,
+    {
+        self.map.update_or_insert_with(key, update, f)
+    }
+
+    /// Updates an existing entry or inserts a default value computed from a closure, where
+    /// either closure may fail.
+    ///
+    /// See [`ShardedHashMap::try_update_or_insert_with`] for details.
+    #[inline]
+    pub fn try_update_or_insert_with<U, F, E>(&self, key: K, update: U, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        U: Fn(&V) -> Result<V, E>,
+    {
+        self.map.try_update_or_insert_with(key, update, f)
+    }
+
+    /// Updates an entry with a compare-and-swap (CAS) function.
+    ///
+    /// See [`ShardedHashMap::compute`] for details.
+    #[inline]
+    pub fn compute<'g, F, T>(&'g self, key: K, compute: F) -> Compute<'g, K, V, T>
+    where
+        F: FnMut(Option<(&'g K, &'g V)>) -> Operation<V, T>,
+    {
+        self.map.compute(key, compute)
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    ///
+    /// See [`ShardedHashMap::remove`] for details.
+    #[inline]
+    pub fn remove<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.remove(key)
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    ///
+    /// See [`ShardedHashMap::remove_entry`] for details.
+    #[inline]
+    pub fn remove_entry<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.remove_entry(key)
+    }
+
+    /// Conditionally removes a key from the map based on the provided closure.
+    ///
+    /// See [`ShardedHashMap::remove_if`] for details.
+    #[inline]
+    pub fn remove_if<Q, F>(&self, key: &Q, should_remove: F) -> Result<Option<(&K, &V)>, (&K, &V)>
+    where
+        Q: Equivalent<K> + Hash + ?Sized,
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.map.remove_if(key, should_remove)
+    }
+
+    /// Atomically moves the entry at a key to a new key, returning a reference to the value
+    /// at the new key.
+    ///
+    /// See [`ShardedHashMap::rename`] for details.
+    #[inline]
+    pub fn rename<Q>(&self, key: &Q, new_key: K) -> Result<&V, MoveError<'_, V>>
+    where
+        K: Clone,
+        V: Clone,
+        Q: Equivalent<K> + Hash + ?Sized,
+    {
+        self.map.rename(key, new_key)
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    ///
+    /// See [`ShardedHashMap::clear`] for details.
+    #[inline]
+    pub fn clear(&self) {
+        self.map.clear()
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// See [`ShardedHashMap::retain`] for details.
+    #[inline]
+    pub fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.map.retain(f)
+    }
+
+    /// Tries to reserve capacity for `additional` more elements to be inserted
+    /// in the map.
+    ///
+    /// See [`ShardedHashMap::reserve`] for details.
+    #[inline]
+    pub fn reserve(&self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    /// The iterator element type is `(&K, &V)`.
+    ///
+    /// See [`ShardedHashMap::iter`] for details.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    /// The iterator element type is `&K`.
+    ///
+    /// See [`ShardedHashMap::keys`] for details.
+    #[inline]
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&V`.
+    ///
+    /// See [`ShardedHashMap::values`] for details.
+    #[inline]
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+}
+
+impl<K, V, S> fmt::Debug for ShardedHashMapRef<'_, K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a ShardedHashMapRef<'_, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over a sharded map's entries.
+///
+/// This struct is created by the [`iter`](ShardedHashMap::iter) method on [`ShardedHashMap`].
+/// See its documentation for details.
+pub struct Iter<'g, K, V> {
+    shards: Vec<raw::Iter<'g, K, V>>,
+    current: usize,
+}
+
+impl<'g, K: 'g, V: 'g> Iterator for Iter<'g, K, V> {
+    type Item = (&'g K, &'g V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let shard = self.shards.get_mut(self.current)?;
+
+            if let Some(entry) = shard.next() {
+                return Some(entry);
+            }
+
+            // Continue to the next shard.
+            self.current += 1;
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Iter<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(Iter {
+                shards: self.shards.clone(),
+                current: self.current,
+            })
+            .finish()
+    }
+}
+
+/// An iterator over a sharded map's keys.
+///
+/// This struct is created by the [`keys`](ShardedHashMap::keys) method on [`ShardedHashMap`].
+/// See its documentation for details.
+pub struct Keys<'g, K, V> {
+    iter: Iter<'g, K, V>,
+}
+
+impl<'g, K: 'g, V: 'g> Iterator for Keys<'g, K, V> {
+    type Item = &'g K;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, _) = self.iter.next()?;
+        Some(key)
+    }
+}
+
+impl<K, V> fmt::Debug for Keys<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Keys").field(&self.iter).finish()
+    }
+}
+
+/// An iterator over a sharded map's values.
+///
+/// This struct is created by the [`values`](ShardedHashMap::values) method on [`ShardedHashMap`].
+/// See its documentation for details.
+pub struct Values<'g, K, V> {
+    iter: Iter<'g, K, V>,
+}
+
+impl<'g, K: 'g, V: 'g> Iterator for Values<'g, K, V> {
+    type Item = &'g V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, value) = self.iter.next()?;
+        Some(value)
+    }
+}
+
+impl<K, V> fmt::Debug for Values<'_, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Values").field(&self.iter).finish()
+    }
+}
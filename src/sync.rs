@@ -0,0 +1,63 @@
+// Synchronization primitives used by the map.
+//
+// Under `cfg(papaya_loom)`, these are replaced by their `loom` equivalents so that
+// the concurrent protocols of the map, such as resizing, can be model-checked.
+
+#[cfg(not(papaya_loom))]
+pub use std::{
+    hint,
+    sync::{atomic, Condvar, Mutex},
+    thread,
+};
+
+#[cfg(papaya_loom)]
+pub use loom::{
+    hint,
+    sync::{atomic, Condvar, Mutex},
+    thread,
+};
+
+use atomic::{AtomicPtr, AtomicU8, AtomicUsize};
+
+// Access to the value of an atomic through a mutable reference.
+//
+// The `loom` atomics do not provide `get_mut`, as accesses must be tracked by the model.
+pub trait AtomicMut<T> {
+    // Returns the current value.
+    fn load_mut(&mut self) -> T;
+
+    // Overwrites the current value.
+    fn store_mut(&mut self, value: T);
+}
+
+macro_rules! impl_atomic_mut {
+    ($(impl$(<$param:ident>)? for $atomic:ty => $ty:ty;)*) => {$(
+        impl$(<$param>)? AtomicMut<$ty> for $atomic {
+            #[inline]
+            fn load_mut(&mut self) -> $ty {
+                #[cfg(not(papaya_loom))]
+                return *self.get_mut();
+
+                #[cfg(papaya_loom)]
+                return self.with_mut(|value| *value);
+            }
+
+            #[inline]
+            fn store_mut(&mut self, value: $ty) {
+                #[cfg(not(papaya_loom))]
+                {
+                    *self.get_mut() = value;
+                }
+
+                #[cfg(papaya_loom)]
+                self.with_mut(|current| *current = value);
+            }
+        }
+    )*};
+}
+
+impl_atomic_mut! {
+    impl<T> for AtomicPtr<T> => *mut T;
+    impl for AtomicU8 => u8;
+    impl for AtomicUsize => usize;
+}
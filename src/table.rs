@@ -0,0 +1,422 @@
+use crate::map::{CounterMode, ResizeMode};
+use crate::raw::{self, KeyHasher, RawInsertResult};
+
+use std::fmt;
+
+/// A concurrent hash table of values with caller-defined keys.
+///
+/// Unlike [`HashMap`](crate::HashMap), a `HashTable` does not require its values to implement
+/// [`Hash`](std::hash::Hash) or [`Eq`]. Instead, the hash of a value is supplied with every
+/// operation, along with a closure to test for equality. This allows storing values keyed by
+/// one of their fields without duplicating the key.
+///
+/// The hasher closure provided on construction is used to rehash values when the table is resized,
+/// and *must* produce the same hash that is provided to the table's methods. Hashes should also be
+/// evenly distributed across all 64 bits, as the top bits of the hash are used for entry metadata.
+///
+/// # Examples
+///
+/// ```
+/// use papaya_alloy::HashTable;
+/// use std::hash::{BuildHasher, RandomState};
+///
+/// struct Record {
+///     id: u64,
+///     name: &'static str,
+/// }
+///
+/// let state = RandomState::new();
+/// let table = HashTable::new({
+///     let state = state.clone();
+///     move |record: &Record| state.hash_one(record.id)
+/// });
+///
+/// let hash = state.hash_one(1_u64);
+/// let table = table.pin();
+/// table.insert_unique(hash, Record { id: 1, name: "a" });
+///
+/// let record = table.find(hash, |record| record.id == 1).unwrap();
+/// assert_eq!(record.name, "a");
+/// ```
+pub struct HashTable<T, H> {
+    raw: raw::HashMap<T, (), HashFn<H>>,
+}
+
+// Safety: We only ever hand out &T through shared references to the table,
+// so normal Send/Sync rules apply. See the implementations for `HashSet` for details.
+unsafe impl<T: Send, H: Send> Send for HashTable<T, H> {}
+unsafe impl<T: Sync, H: Sync> Sync for HashTable<T, H> {}
+
+// Adapts a hasher closure for use by the table engine.
+struct HashFn<H>(H);
+
+impl<T, H> KeyHasher<T> for HashFn<H>
+where
+    H: Fn(&T) -> u64,
+{
+    #[inline]
+    fn hash_key(&self, key: &T) -> u64 {
+        (self.0)(key)
+    }
+}
+
+impl<T, H> HashTable<T, H>
+where
+    H: Fn(&T) -> u64,
+{
+    /// Creates an empty `HashTable` which will use the given closure to hash values
+    /// when resizing.
+    ///
+    /// The table is initially created with a capacity of 0, so it will not allocate
+    /// until it is first inserted into.
+    pub fn new(hasher: H) -> HashTable<T, H> {
+        HashTable::with_capacity(0, hasher)
+    }
+
+    /// Creates an empty `HashTable` with the specified capacity, which will use the given
+    /// closure to hash values when resizing.
+    ///
+    /// The table should be able to hold at least `capacity` elements before resizing.
+    /// However, the capacity is an estimate, and the table may prematurely resize due
+    /// to poor hash distribution. If `capacity` is 0, the table will not allocate.
+    pub fn with_capacity(capacity: usize, hasher: H) -> HashTable<T, H> {
+        HashTable {
+            raw: raw::HashMap::new(
+                capacity,
+                HashFn(hasher),
+                ResizeMode::default(),
+                CounterMode::default(),
+                None,
+                None,
+                false,
+            ),
+        }
+    }
+
+    /// Returns a reference to the table's hasher closure.
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.raw.hasher.0
+    }
+
+    /// Returns a pinned reference to the table.
+    ///
+    /// The returned reference manages a guard internally, preventing garbage collection
+    /// for as long as it is held. See the [crate-level documentation](crate#usage) for details.
+    #[inline]
+    pub fn pin(&self) -> HashTableRef<'_, T, H> {
+        HashTableRef { table: self }
+    }
+
+    /// Returns the number of values in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Returns `true` if the table is empty. Otherwise returns `false`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Returns a reference to the value with the given hash for which `eq` returns `true`.
+    #[inline]
+    pub fn find<'g, F>(&self, hash: u64, eq: F) -> Option<&'g T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        match self.raw.get_with_hash(hash, eq) {
+            Some((value, _)) => Some(value),
+            None => None,
+        }
+    }
+
+    /// Inserts a value with the given hash into the table, without checking whether an
+    /// equal value is already present.
+    ///
+    /// Inserting a value that is equal to one already in the table will result in both
+    /// values being present, and it is unspecified which is returned by [`find`](HashTable::find).
+    #[inline]
+    pub fn insert_unique<'g>(&self, hash: u64, value: T) -> &'g T {
+        match self.raw.insert_entry(hash, value, (), false, |_, _| false) {
+            // Safety: The entry was just inserted and is valid for reads.
+            RawInsertResult::Inserted(entry) => unsafe { &(*entry).key },
+            RawInsertResult::Replaced(_) | RawInsertResult::Error { .. } => unreachable!(),
+        }
+    }
+
+    /// Returns a reference to the value with the given hash for which `eq` returns `true`,
+    /// or inserts a value computed from a closure.
+    ///
+    /// Note that the closure may be called even if the value is not inserted, if an equal
+    /// value is inserted concurrently.
+    #[inline]
+    pub fn find_or_insert_with<'g, E, F>(&self, hash: u64, mut eq: E, f: F) -> &'g T
+    where
+        E: FnMut(&T) -> bool,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = self.find(hash, &mut eq) {
+            return value;
+        }
+
+        match self
+            .raw
+            .insert_entry(hash, f(), (), false, |current, _| eq(current))
+        {
+            // Safety: The entry was just inserted and is valid for reads.
+            RawInsertResult::Inserted(entry) => unsafe { &(*entry).key },
+
+            // Lost to a concurrent insert of an equal value.
+            RawInsertResult::Error {
+                current,
+                not_inserted,
+            } => {
+                // Safety: We allocated this box above and it was not inserted into the table.
+                let _ = unsafe { Box::from_raw(not_inserted) };
+
+                // Safety: The current entry was found in the table and is valid for reads.
+                unsafe { &(*current).key }
+            }
+
+            RawInsertResult::Replaced(_) => unreachable!(),
+        }
+    }
+
+    /// Removes the value with the given hash for which `eq` returns `true`, returning
+    /// the value if it was previously in the table.
+    #[inline]
+    pub fn remove<'g, F>(&self, hash: u64, eq: F) -> Option<&'g T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        match self.raw.remove_with_hash(hash, eq) {
+            Some((value, _)) => Some(value),
+            None => None,
+        }
+    }
+
+    /// Tries to reserve capacity for `additional` more values to be inserted
+    /// in the table.
+    ///
+    /// See [`HashMap::reserve`](crate::HashMap::reserve) for details.
+    #[inline]
+    pub fn reserve(&self, additional: usize) {
+        self.raw.reserve(additional)
+    }
+
+    /// Clears the table, removing all values.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    #[inline]
+    pub fn clear(&self) {
+        self.raw.clear()
+    }
+
+    /// Retains only the values specified by the predicate.
+    ///
+    /// See [`HashMap::retain`](crate::HashMap::retain) for details.
+    #[inline]
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.raw.retain(|value, _| f(value))
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&T`.
+    ///
+    /// Note that this method will block until any in-progress resizes are
+    /// completed before proceeding. See the [consistency](crate#consistency)
+    /// section for details.
+    #[inline]
+    pub fn iter<'g>(&self) -> Iter<'g, T> {
+        Iter {
+            raw: self.raw.iter(),
+        }
+    }
+}
+
+impl<T, H> fmt::Debug for HashTable<T, H>
+where
+    T: fmt::Debug,
+    H: Fn(&T) -> u64,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// A pinned reference to a [`HashTable`].
+///
+/// This type is created with [`HashTable::pin`] and can be used to easily access a [`HashTable`]
+/// without explicitly managing a guard. See the [crate-level documentation](crate#usage) for details.
+pub struct HashTableRef<'table, T, H> {
+    table: &'table HashTable<T, H>,
+}
+
+impl<'table, T, H> HashTableRef<'table, T, H>
+where
+    H: Fn(&T) -> u64,
+{
+    /// Returns a reference to the inner [`HashTable`].
+    #[inline]
+    pub fn table(&self) -> &'table HashTable<T, H> {
+        self.table
+    }
+
+    /// Returns the number of values in the table.
+    ///
+    /// See [`HashTable::len`] for details.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns `true` if the table is empty. Otherwise returns `false`.
+    ///
+    /// See [`HashTable::is_empty`] for details.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Returns a reference to the value with the given hash for which `eq` returns `true`.
+    ///
+    /// See [`HashTable::find`] for details.
+    #[inline]
+    pub fn find<F>(&self, hash: u64, eq: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.table.find(hash, eq)
+    }
+
+    /// Inserts a value with the given hash into the table, without checking whether an
+    /// equal value is already present.
+    ///
+    /// See [`HashTable::insert_unique`] for details.
+    #[inline]
+    pub fn insert_unique(&self, hash: u64, value: T) -> &T {
+        self.table.insert_unique(hash, value)
+    }
+
+    /// Returns a reference to the value with the given hash for which `eq` returns `true`,
+    /// or inserts a value computed from a closure.
+    ///
+    /// See [`HashTable::find_or_insert_with`] for details.
+    #[inline]
+    pub fn find_or_insert_with<E, F>(&self, hash: u64, eq: E, f: F) -> &T
+    where
+        E: FnMut(&T) -> bool,
+        F: FnOnce() -> T,
+    {
+        self.table.find_or_insert_with(hash, eq, f)
+    }
+
+    /// Removes the value with the given hash for which `eq` returns `true`, returning
+    /// the value if it was previously in the table.
+    ///
+    /// See [`HashTable::remove`] for details.
+    #[inline]
+    pub fn remove<F>(&self, hash: u64, eq: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.table.remove(hash, eq)
+    }
+
+    /// Tries to reserve capacity for `additional` more values to be inserted
+    /// in the table.
+    ///
+    /// See [`HashTable::reserve`] for details.
+    #[inline]
+    pub fn reserve(&self, additional: usize) {
+        self.table.reserve(additional)
+    }
+
+    /// Clears the table, removing all values.
+    ///
+    /// See [`HashTable::clear`] for details.
+    #[inline]
+    pub fn clear(&self) {
+        self.table.clear()
+    }
+
+    /// Retains only the values specified by the predicate.
+    ///
+    /// See [`HashTable::retain`] for details.
+    #[inline]
+    pub fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.table.retain(f)
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&T`.
+    ///
+    /// See [`HashTable::iter`] for details.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.table.iter()
+    }
+}
+
+impl<T, H> fmt::Debug for HashTableRef<'_, T, H>
+where
+    T: fmt::Debug,
+    H: Fn(&T) -> u64,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T, H> IntoIterator for &'a HashTableRef<'_, T, H>
+where
+    H: Fn(&T) -> u64,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over a table's values.
+///
+/// This struct is created by the [`iter`](HashTable::iter) method on [`HashTable`].
+/// See its documentation for details.
+pub struct Iter<'g, T> {
+    raw: raw::Iter<'g, T, ()>,
+}
+
+impl<'g, T: 'g> Iterator for Iter<'g, T> {
+    type Item = &'g T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, _) = self.raw.next()?;
+        Some(value)
+    }
+}
+
+impl<T> fmt::Debug for Iter<'_, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(Iter {
+                raw: self.raw.clone(),
+            })
+            .finish()
+    }
+}
@@ -0,0 +1,523 @@
+//! Utilities for testing the consistency of concurrent operations.
+//!
+//! This module provides a [`History`] of operations performed on a [`HashMap`] across threads,
+//! recorded through a [`Recorder`], and a checker that verifies the history is *linearizable*
+//! against a sequential [`std::collections::HashMap`]. Linearizability is the formal version of
+//! the guarantee that reads reflect the most-recent write, see the
+//! [crate-level documentation](crate#consistency) for details.
+//!
+//! Every operation on the map accesses a single key, so the history is checked for each key
+//! independently (P-compositionality), using the Wing–Gong algorithm with memoization of
+//! visited states.
+//!
+//! This module is only available with the `testing` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use papaya_alloy::testing::History;
+//! use papaya_alloy::HashMap;
+//!
+//! let map = HashMap::new();
+//! let history = History::new();
+//!
+//! std::thread::scope(|s| {
+//!     for t in 0..4 {
+//!         let (map, history) = (&map, &history);
+//!         s.spawn(move || {
+//!             let mut recorder = history.recorder(map);
+//!             for i in 0..32 {
+//!                 recorder.insert(i % 4, t);
+//!                 recorder.get(&(i % 4));
+//!                 recorder.update(i % 4, |v| v + 1);
+//!                 recorder.remove(&(i % 4));
+//!             }
+//!         });
+//!     }
+//! });
+//!
+//! history.check().unwrap();
+//! ```
+
+use crate::{Compute, HashMap, Operation};
+
+use std::cell::RefCell;
+use std::collections::HashMap as StdHashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A history of operations performed on a map.
+///
+/// Operations are recorded through a [`Recorder`], or with [`History::push`] for
+/// operations on other map-like types. See the [module-level documentation](self) for details.
+pub struct History<K, V> {
+    // A logical clock used to timestamp invocations and responses.
+    clock: AtomicU64,
+
+    // The number of recorders that have been created.
+    threads: AtomicUsize,
+
+    // The recorded events.
+    events: Mutex<Vec<Event<K, V>>>,
+}
+
+/// A completed operation in a [`History`].
+///
+/// The sequential effect of every operation is described by the value it *observed* for its
+/// key, and the *effect* it had on that key. For example, an `insert` that returned `Some(1)`
+/// observed `Some(1)` and had the effect `Effect::Insert(value)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event<K, V> {
+    /// The recorder that performed the operation.
+    pub thread: usize,
+
+    /// The type of operation.
+    pub kind: Kind,
+
+    /// The key that was accessed.
+    pub key: K,
+
+    /// The value of the key observed by the operation.
+    pub observed: Option<V>,
+
+    /// The effect of the operation on the key.
+    pub effect: Effect<V>,
+
+    /// The timestamp at which the operation was invoked.
+    pub call: u64,
+
+    /// The timestamp at which the operation returned.
+    pub ret: u64,
+}
+
+/// The type of a recorded operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// [`HashMap::get`].
+    Get,
+
+    /// [`HashMap::insert`].
+    Insert,
+
+    /// [`HashMap::remove`].
+    Remove,
+
+    /// [`HashMap::update`].
+    Update,
+
+    /// [`HashMap::compute`].
+    Compute,
+}
+
+/// The effect of a recorded operation on its key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Effect<V> {
+    /// The operation did not modify the key.
+    None,
+
+    /// The given value was inserted.
+    Insert(V),
+
+    /// The key was removed.
+    Remove,
+}
+
+/// A linearizability violation found by [`History::check`].
+///
+/// Contains the key whose operations could not be linearized, and every operation
+/// on that key, ordered by invocation.
+#[derive(Clone, Debug)]
+pub struct Violation<K, V> {
+    /// The key whose history is not linearizable.
+    pub key: K,
+
+    /// The operations performed on the key.
+    pub events: Vec<Event<K, V>>,
+}
+
+impl<K, V> History<K, V> {
+    /// Creates an empty `History`.
+    ///
+    /// The history is checked assuming that the map is initially empty.
+    pub fn new() -> History<K, V> {
+        History {
+            clock: AtomicU64::new(0),
+            threads: AtomicUsize::new(0),
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a [`Recorder`] that records operations on the given map.
+    ///
+    /// A recorder is intended to be used by a single thread. Operations are added to the
+    /// history when the recorder is dropped.
+    pub fn recorder<'a, S>(&'a self, map: &'a HashMap<K, V, S>) -> Recorder<'a, K, V, S> {
+        Recorder {
+            map,
+            history: self,
+            thread: self.threads.fetch_add(1, Ordering::Relaxed),
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns the current timestamp of the history's logical clock.
+    ///
+    /// This should be called immediately before invoking and after returning from an operation
+    /// recorded with [`History::push`].
+    pub fn now(&self) -> u64 {
+        // `SeqCst` ensures timestamps respect the real-time order of operations.
+        self.clock.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Adds a completed operation to the history.
+    pub fn push(&self, event: Event<K, V>) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    /// Returns the number of operations in the history.
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    /// Returns `true` if the history contains no operations.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Default for History<K, V> {
+    fn default() -> History<K, V> {
+        History::new()
+    }
+}
+
+impl<K, V> History<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Hash + Eq + Clone,
+{
+    /// Checks that the history is linearizable.
+    ///
+    /// Returns the operations on the first key that could not be linearized against a
+    /// sequential [`std::collections::HashMap`].
+    pub fn check(&self) -> Result<(), Violation<K, V>> {
+        let events = self.events.lock().unwrap();
+
+        // Partition the history by key, as operations on separate keys are independent.
+        let mut partitions: StdHashMap<&K, Vec<&Event<K, V>>> = StdHashMap::new();
+        for event in events.iter() {
+            partitions.entry(&event.key).or_default().push(event);
+        }
+
+        for (key, mut events) in partitions {
+            events.sort_by_key(|event| event.call);
+
+            if !linearizable(key, &events) {
+                return Err(Violation {
+                    key: key.clone(),
+                    events: events.into_iter().cloned().collect(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V> fmt::Debug for History<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("History").field("len", &self.len()).finish()
+    }
+}
+
+/// Records operations on a [`HashMap`] into a [`History`].
+///
+/// Values are returned by clone, as references cannot outlive the operation
+/// being recorded. See [`History::recorder`] for details.
+pub struct Recorder<'a, K, V, S> {
+    map: &'a HashMap<K, V, S>,
+    history: &'a History<K, V>,
+    thread: usize,
+    events: Vec<Event<K, V>>,
+}
+
+impl<K, V, S> Recorder<'_, K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Records a [`get`](HashMap::get) operation.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.record(Kind::Get, key.clone(), |map| {
+            let value = map.pin().get(key).cloned();
+            (value.clone(), value, Effect::None)
+        })
+    }
+
+    /// Records an [`insert`](HashMap::insert) operation.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.record(Kind::Insert, key.clone(), |map| {
+            let effect = Effect::Insert(value.clone());
+            let old = map.pin().insert(key, value).cloned();
+            (old.clone(), old, effect)
+        })
+    }
+
+    /// Records a [`remove`](HashMap::remove) operation.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.record(Kind::Remove, key.clone(), |map| {
+            let old = map.pin().remove(key).cloned();
+            let effect = match old {
+                Some(_) => Effect::Remove,
+                None => Effect::None,
+            };
+
+            (old.clone(), old, effect)
+        })
+    }
+
+    /// Records an [`update`](HashMap::update) operation.
+    pub fn update<F>(&mut self, key: K, update: F) -> Option<V>
+    where
+        F: Fn(&V) -> V,
+    {
+        self.record(Kind::Update, key.clone(), |map| {
+            // The input of the last call to `update` is the value that was replaced.
+            let input = RefCell::new(None);
+            let new = map
+                .pin()
+                .update(key, |value| {
+                    *input.borrow_mut() = Some(value.clone());
+                    update(value)
+                })
+                .cloned();
+
+            match new {
+                Some(new) => (Some(new.clone()), input.into_inner(), Effect::Insert(new)),
+                None => (None, None, Effect::None),
+            }
+        })
+    }
+
+    /// Records a [`compute`](HashMap::compute) operation.
+    ///
+    /// Returns the value of the key after the operation, and the result of the closure
+    /// if the operation was aborted.
+    pub fn compute<F, T>(&mut self, key: K, mut compute: F) -> (Option<V>, Option<T>)
+    where
+        F: FnMut(Option<&V>) -> Operation<V, T>,
+    {
+        self.record(Kind::Compute, key.clone(), |map| {
+            // The input of the last call to `compute` is the value the operation observed.
+            let mut input = None;
+            let map = map.pin();
+            let result = map.compute(key, |entry| {
+                input = entry.map(|(_, value)| value.clone());
+                compute(entry.map(|(_, value)| value))
+            });
+
+            match result {
+                Compute::Inserted(_, new) => {
+                    ((Some(new.clone()), None), None, Effect::Insert(new.clone()))
+                }
+                Compute::Updated { old, new } => (
+                    (Some(new.1.clone()), None),
+                    Some(old.1.clone()),
+                    Effect::Insert(new.1.clone()),
+                ),
+                Compute::Removed(_, old) => ((None, None), Some(old.clone()), Effect::Remove),
+                Compute::Aborted(value) => ((input.clone(), Some(value)), input, Effect::None),
+            }
+        })
+    }
+
+    // Records an operation, returning its output.
+    //
+    // The operation returns its output, the value it observed, and its effect on the key.
+    fn record<T>(
+        &mut self,
+        kind: Kind,
+        key: K,
+        operation: impl FnOnce(&HashMap<K, V, S>) -> (T, Option<V>, Effect<V>),
+    ) -> T {
+        let call = self.history.now();
+        let (output, observed, effect) = operation(self.map);
+        let ret = self.history.now();
+
+        self.events.push(Event {
+            thread: self.thread,
+            kind,
+            key,
+            observed,
+            effect,
+            call,
+            ret,
+        });
+
+        output
+    }
+}
+
+impl<K, V, S> fmt::Debug for Recorder<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("thread", &self.thread)
+            .field("len", &self.events.len())
+            .finish()
+    }
+}
+
+impl<K, V, S> Drop for Recorder<'_, K, V, S> {
+    fn drop(&mut self) {
+        let mut events = self.history.events.lock().unwrap();
+        events.append(&mut self.events);
+    }
+}
+
+impl<K, V> fmt::Display for Violation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "history of key {:?} is not linearizable:", self.key)?;
+
+        for event in &self.events {
+            writeln!(
+                f,
+                "  [{}, {}] thread {}: {:?} observed {:?}, effect {:?}",
+                event.call, event.ret, event.thread, event.kind, event.observed, event.effect
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V> Error for Violation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+}
+
+// Applies an operation to the sequential model, returning `false` if the
+// operation could not have been performed in the current state.
+fn step<K, V>(model: &mut StdHashMap<K, V>, event: &Event<K, V>) -> bool
+where
+    K: Hash + Eq + Clone,
+    V: Eq + Clone,
+{
+    if model.get(&event.key) != event.observed.as_ref() {
+        return false;
+    }
+
+    match &event.effect {
+        Effect::None => {}
+        Effect::Insert(value) => {
+            model.insert(event.key.clone(), value.clone());
+        }
+        Effect::Remove => {
+            model.remove(&event.key);
+        }
+    }
+
+    true
+}
+
+// Checks whether the operations on a single key are linearizable, with events
+// sorted by invocation.
+//
+// This performs a depth-first search over the possible linearization orders, where an
+// operation may be linearized next if it was invoked before any pending operation
+// returned. Visited combinations of linearized operations and model states are cached
+// to avoid exploring equivalent orders more than once.
+fn linearizable<K, V>(key: &K, events: &[&Event<K, V>]) -> bool
+where
+    K: Hash + Eq + Clone,
+    V: Hash + Eq + Clone,
+{
+    let mut model = StdHashMap::new();
+    let mut linearized = vec![0u64; events.len().div_ceil(64)];
+    let mut cache = HashSet::new();
+
+    // The linearized operations and the model state before each was applied.
+    let mut stack: Vec<(usize, Option<V>)> = Vec::new();
+
+    // The next operation to try at the current depth.
+    let mut start = 0;
+
+    let is_linearized = |linearized: &[u64], i: usize| linearized[i / 64] & (1 << (i % 64)) != 0;
+
+    while stack.len() < events.len() {
+        // The earliest response of any pending operation.
+        let min_ret = (0..events.len())
+            .filter(|&i| !is_linearized(&linearized, i))
+            .map(|i| events[i].ret)
+            .min()
+            .unwrap();
+
+        let mut next = None;
+
+        // Events are sorted by invocation, so no later operation can be linearized
+        // once one is invoked after a pending response.
+        for i in (start..events.len()).take_while(|&i| events[i].call < min_ret) {
+            if is_linearized(&linearized, i) {
+                continue;
+            }
+
+            let prev = model.get(key).cloned();
+            if !step(&mut model, events[i]) {
+                continue;
+            }
+
+            linearized[i / 64] |= 1 << (i % 64);
+
+            // Skip states that have already been explored.
+            if cache.insert((linearized.clone(), model.get(key).cloned())) {
+                next = Some((i, prev));
+                break;
+            }
+
+            linearized[i / 64] &= !(1 << (i % 64));
+            restore(&mut model, key, prev);
+        }
+
+        match next {
+            // Linearize the operation and continue the search.
+            Some(next) => {
+                stack.push(next);
+                start = 0;
+            }
+
+            // Backtrack and try the next candidate at the previous depth.
+            None => {
+                let Some((i, prev)) = stack.pop() else {
+                    return false;
+                };
+
+                linearized[i / 64] &= !(1 << (i % 64));
+                restore(&mut model, key, prev);
+                start = i + 1;
+            }
+        }
+    }
+
+    true
+}
+
+// Restores the state of the key in the sequential model.
+fn restore<K, V>(model: &mut StdHashMap<K, V>, key: &K, value: Option<V>)
+where
+    K: Hash + Eq + Clone,
+{
+    match value {
+        Some(value) => model.insert(key.clone(), value),
+        None => model.remove(key),
+    };
+}
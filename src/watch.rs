@@ -0,0 +1,862 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+use std::{fmt, mem, ptr};
+
+use crate::raw::Entry;
+use crate::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::{AtomicMut, Condvar, Mutex};
+
+/// A change to an entry in a [`HashMap`](crate::HashMap).
+///
+/// Changes are received through a [`Watcher`] returned by [`HashMap::watch`](crate::HashMap::watch),
+/// or a [`Receiver`] returned by [`HashMap::watch_all`](crate::HashMap::watch_all).
+///
+/// The key and values of a change are shared with the map, and remain valid even after
+/// they are replaced or removed from the map.
+pub struct Change<K, V> {
+    /// The entry that was replaced or removed, or null.
+    old: *mut Entry<K, V>,
+
+    /// The entry that was inserted, or null.
+    new: *mut Entry<K, V>,
+}
+
+// Safety: `Change` only hands out shared references to the keys and values.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Change<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Change<K, V> {}
+
+/// The kind of a [`Change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The key was inserted into the map.
+    Inserted,
+
+    /// The value of the key was replaced.
+    Replaced,
+
+    /// The key was removed from the map.
+    Removed,
+}
+
+impl<K, V> Change<K, V> {
+    /// Creates a new `Change`.
+    ///
+    /// # Safety
+    ///
+    /// `old` and `new` must be null or valid entries for the same key, and at least
+    /// one of them must be non-null.
+    pub(crate) unsafe fn new(old: *mut Entry<K, V>, new: *mut Entry<K, V>) -> Change<K, V> {
+        debug_assert!(!old.is_null() || !new.is_null());
+        Change { old, new }
+    }
+
+    /// Returns the kind of this change.
+    #[inline]
+    pub fn kind(&self) -> ChangeKind {
+        match (self.old.is_null(), self.new.is_null()) {
+            (true, _) => ChangeKind::Inserted,
+            (false, false) => ChangeKind::Replaced,
+            (false, true) => ChangeKind::Removed,
+        }
+    }
+
+    /// Returns the key that was changed.
+    #[inline]
+    pub fn key(&self) -> &K {
+        let entry = if self.new.is_null() {
+            self.old
+        } else {
+            self.new
+        };
+
+        // Safety: At least one of the entries is non-null, and entries are never
+        // deallocated while reachable.
+        unsafe { &(*entry).key }
+    }
+
+    /// Returns the value that was replaced or removed, if any.
+    #[inline]
+    pub fn old_value(&self) -> Option<&V> {
+        // Safety: Entries are never deallocated while reachable.
+        unsafe { self.old.as_ref() }.map(|entry| &entry.value)
+    }
+
+    /// Returns the value that was inserted, if any.
+    #[inline]
+    pub fn new_value(&self) -> Option<&V> {
+        // Safety: Entries are never deallocated while reachable.
+        unsafe { self.new.as_ref() }.map(|entry| &entry.value)
+    }
+}
+
+impl<K, V> Clone for Change<K, V> {
+    fn clone(&self) -> Self {
+        Change {
+            old: self.old,
+            new: self.new,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Change<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Change")
+            .field("kind", &self.kind())
+            .field("key", self.key())
+            .field("old_value", &self.old_value())
+            .field("new_value", &self.new_value())
+            .finish()
+    }
+}
+
+/// A subscription to the changes of a single key in a [`HashMap`](crate::HashMap).
+///
+/// This type is created with [`HashMap::watch`](crate::HashMap::watch). See its
+/// documentation for details.
+pub struct Watcher<K, V> {
+    receiver: Receiver<Change<K, V>>,
+}
+
+impl<K, V> Watcher<K, V> {
+    /// Returns the next change to the key if one is available, without blocking.
+    #[inline]
+    pub fn try_recv(&self) -> Option<Change<K, V>> {
+        self.receiver.try_recv()
+    }
+
+    /// Blocks the current thread until the key is changed, returning the change.
+    ///
+    /// Returns `None` if the map was dropped and every change has been received.
+    #[inline]
+    pub fn recv(&self) -> Option<Change<K, V>> {
+        self.receiver.recv()
+    }
+
+    /// Waits asynchronously until the key is changed, returning the change.
+    ///
+    /// The returned future resolves to `None` if the map was dropped and every change
+    /// has been received.
+    #[inline]
+    pub fn recv_async(&self) -> Recv<'_, Change<K, V>> {
+        self.receiver.recv_async()
+    }
+}
+
+impl<K, V> fmt::Debug for Watcher<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher").finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of a subscription to changes in a [`HashMap`](crate::HashMap).
+///
+/// This type is created with [`HashMap::watch_all`](crate::HashMap::watch_all). See its
+/// documentation for details.
+///
+/// Values are queued without bound until they are received, so a receiver that falls
+/// behind the changes to the map holds on to every change it has not yet received.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Returns the next value if one is available, without blocking.
+    #[inline]
+    pub fn try_recv(&self) -> Option<T> {
+        self.channel.state.lock().unwrap().queue.pop_front()
+    }
+
+    /// Blocks the current thread until a value is available.
+    ///
+    /// Returns `None` if the sender was dropped and every value has been received.
+    pub fn recv(&self) -> Option<T> {
+        let mut state = self.channel.state.lock().unwrap();
+
+        loop {
+            if let Some(value) = state.queue.pop_front() {
+                return Some(value);
+            }
+
+            if state.disconnected {
+                return None;
+            }
+
+            state = self.channel.available.wait(state).unwrap();
+        }
+    }
+
+    /// Waits asynchronously until a value is available.
+    ///
+    /// The returned future resolves to `None` if the sender was dropped and every value
+    /// has been received.
+    #[inline]
+    pub fn recv_async(&self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+
+        // Stop the sender from queueing any more values.
+        state.disconnected = true;
+        state.queue.clear();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// A future that resolves to the next value of a [`Receiver`] or [`Watcher`].
+///
+/// This type is created with [`Receiver::recv_async`] or [`Watcher::recv_async`].
+pub struct Recv<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.receiver.channel.state.lock().unwrap();
+
+        if let Some(value) = state.queue.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+
+        if state.disconnected {
+            return Poll::Ready(None);
+        }
+
+        // Register to be woken when a value is sent.
+        match &mut state.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => state.waker = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Debug for Recv<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recv").finish_non_exhaustive()
+    }
+}
+
+// An unbounded channel with a single sender and receiver.
+//
+// A subscription must not block writers to the map, so changes are queued until the
+// receiver is dropped, rather than applying backpressure or dropping changes.
+struct Channel<T> {
+    // The state of the channel.
+    state: Mutex<ChannelState<T>>,
+
+    // Notifies a blocked receiver that a value is available.
+    available: Condvar,
+}
+
+struct ChannelState<T> {
+    // Values that have been sent but not yet received.
+    queue: VecDeque<T>,
+
+    // The waker of an asynchronous receiver.
+    waker: Option<Waker>,
+
+    // Whether either half of the channel was dropped.
+    disconnected: bool,
+}
+
+// The sending half of a channel.
+struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+// Create a new channel.
+fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        state: Mutex::new(ChannelState {
+            queue: VecDeque::new(),
+            waker: None,
+            disconnected: false,
+        }),
+        available: Condvar::new(),
+    });
+
+    let sender = Sender {
+        channel: channel.clone(),
+    };
+
+    (sender, Receiver { channel })
+}
+
+impl<T> Channel<T> {
+    // Send a value, returning `false` if the receiver was dropped.
+    fn send(&self, value: T) -> bool {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            if state.disconnected {
+                return false;
+            }
+
+            state.queue.push_back(value);
+            state.waker.take()
+        };
+
+        self.available.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        true
+    }
+
+    // Returns `true` if the receiver was dropped.
+    fn is_disconnected(&self) -> bool {
+        self.state.lock().unwrap().disconnected
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.channel.state.lock().unwrap();
+            state.disconnected = true;
+            state.waker.take()
+        };
+
+        self.channel.available.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// A thread or task waiting to be notified.
+pub(crate) struct Waiter {
+    // The state of the waiter.
+    state: Mutex<WaiterState>,
+
+    // Notifies a blocked thread.
+    notified: Condvar,
+}
+
+struct WaiterState {
+    // Whether the waiter was notified since it last waited.
+    notified: bool,
+
+    // The waker of an asynchronous waiter.
+    waker: Option<Waker>,
+}
+
+impl Waiter {
+    // Create a new `Waiter`.
+    fn new() -> Waiter {
+        Waiter {
+            state: Mutex::new(WaiterState {
+                notified: false,
+                waker: None,
+            }),
+            notified: Condvar::new(),
+        }
+    }
+
+    // Notify the waiter.
+    fn notify(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.notified = true;
+            state.waker.take()
+        };
+
+        self.notified.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    // Blocks the current thread until the waiter is notified or the deadline passes.
+    //
+    // Returns `false` if the deadline passed.
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        while !state.notified {
+            state = match deadline {
+                Some(deadline) => {
+                    let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                        return false;
+                    };
+
+                    self.notified.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.notified.wait(state).unwrap(),
+            };
+        }
+
+        state.notified = false;
+        true
+    }
+
+    // Polls for a notification of the waiter, registering the waker of the current task
+    // if it was not notified.
+    fn poll_notified(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.notified {
+            state.notified = false;
+            return Poll::Ready(());
+        }
+
+        match &mut state.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => state.waker = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+// A waiter registered for changes to keys with a given hash.
+//
+// The waiter is removed from the registry when dropped.
+pub(crate) struct Waiting<'a, K, V> {
+    watchers: &'a Watchers<K, V>,
+    hash: u64,
+    waiter: Arc<Waiter>,
+}
+
+impl<K, V> Waiting<'_, K, V> {
+    // Blocks the current thread until a key with the hash is changed, or the deadline passes.
+    //
+    // Returns `false` if the deadline passed. Note that changes made after registering and
+    // before the first call are not missed.
+    pub fn wait(&self, deadline: Option<Instant>) -> bool {
+        self.waiter.wait(deadline)
+    }
+
+    // Waits asynchronously until a key with the hash is changed.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            waiter: &self.waiter,
+        }
+    }
+}
+
+impl<K, V> Drop for Waiting<'_, K, V> {
+    fn drop(&mut self) {
+        // The registry was allocated when we registered.
+        let shard = self.watchers.shard(self.hash).unwrap();
+        let mut shard = shard.lock().unwrap();
+
+        let watched = shard.get_mut(&self.hash).unwrap();
+        watched
+            .waiters
+            .retain(|waiter| !Arc::ptr_eq(waiter, &self.waiter));
+
+        if watched.is_empty() {
+            shard.remove(&self.hash);
+        }
+
+        self.watchers.unsubscribe(self.hash);
+    }
+}
+
+// A future that resolves when a waiter is notified.
+pub(crate) struct Notified<'a> {
+    waiter: &'a Waiter,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.waiter.poll_notified(cx)
+    }
+}
+
+// The number of slots in the filter of watched keys.
+const FILTER_SLOTS: usize = 128;
+
+// The subscriptions to changes in a map.
+//
+// Writers must check for subscriptions on every update, so subscriptions are tracked by
+// a filter of key hashes that allows writers to skip notifications for any key that is
+// not watched with a single load.
+//
+// Subscriptions to individual keys, and threads waiting for a key to be inserted, are
+// registered by the full hash of the key, in a shard of the registry for its filter slot.
+// Notifying the watchers of a key only locks its shard, and values are sent and waiters
+// woken after the lock is released.
+pub(crate) struct Watchers<K, V> {
+    // The number of subscriptions matching each slot of the key hash.
+    //
+    // Subscriptions to every key are counted in every slot.
+    filter: Box<[AtomicUsize]>,
+
+    // The total number of subscriptions.
+    active: AtomicUsize,
+
+    // Subscriptions to individual keys, with a shard for each filter slot, or null if no
+    // key has been watched yet.
+    keyed: AtomicPtr<[Shard<K, V>; FILTER_SLOTS]>,
+
+    // The number of subscriptions to every key.
+    watching_all: AtomicUsize,
+
+    // Subscriptions to every key.
+    all: Mutex<Vec<Sender<Change<K, V>>>>,
+
+    _shards: PhantomData<Box<[Shard<K, V>; FILTER_SLOTS]>>,
+}
+
+// A shard of the subscriptions to individual keys, keyed by the full hash of the key.
+type Shard<K, V> = Mutex<HashMap<u64, Watched<K, V>>>;
+
+// The subscriptions to keys with a given hash.
+struct Watched<K, V> {
+    // Subscriptions to the changes of a key.
+    subscriptions: Vec<Subscription<K, V>>,
+
+    // Threads or tasks waiting for any key with the hash to change.
+    waiters: Vec<Arc<Waiter>>,
+}
+
+impl<K, V> Watched<K, V> {
+    // Returns `true` if there are no subscriptions to the hash.
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty() && self.waiters.is_empty()
+    }
+}
+
+impl<K, V> Default for Watched<K, V> {
+    fn default() -> Self {
+        Watched {
+            subscriptions: Vec::new(),
+            waiters: Vec::new(),
+        }
+    }
+}
+
+// A subscription to changes of a key.
+struct Subscription<K, V> {
+    // The key being watched.
+    key: WatchedKey<K>,
+
+    // The sending half of the subscription.
+    sender: Sender<Change<K, V>>,
+}
+
+struct WatchedKey<K> {
+    // The key being watched.
+    key: K,
+
+    // The equality function of the key.
+    //
+    // Writes are not always performed with `K: Eq`, so we store it on subscription.
+    eq: fn(&K, &K) -> bool,
+}
+
+impl<K, V> Subscription<K, V> {
+    // Returns `true` if this subscription is watching the given key.
+    #[inline]
+    fn is_watching(&self, key: &K) -> bool {
+        (self.key.eq)(&self.key.key, key)
+    }
+}
+
+impl<K, V> Watchers<K, V> {
+    // Create a new `Watchers`.
+    pub fn new() -> Watchers<K, V> {
+        Watchers {
+            filter: (0..FILTER_SLOTS).map(|_| AtomicUsize::new(0)).collect(),
+            active: AtomicUsize::new(0),
+            keyed: AtomicPtr::new(ptr::null_mut()),
+            watching_all: AtomicUsize::new(0),
+            all: Mutex::new(Vec::new()),
+            _shards: PhantomData,
+        }
+    }
+
+    // Returns the filter slot for the given hash.
+    #[inline]
+    fn slot(&self, hash: u64) -> &AtomicUsize {
+        &self.filter[(hash as usize) & (FILTER_SLOTS - 1)]
+    }
+
+    // Returns the registry shard for the given hash, if any key has been watched.
+    #[inline]
+    fn shard(&self, hash: u64) -> Option<&Shard<K, V>> {
+        // Safety: The registry is never deallocated until the watchers are dropped.
+        let keyed = unsafe { self.keyed.load(Ordering::Acquire).as_ref()? };
+        Some(&keyed[(hash as usize) & (FILTER_SLOTS - 1)])
+    }
+
+    // Returns the registry shard for the given hash, allocating the registry if necessary.
+    fn shard_or_init(&self, hash: u64) -> &Shard<K, V> {
+        if let Some(shard) = self.shard(hash) {
+            return shard;
+        }
+
+        let new = Box::into_raw(Box::new(std::array::from_fn(
+            |_| Mutex::new(HashMap::new()),
+        )));
+
+        let result =
+            self.keyed
+                .compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire);
+
+        if result.is_err() {
+            // Lost the race to allocate the registry.
+            //
+            // Safety: Our allocation was never shared.
+            drop(unsafe { Box::from_raw(new) });
+        }
+
+        self.shard(hash).unwrap()
+    }
+
+    // Subscribe to changes of the given key.
+    pub fn watch(&self, hash: u64, key: K) -> Watcher<K, V>
+    where
+        K: Eq,
+    {
+        let key = WatchedKey { key, eq: K::eq };
+
+        let (sender, receiver) = channel();
+        self.register(hash, |watched| {
+            watched.subscriptions.push(Subscription { key, sender })
+        });
+
+        Watcher { receiver }
+    }
+
+    // Register a waiter for changes to any key with the given hash.
+    //
+    // This allows waiting on keys that are not owned.
+    pub fn wait(&self, hash: u64) -> Waiting<'_, K, V> {
+        let waiter = Arc::new(Waiter::new());
+        self.register(hash, |watched| watched.waiters.push(waiter.clone()));
+
+        Waiting {
+            watchers: self,
+            hash,
+            waiter,
+        }
+    }
+
+    // Register a subscription to keys with the given hash.
+    fn register(&self, hash: u64, register: impl FnOnce(&mut Watched<K, V>)) {
+        {
+            let mut shard = self.shard_or_init(hash).lock().unwrap();
+
+            // Announce the subscription to writers.
+            //
+            // This happens under the lock to ensure that the subscription is not removed
+            // before it is counted.
+            self.active.fetch_add(1, Ordering::SeqCst);
+            self.slot(hash).fetch_add(1, Ordering::SeqCst);
+
+            register(shard.entry(hash).or_default());
+        }
+
+        // Ensure that any write the caller does not observe after subscribing observes
+        // the subscription.
+        //
+        // This pairs with the `SeqCst` write and filter load by writers.
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    // Subscribe to changes of every key.
+    pub fn watch_all(&self) -> Receiver<Change<K, V>> {
+        let (sender, receiver) = channel();
+
+        {
+            let mut all = self.all.lock().unwrap();
+
+            // Announce the subscription to writers, see `register`.
+            //
+            // Note that writers check `watching_all` after the filter, so it must be
+            // incremented first.
+            self.active.fetch_add(1, Ordering::SeqCst);
+            self.watching_all.fetch_add(1, Ordering::SeqCst);
+            for slot in self.filter.iter() {
+                slot.fetch_add(1, Ordering::SeqCst);
+            }
+
+            all.push(sender);
+        }
+
+        // See `register`.
+        atomic::fence(Ordering::SeqCst);
+
+        receiver
+    }
+
+    // Returns `true` if any changes are being watched.
+    //
+    // Note that this must be called after the change was performed with a `SeqCst` write.
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        Self::loom_fence();
+        self.active.load(Ordering::SeqCst) != 0
+    }
+
+    // Returns `true` if changes to keys with the given hash may be watched.
+    //
+    // Note that this must be called after the change was performed with a `SeqCst` write.
+    #[inline]
+    pub fn is_watched(&self, hash: u64) -> bool {
+        Self::loom_fence();
+        self.slot(hash).load(Ordering::SeqCst) != 0
+    }
+
+    // `loom` models `SeqCst` accesses as `AcqRel`, so the total order between the write
+    // and the filter load must be established with a fence.
+    #[inline(always)]
+    fn loom_fence() {
+        #[cfg(papaya_loom)]
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    // Notify any subscriptions of a change to the key with the given hash.
+    //
+    // This must only be called after `is_watched` returns `true` for the hash.
+    //
+    // # Safety
+    //
+    // `old` and `new` must be null or valid entries for the same key with the given
+    // hash, and at least one of them must be non-null.
+    #[cold]
+    #[inline(never)]
+    pub unsafe fn notify(&self, hash: u64, old: *mut Entry<K, V>, new: *mut Entry<K, V>) {
+        // Safety: Guaranteed by caller.
+        let change = || unsafe { Change::new(old, new) };
+        let key = change();
+
+        // The channels to send the change to, and waiters to wake, once every lock
+        // is released.
+        let mut channels = Vec::new();
+        let mut waiters = Vec::new();
+
+        if let Some(shard) = self.shard(hash) {
+            let mut shard = shard.lock().unwrap();
+
+            match shard.get_mut(&hash) {
+                Some(watched) => {
+                    waiters.extend(watched.waiters.iter().cloned());
+
+                    watched.subscriptions.retain(|subscription| {
+                        let channel = &subscription.sender.channel;
+
+                        // Remove any subscriptions that were dropped.
+                        if channel.is_disconnected() {
+                            self.unsubscribe(hash);
+                            return false;
+                        }
+
+                        if subscription.is_watching(key.key()) {
+                            channels.push(channel.clone());
+                        }
+
+                        true
+                    });
+
+                    if watched.is_empty() {
+                        shard.remove(&hash);
+                    }
+                }
+
+                // The filter slot is shared with other hashes, some of which may only
+                // be watched by dropped subscriptions that were never notified since.
+                None => shard.retain(|&hash, watched| {
+                    watched.subscriptions.retain(|subscription| {
+                        let connected = !subscription.sender.channel.is_disconnected();
+                        if !connected {
+                            self.unsubscribe(hash);
+                        }
+                        connected
+                    });
+
+                    !watched.is_empty()
+                }),
+            }
+        }
+
+        // Note that the filter is loaded with `SeqCst` by `is_watched`, which synchronizes
+        // with the increment of the filter by `watch_all`.
+        if self.watching_all.load(Ordering::Relaxed) != 0 {
+            let mut all = self.all.lock().unwrap();
+
+            all.retain(|sender| {
+                // Remove any subscriptions that were dropped.
+                if sender.channel.is_disconnected() {
+                    self.unsubscribe_all();
+                    return false;
+                }
+
+                channels.push(sender.channel.clone());
+                true
+            });
+        }
+
+        for channel in channels {
+            channel.send(change());
+        }
+
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+
+    // Remove a subscription to keys with the given hash from the filter.
+    fn unsubscribe(&self, hash: u64) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        self.slot(hash).fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Remove a subscription to every key from the filter.
+    fn unsubscribe_all(&self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        self.watching_all.fetch_sub(1, Ordering::Relaxed);
+        for slot in self.filter.iter() {
+            slot.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    // Returns the number of bytes allocated for the filter and registry.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        let keyed = match self.keyed.load(Ordering::Acquire).is_null() {
+            true => 0,
+            false => mem::size_of::<[Shard<K, V>; FILTER_SLOTS]>(),
+        };
+
+        mem::size_of_val::<[_]>(&self.filter) + keyed
+    }
+}
+
+impl<K, V> Drop for Watchers<K, V> {
+    fn drop(&mut self) {
+        let keyed = self.keyed.load_mut();
+
+        if !keyed.is_null() {
+            // Safety: We have unique access to the registry, which was allocated with `Box`.
+            drop(unsafe { Box::from_raw(keyed) });
+        }
+    }
+}
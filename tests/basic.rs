@@ -1,12 +1,18 @@
 // Adapted from: https://github.com/jonhoo/flurry/blob/main/tests/basic.rs
 
-use papaya_alloy::{Compute, HashMap, OccupiedError, Operation};
+use papaya_alloy::{
+    ChangeKind, Compute, CounterMode, HashMap, LaggedError, MoveError, OccupiedError, Operation,
+    ResizeMode, ResizeStatus,
+};
 
 use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 mod common;
-use common::with_map;
+use common::{with_logged_map, with_map, with_snapshot_map};
 
 #[test]
 fn new() {
@@ -167,6 +173,51 @@ fn update_empty() {
     });
 }
 
+#[test]
+fn try_update() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        assert_eq!(map.try_update(42, |v| Ok::<_, ()>(v + 1)), Ok(None));
+        assert!(map.is_empty());
+
+        map.insert(42, 0);
+        assert_eq!(map.try_update(42, |v| Ok::<_, ()>(v + 1)), Ok(Some(&1)));
+        assert_eq!(map.try_update(42, |_| Err("error")), Err("error"));
+        assert_eq!(
+            map.pin().try_update(42, |v| Ok::<_, ()>(v + 1)),
+            Ok(Some(&2))
+        );
+        assert_eq!(map.get(&42), Some(&2));
+        assert_eq!(map.len(), 1);
+    });
+}
+
+#[test]
+fn try_update_concurrent() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        map.insert(0, 0);
+
+        // Updates are retried on contention, and fail once the value reaches the limit.
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let _ = map.try_update(0, |&v| if v < 2000 { Ok(v + 1) } else { Err(v) });
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.get(&0), Some(&2000));
+        assert_eq!(
+            map.try_update(0, |&v| if v < 2000 { Ok(v + 1) } else { Err(v) }),
+            Err(2000)
+        );
+    });
+}
+
 #[test]
 fn update_or_insert() {
     with_map::<usize, usize>(|map| {
@@ -225,6 +276,39 @@ fn update_or_insert_with() {
     });
 }
 
+#[test]
+fn try_update_or_insert_with() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let increment = |v: &usize| if *v < 2 { Ok(v + 1) } else { Err("limit") };
+
+        assert_eq!(
+            map.try_update_or_insert_with(42, increment, || Err("error")),
+            Err("error")
+        );
+        assert!(map.is_empty());
+
+        assert_eq!(
+            map.try_update_or_insert_with(42, increment, || Ok(0)),
+            Ok(&0)
+        );
+        assert_eq!(
+            map.try_update_or_insert_with(42, increment, || Ok(0)),
+            Ok(&1)
+        );
+        assert_eq!(
+            map.pin().try_update_or_insert_with(42, increment, || Ok(0)),
+            Ok(&2)
+        );
+        assert_eq!(
+            map.try_update_or_insert_with(42, increment, || Ok(0)),
+            Err("limit")
+        );
+        assert_eq!(map.get(&42), Some(&2));
+        assert_eq!(map.len(), 1);
+    });
+}
+
 #[test]
 fn get_or_insert() {
     with_map::<usize, usize>(|map| {
@@ -327,6 +411,180 @@ fn get_or_insert_with() {
     });
 }
 
+#[test]
+fn get_or_try_insert_with() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        assert_eq!(
+            map.get_or_try_insert_with(42, || Err("error")),
+            Err("error")
+        );
+        assert!(map.is_empty());
+
+        assert_eq!(map.get_or_try_insert_with(42, || Ok::<_, ()>(0)), Ok(&0));
+        assert_eq!(map.len(), 1);
+
+        // The closure is not called if the key is present.
+        let result = map.get_or_try_insert_with(42, || -> Result<_, ()> { unreachable!() });
+        assert_eq!(result, Ok(&0));
+        assert_eq!(
+            map.pin().get_or_try_insert_with(43, || Ok::<_, ()>(1)),
+            Ok(&1)
+        );
+        assert_eq!(map.len(), 2);
+    });
+}
+
+#[test]
+fn get_or_insert_with_once() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        assert_eq!(map.get_or_insert_with_once(42, || 0), &0);
+        assert_eq!(map.get_or_insert_with_once(42, || 1), &0);
+        assert_eq!(map.pin().get_or_insert_with_once(43, || 1), &1);
+        assert_eq!(map.len(), 2);
+
+        // A panicking initializer releases its claim.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.get_or_insert_with_once(44, || panic!("initializer failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.get(&44), None);
+        assert_eq!(map.get_or_insert_with_once(44, || 2), &2);
+
+        // Concurrent initializers of the same key run once.
+        let calls = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let value = map.get_or_insert_with_once(45, || {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        thread::sleep(Duration::from_millis(10));
+                        3
+                    });
+
+                    assert_eq!(value, &3);
+                });
+            }
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+#[should_panic(expected = "attempted to initialize a key from within its own initializer")]
+fn get_or_insert_with_once_reentrant() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        map.get_or_insert_with_once(1, || *map.get_or_insert_with_once(1, || 1));
+    });
+}
+
+#[test]
+fn get_or_insert_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    with_map::<usize, usize>(|map| {
+        let map = Arc::new(map());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        runtime.block_on(async {
+            assert_eq!(map.get_or_insert_async(1, || async { 1 }).await, &1);
+            assert_eq!(map.get_or_insert_async(1, || async { 2 }).await, &1);
+
+            // Concurrent loads of the same key are coalesced.
+            let tasks: Vec<_> = (0..8)
+                .map(|_| {
+                    let (map, calls) = (map.clone(), calls.clone());
+                    tokio::spawn(async move {
+                        let value = map.get_or_insert_async(2, || async {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            for _ in 0..10 {
+                                tokio::task::yield_now().await;
+                            }
+                            2
+                        });
+
+                        *value.await
+                    })
+                })
+                .collect();
+
+            for task in tasks {
+                assert_eq!(task.await.unwrap(), 2);
+            }
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+            // An error is returned to its caller, and releases the load.
+            let result = map
+                .get_or_try_insert_async(3, || async { Err::<usize, _>("failed") })
+                .await;
+            assert_eq!(result, Err("failed"));
+            assert_eq!(map.get(&3), None);
+
+            let result = map
+                .get_or_try_insert_async(3, || async { Ok::<_, ()>(3) })
+                .await;
+            assert_eq!(result, Ok(&3));
+
+            // The error of the leading load is shared with the waiting tasks.
+            let calls = Arc::new(AtomicUsize::new(0));
+            let tasks: Vec<_> = (0..4)
+                .map(|i| {
+                    let (map, calls) = (map.clone(), calls.clone());
+                    tokio::spawn(async move {
+                        let value = map.get_or_try_insert_async(5, || async move {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            for _ in 0..10 {
+                                tokio::task::yield_now().await;
+                            }
+                            Err::<usize, _>(i)
+                        });
+
+                        value.await.copied()
+                    })
+                })
+                .collect();
+
+            for task in tasks {
+                assert_eq!(task.await.unwrap(), Err(0));
+            }
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+            assert_eq!(map.get(&5), None);
+
+            // A failed load is not cached.
+            let result = map
+                .get_or_try_insert_async(5, || async { Ok::<_, usize>(5) })
+                .await;
+            assert_eq!(result, Ok(&5));
+
+            // Cancelling the leading load hands it over to a waiting task.
+            let leader = tokio::spawn({
+                let map = map.clone();
+                async move {
+                    let value = map.get_or_insert_async(4, std::future::pending::<usize>);
+                    *value.await
+                }
+            });
+            tokio::task::yield_now().await;
+
+            let follower = tokio::spawn({
+                let map = map.clone();
+                async move { *map.get_or_insert_async(4, || async { 4 }).await }
+            });
+            tokio::task::yield_now().await;
+
+            assert!(!follower.is_finished());
+            leader.abort();
+            assert_eq!(follower.await.unwrap(), 4);
+        });
+    });
+}
+
 #[test]
 fn compute() {
     with_map::<usize, usize>(|map| {
@@ -360,6 +618,167 @@ fn compute() {
     });
 }
 
+#[test]
+fn rename() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        map.pin().insert(1, 10);
+        map.pin().insert(2, 20);
+
+        assert_eq!(map.pin().rename(&1, 3), Ok(&10));
+        assert_eq!(map.pin().get(&1), None);
+        assert_eq!(map.pin().get(&3), Some(&10));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.pin().rename(&3, 2), Err(MoveError::Occupied(&20)));
+        assert_eq!(map.pin().get(&3), Some(&10));
+        assert_eq!(map.pin().rename(&1, 4), Err(MoveError::NotFound));
+        assert_eq!(map.pin().rename(&3, 3), Ok(&10));
+        assert_eq!(map.len(), 2);
+
+        // Rename enough entries to force a resize.
+        for i in 100..1100 {
+            map.pin().insert(i, i);
+        }
+        for i in 100..1100 {
+            assert_eq!(map.pin().rename(&i, i + 1000), Ok(&i));
+        }
+
+        assert_eq!(map.len(), 1002);
+        for i in 100..1100 {
+            assert_eq!(map.pin().get(&i), None);
+            assert_eq!(map.pin().get(&(i + 1000)), Some(&i));
+        }
+        map.check_invariants();
+    });
+}
+
+#[test]
+fn rename_concurrent() {
+    const KEYS: usize = 64;
+
+    with_snapshot_map::<usize, usize>(|map| {
+        let map = map();
+        for i in 0..KEYS {
+            map.pin().insert(i, i);
+        }
+
+        thread::scope(|s| {
+            // Rename every key back and forth, racing in both directions.
+            for forward in [true, false] {
+                let map = &map;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        for i in 0..KEYS {
+                            let (from, to) = if forward {
+                                (i, i + KEYS)
+                            } else {
+                                (i + KEYS, i)
+                            };
+                            match map.pin().rename(&from, to) {
+                                Ok(&value) => assert_eq!(value, i),
+                                Err(MoveError::NotFound) => {}
+                                Err(MoveError::Occupied(&value)) => assert_eq!(value, i),
+                            }
+                        }
+                    }
+                });
+            }
+
+            // Every entry is present at exactly one of its keys in any snapshot.
+            s.spawn(|| {
+                for _ in 0..20 {
+                    let snapshot = map.snapshot();
+                    assert_eq!(snapshot.len(), KEYS);
+                    for i in 0..KEYS {
+                        let found = [i, i + KEYS].map(|key| snapshot.get(&key));
+                        assert!(matches!(found, [Some(_), None] | [None, Some(_)]));
+                    }
+                }
+            });
+        });
+
+        assert_eq!(map.len(), KEYS);
+        map.check_invariants();
+    });
+}
+
+#[test]
+fn move_to() {
+    with_map::<usize, usize>(|map| {
+        let (active, idle) = (map(), map());
+        active.pin().insert(1, 10);
+        idle.pin().insert(2, 20);
+
+        assert_eq!(active.pin().move_to(&idle, &1), Ok(&10));
+        assert_eq!(active.pin().get(&1), None);
+        assert_eq!(idle.pin().get(&1), Some(&10));
+        assert_eq!((active.len(), idle.len()), (0, 2));
+
+        active.pin().insert(2, 30);
+        assert_eq!(
+            active.pin().move_to(&idle, &2),
+            Err(MoveError::Occupied(&20))
+        );
+        assert_eq!(active.pin().get(&2), Some(&30));
+        assert_eq!(active.pin().move_to(&idle, &3), Err(MoveError::NotFound));
+        assert_eq!(active.pin().move_to(&active, &2), Ok(&30));
+
+        // Move enough entries to force a resize of both maps.
+        for i in 100..1100 {
+            active.pin().insert(i, i);
+        }
+        for i in 100..1100 {
+            assert_eq!(active.pin().move_to(&idle, &i), Ok(&i));
+        }
+
+        assert_eq!((active.len(), idle.len()), (1, 1002));
+        for i in 100..1100 {
+            assert_eq!(active.pin().get(&i), None);
+            assert_eq!(idle.pin().get(&i), Some(&i));
+        }
+        active.check_invariants();
+        idle.check_invariants();
+    });
+}
+
+#[test]
+fn move_to_concurrent() {
+    const KEYS: usize = 64;
+
+    with_map::<usize, usize>(|map| {
+        let maps = [map(), map()];
+        for i in 0..KEYS {
+            maps[0].pin().insert(i, i);
+        }
+
+        // Move every entry back and forth, racing in both directions.
+        thread::scope(|s| {
+            for (from, to) in [(0, 1), (1, 0)] {
+                let maps = &maps;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        for i in 0..KEYS {
+                            match maps[from].pin().move_to(&maps[to], &i) {
+                                Ok(&value) => assert_eq!(value, i),
+                                Err(MoveError::NotFound) => {}
+                                Err(MoveError::Occupied(_)) => panic!("entry was duplicated"),
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(maps[0].len() + maps[1].len(), KEYS);
+        for i in 0..KEYS {
+            let found = maps.each_ref().map(|map| map.pin().get(&i).copied());
+            assert!(matches!(found, [Some(v), None] | [None, Some(v)] if v == i));
+        }
+        maps.iter().for_each(HashMap::check_invariants);
+    });
+}
+
 #[test]
 fn concurrent_insert() {
     with_map::<usize, usize>(|map| {
@@ -517,6 +936,34 @@ fn concurrent_resize_and_get() {
     });
 }
 
+#[test]
+fn concurrent_resize_abort() {
+    with_map::<usize, usize>(|map| {
+        let map = Arc::new(map());
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        // Insert from several threads to trigger resizes that other threads wait on. Note that
+        // resizes are only aborted if the new table overflows, which `cfg(papaya_fault)` forces.
+        for t in 0..8 {
+            let (map, tx) = (map.clone(), tx.clone());
+            std::thread::spawn(move || {
+                for i in 0..4096 {
+                    map.pin().insert(t * 4096 + i, i);
+                }
+                tx.send(()).unwrap();
+            });
+        }
+
+        // Threads waiting on an aborted resize are woken to retry in the new table.
+        for _ in 0..8 {
+            rx.recv_timeout(std::time::Duration::from_secs(60))
+                .expect("thread waiting on an aborted resize was never woken");
+        }
+
+        assert_eq!(map.len(), 8 * 4096);
+    });
+}
+
 #[test]
 fn current_kv_dropped() {
     let dropped1 = Arc::new(0);
@@ -729,6 +1176,83 @@ fn len() {
     });
 }
 
+#[test]
+fn len_counter_modes() {
+    let len = if cfg!(miri) { 100 } else { 10_000 };
+
+    for mode in [CounterMode::Exact, CounterMode::Sharded(3)] {
+        let map = HashMap::builder().counter_mode(mode).build();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let map = &map;
+                s.spawn(move || {
+                    for i in (t..len).step_by(4) {
+                        map.pin().insert(i, i + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(map.len(), len);
+
+        for i in 0..len / 2 {
+            map.pin().remove(&i);
+        }
+        assert_eq!(map.len(), len - len / 2);
+    }
+
+    // The length is an estimate when the counter is disabled.
+    let map = HashMap::builder()
+        .counter_mode(CounterMode::Disabled)
+        .build();
+    assert_eq!(map.len(), 0);
+    for i in 0..len {
+        map.pin().insert(i, i + 1);
+    }
+    assert!(map.len() > len / 2 && map.len() < len * 2);
+    assert!(map.pin().iter().count() == len);
+}
+
+#[test]
+fn is_empty_counter_disabled() {
+    // Places each key at the index of its value.
+    #[derive(Default)]
+    struct IndexHasher(u64);
+
+    impl Hasher for IndexHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unreachable!()
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.0 = i as u64;
+        }
+    }
+
+    let map = HashMap::builder()
+        .counter_mode(CounterMode::Disabled)
+        .hasher(BuildHasherDefault::<IndexHasher>::default())
+        .capacity(1 << 14)
+        .build();
+    assert!(map.is_empty());
+
+    // Entries beyond the start of the table are accounted for.
+    for i in 4096_usize..4160 {
+        map.pin().insert(i, i);
+        assert!(!map.is_empty());
+    }
+    assert_ne!(map.len(), 0);
+
+    for i in 4096..4160 {
+        assert!(!map.is_empty());
+        map.pin().remove(&i);
+    }
+    assert!(map.is_empty());
+}
+
 #[test]
 fn iter() {
     if cfg!(papaya_stress) {
@@ -749,6 +1273,76 @@ fn iter() {
     });
 }
 
+#[test]
+fn scan() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        assert_eq!(map.scan(0, 10), (Vec::new(), 0));
+
+        let len = if cfg!(miri) { 100 } else { 10_000 };
+        for i in 0..len {
+            assert_eq!(map.pin().insert(i, i + 1), None);
+        }
+
+        // Without resizes, every entry is returned exactly once.
+        let mut got = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (entries, next) = map.scan(cursor, 100);
+            got.extend(entries.into_iter().map(|(&k, &v)| (k, v)));
+
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        got.sort();
+        let v: Vec<_> = (0..len).map(|i| (i, i + 1)).collect();
+        assert_eq!(v, got);
+    });
+}
+
+#[test]
+fn scan_resize() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let len = if cfg!(miri) { 100 } else { 1000 };
+        for i in 0..len {
+            assert_eq!(map.pin().insert(i, i), None);
+        }
+
+        let mut got = Vec::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (entries, next) = map.scan(cursor, 10);
+            got.extend(entries.into_iter().map(|(&k, _)| k));
+
+            // Grow the table between calls, removing the new keys again later.
+            calls += 1;
+            if calls % 8 == 0 {
+                for i in 0..len {
+                    map.pin().insert(len * calls + i, 0);
+                }
+            } else if calls % 8 == 4 {
+                map.pin().retain(|&k, _| k < len);
+            }
+
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        // Every original entry is returned at least once.
+        got.retain(|&k| k < len);
+        got.sort();
+        got.dedup();
+        assert_eq!(got, (0..len).collect::<Vec<_>>());
+    });
+}
+
 #[test]
 fn retain_empty() {
     with_map::<usize, usize>(|map| {
@@ -798,53 +1392,264 @@ fn retain_some() {
 }
 
 #[test]
-fn mixed() {
-    const LEN: usize = if cfg!(miri) { 48 } else { 1024 };
+fn extract_if() {
     with_map::<usize, usize>(|map| {
         let map = map();
-        assert!(map.pin().get(&100).is_none());
-        map.pin().insert(100, 101);
-        assert_eq!(map.pin().get(&100), Some(&101));
-        map.pin().update(100, |x| x + 2);
-        assert_eq!(map.pin().get(&100), Some(&103));
-
-        assert!(map.pin().get(&200).is_none());
-        map.pin().insert(200, 202);
-        assert_eq!(map.pin().get(&200), Some(&202));
-
-        assert!(map.pin().get(&300).is_none());
-
-        assert_eq!(map.pin().remove(&100), Some(&103));
-        assert_eq!(map.pin().remove(&200), Some(&202));
-        assert!(map.pin().remove(&300).is_none());
-
-        assert!(map.pin().get(&100).is_none());
-        assert!(map.pin().get(&200).is_none());
-        assert!(map.pin().get(&300).is_none());
-
-        for i in 0..LEN {
-            assert_eq!(map.pin().insert(i, i + 1), None);
-        }
+        assert_eq!(map.extract_if(|_, _| true).next(), None);
 
-        for i in 0..LEN {
-            assert_eq!(map.pin().get(&i), Some(&(i + 1)));
+        let len = if cfg!(miri) { 100 } else { 1000 };
+        for i in 0..len {
+            map.pin().insert(i, i + 1);
         }
 
-        for i in 0..LEN {
-            assert_eq!(map.pin().update(i, |i| i - 1), Some(&i));
-        }
+        let mut got: Vec<_> = map
+            .pin()
+            .extract_if(|&k, _| k % 2 == 0)
+            .map(|(&k, &v)| (k, v))
+            .collect();
+        got.sort();
+        assert_eq!(
+            got,
+            (0..len).step_by(2).map(|i| (i, i + 1)).collect::<Vec<_>>()
+        );
 
-        for i in 0..LEN {
-            assert_eq!(map.pin().get(&i), Some(&i));
+        assert_eq!(map.len(), len / 2);
+        for i in 0..len {
+            assert_eq!(map.pin().get(&i).is_some(), i % 2 == 1);
         }
+    });
+}
 
-        for i in 0..LEN {
-            assert_eq!(map.pin().remove(&i), Some(&i));
+#[test]
+fn extract_if_lazy() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        for i in 0..100 {
+            map.pin().insert(i, i);
         }
 
-        for i in 0..LEN {
-            assert_eq!(map.pin().get(&i), None);
-        }
+        // Entries are only removed as the iterator advances.
+        let (first, second) = {
+            let mut extract = map.extract_if(|_, _| true);
+            let (&first, _) = extract.next().unwrap();
+            let (&second, _) = extract.next().unwrap();
+            (first, second)
+        };
+
+        assert_ne!(first, second);
+        assert_eq!(map.len(), 98);
+        assert_eq!(map.pin().get(&first), None);
+        assert_eq!(map.pin().get(&second), None);
+    });
+}
+
+#[test]
+fn extract_if_concurrent() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let len = if cfg!(miri) { 100 } else { 10_000 };
+        for i in 0..len {
+            map.pin().insert(i, i);
+        }
+
+        let mut extracted = thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        map.pin()
+                            .extract_if(|&k, _| k % 2 == 0)
+                            .map(|(&k, _)| k)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        // Every matching entry was removed by exactly one thread.
+        extracted.sort();
+        assert_eq!(extracted, (0..len).step_by(2).collect::<Vec<_>>());
+        assert_eq!(map.len(), len / 2);
+    });
+}
+
+#[test]
+fn pop_any() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        assert_eq!(map.pin().pop_any(), None);
+
+        let len = if cfg!(miri) { 100 } else { 1000 };
+        for i in 0..len {
+            map.pin().insert(i, i + 1);
+        }
+
+        // Every entry is removed exactly once.
+        let mut got = Vec::new();
+        while let Some((&k, &v)) = map.pin().pop_any() {
+            assert_eq!(v, k + 1);
+            got.push(k);
+        }
+
+        got.sort();
+        assert_eq!(got, (0..len).collect::<Vec<_>>());
+        assert!(map.pin().is_empty());
+        assert_eq!(map.pin().pop_any(), None);
+    });
+}
+
+#[test]
+fn pop_any_concurrent() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let len = if cfg!(miri) { 100 } else { 10_000 };
+        for i in 0..len {
+            map.pin().insert(i, i);
+        }
+
+        let mut popped = thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut popped = Vec::new();
+                        while let Some((&k, &v)) = map.pin().pop_any() {
+                            assert_eq!(k, v);
+                            popped.push(k);
+                        }
+                        popped
+                    })
+                })
+                .collect();
+
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        // Every entry was removed by exactly one thread.
+        popped.sort();
+        assert_eq!(popped, (0..len).collect::<Vec<_>>());
+        assert!(map.pin().is_empty());
+    });
+}
+
+#[test]
+fn random_entry() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        assert_eq!(map.pin().random_entry(), None);
+
+        map.pin().insert(1, 2);
+        assert_eq!(map.pin().random_entry(), Some((&1, &2)));
+
+        for i in 2..100 {
+            map.pin().insert(i, i + 1);
+        }
+
+        // Every entry is eventually picked.
+        let mut seen = [false; 100];
+        let mut picks = 0;
+        while seen[1..].iter().any(|&seen| !seen) {
+            let (&k, &v) = map.pin().random_entry().unwrap();
+            assert_eq!(v, k + 1);
+            seen[k] = true;
+
+            picks += 1;
+            assert!(picks < 100_000);
+        }
+
+        // Removed entries are never picked, even in a sparse table.
+        map.pin().retain(|&k, _| k == 50);
+        for _ in 0..100 {
+            assert_eq!(map.pin().random_entry(), Some((&50, &51)));
+        }
+    });
+}
+
+#[test]
+fn sample() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        assert!(map.pin().sample(10).is_empty());
+
+        for i in 0..100 {
+            map.pin().insert(i, i + 1);
+        }
+
+        assert!(map.pin().sample(0).is_empty());
+
+        // Sampled entries are distinct.
+        let mut got: Vec<_> = map.pin().sample(30).into_iter().map(|(&k, _)| k).collect();
+        assert_eq!(got.len(), 30);
+        got.sort();
+        got.dedup();
+        assert_eq!(got.len(), 30);
+
+        // Every entry is returned if there are fewer than requested.
+        let mut got: Vec<_> = map
+            .pin()
+            .sample(1000)
+            .into_iter()
+            .map(|(&k, &v)| (k, v))
+            .collect();
+        got.sort();
+        assert_eq!(got, (0..100).map(|i| (i, i + 1)).collect::<Vec<_>>());
+    });
+}
+
+#[test]
+fn mixed() {
+    const LEN: usize = if cfg!(miri) { 48 } else { 1024 };
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        assert!(map.pin().get(&100).is_none());
+        map.pin().insert(100, 101);
+        assert_eq!(map.pin().get(&100), Some(&101));
+        map.pin().update(100, |x| x + 2);
+        assert_eq!(map.pin().get(&100), Some(&103));
+
+        assert!(map.pin().get(&200).is_none());
+        map.pin().insert(200, 202);
+        assert_eq!(map.pin().get(&200), Some(&202));
+
+        assert!(map.pin().get(&300).is_none());
+
+        assert_eq!(map.pin().remove(&100), Some(&103));
+        assert_eq!(map.pin().remove(&200), Some(&202));
+        assert!(map.pin().remove(&300).is_none());
+
+        assert!(map.pin().get(&100).is_none());
+        assert!(map.pin().get(&200).is_none());
+        assert!(map.pin().get(&300).is_none());
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().insert(i, i + 1), None);
+        }
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().get(&i), Some(&(i + 1)));
+        }
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().update(i, |i| i - 1), Some(&i));
+        }
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().get(&i), Some(&i));
+        }
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().remove(&i), Some(&i));
+        }
+
+        for i in 0..LEN {
+            assert_eq!(map.pin().get(&i), None);
+        }
 
         for i in 0..(LEN * 2) {
             assert_eq!(map.pin().insert(i, i + 1), None);
@@ -911,3 +1716,790 @@ mod hasher {
         check::<BuildHasherDefault<MaxHasher>>();
     }
 }
+
+#[test]
+fn int_hash_map() {
+    use papaya_alloy::{IntHashMap, IntHashSet};
+
+    let map: IntHashMap<u64> = IntHashMap::default();
+    for i in 0..1000 {
+        map.pin().insert(i, i * 2);
+    }
+    for i in 0..1000 {
+        assert_eq!(map.pin().get(&i), Some(&(i * 2)));
+    }
+    assert_eq!(map.len(), 1000);
+
+    let set: IntHashSet = IntHashSet::default();
+    for i in (0..1000).map(|i| i << 32) {
+        assert!(set.pin().insert(i));
+    }
+    assert_eq!(set.len(), 1000);
+}
+
+#[test]
+fn identity_hasher() {
+    use papaya_alloy::PreHashed;
+
+    // Distinct keys produce distinct hashes.
+    let hashes = (0..1000_u64)
+        .map(|i| PreHashed.hash_one(i))
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(hashes.len(), 1000);
+
+    // Sequential keys still produce varied tags.
+    let tags = (0..1000_u64)
+        .map(|i| PreHashed.hash_one(i) >> 57)
+        .collect::<std::collections::HashSet<_>>();
+    assert!(tags.len() > 64);
+
+    // Keys that differ only in their high bits are spread across the index bits of
+    // a small table, see `meta::h1`.
+    for shift in [8, 16, 24, 32, 40, 48, 56] {
+        let slots = (0..256_u64)
+            .map(|i| PreHashed.hash_one(i << shift) & 0xFF)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(slots.len() > 128, "{shift}: {}", slots.len());
+    }
+}
+
+#[test]
+fn int_hash_map_high_bits() {
+    use papaya_alloy::IntHashMap;
+
+    // Keys that differ only in bits above the index bits of the table do not all probe
+    // from the same slot, which would otherwise prevent the table from growing.
+    let map: IntHashMap<u64> = IntHashMap::default();
+    for i in 1..256 {
+        for shift in [24, 40, 56] {
+            map.pin().insert(i << shift, i);
+        }
+    }
+    for i in 1..256 {
+        for shift in [24, 40, 56] {
+            assert_eq!(map.pin().get(&(i << shift)), Some(&i));
+        }
+    }
+    assert_eq!(map.len(), 255 * 3);
+}
+
+#[test]
+fn with_hash() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        for i in 0..1000 {
+            let hash = map.hasher().hash_one(i);
+            assert_eq!(guard.insert_with_hash(hash, i, i), None);
+        }
+
+        for i in 0..1000 {
+            let hash = map.hasher().hash_one(i);
+            assert_eq!(guard.get(&i), Some(&i));
+            assert_eq!(guard.get_with_hash(hash, |k| *k == i), Some((&i, &i)));
+            assert!(guard.iter_with_hash(hash).any(|(k, _)| *k == i));
+        }
+
+        for i in 0..500 {
+            let hash = map.hasher().hash_one(i);
+            assert_eq!(guard.remove_with_hash(hash, |k| *k == i), Some((&i, &i)));
+            assert_eq!(guard.get_with_hash(hash, |k| *k == i), None);
+        }
+
+        assert_eq!(guard.len(), 500);
+    });
+}
+
+#[test]
+fn stats() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        let stats = map.stats();
+        assert_eq!(stats.capacity, 0);
+        assert_eq!(stats.tables, 0);
+
+        for i in 0..1000 {
+            map.pin().insert(i, i);
+        }
+        for i in 0..100 {
+            map.pin().remove(&i);
+        }
+
+        let stats = map.stats();
+        assert!(stats.capacity >= 900);
+        assert!(stats.tables >= 1);
+        assert!(stats.bytes > 0);
+        assert_eq!(stats.probe_lengths.len(), stats.probe_limit + 1);
+        assert_eq!(stats.probe_lengths.iter().sum::<usize>(), stats.live);
+        assert!(stats.load_factor() > 0.0 && stats.load_factor() <= 1.0);
+
+        // All entries are in the root table once any resize is complete.
+        if stats.resize.is_none() {
+            assert_eq!(stats.live, 900);
+        }
+    });
+}
+
+#[test]
+fn stats_resize() {
+    let map = HashMap::builder()
+        .resize_mode(ResizeMode::Incremental(1))
+        .build();
+
+    // Insert until a resize is triggered, which copies a single entry per write.
+    let mut i = 0;
+    let stats = loop {
+        map.pin().insert(i, i);
+        i += 1;
+
+        let stats = map.stats();
+        if stats.resize.is_some() {
+            break stats;
+        }
+    };
+
+    assert_eq!(stats.resize, Some(ResizeStatus::Pending));
+    assert_eq!(stats.tables, 2);
+    assert!(stats.copied < stats.capacity);
+    assert!(stats.claimed < stats.capacity);
+    assert!(stats.copied <= stats.claimed);
+}
+
+#[test]
+fn snapshot() {
+    with_snapshot_map::<usize, usize>(|map| {
+        let map = map();
+        assert!(map.snapshot().is_empty());
+
+        let len = if cfg!(miri) { 100 } else { 1000 };
+        for i in 0..len {
+            map.pin().insert(i, i);
+        }
+
+        let snapshot = map.snapshot();
+
+        // Modify every entry, forcing a resize.
+        for i in 0..len {
+            if i % 2 == 0 {
+                map.pin().remove(&i);
+            } else {
+                map.pin().insert(i, i + 1);
+            }
+        }
+        for i in len..len * 2 {
+            map.pin().insert(i, i);
+        }
+
+        // The snapshot is unchanged.
+        assert_eq!(snapshot.len(), len);
+        for i in 0..len {
+            assert_eq!(snapshot.get(&i), Some(&i));
+        }
+        assert_eq!(snapshot.get(&len), None);
+
+        let mut got: Vec<_> = snapshot.iter().map(|(&k, &v)| (k, v)).collect();
+        got.sort();
+        assert_eq!(got, (0..len).map(|i| (i, i)).collect::<Vec<_>>());
+
+        // A new snapshot reflects the changes.
+        let snapshot = map.snapshot();
+        assert_eq!(snapshot.len(), len / 2 + len);
+        assert_eq!(snapshot.get(&0), None);
+        assert_eq!(snapshot.get(&1), Some(&2));
+        assert_eq!(snapshot.get(&len), Some(&len));
+    });
+}
+
+#[test]
+#[should_panic(expected = "snapshots are not enabled")]
+fn snapshot_disabled() {
+    let map = HashMap::<usize, usize>::new();
+    map.snapshot();
+}
+
+#[test]
+fn share_clone() {
+    // A value that does not implement `Clone`.
+    #[derive(Debug, PartialEq)]
+    struct Value(usize);
+
+    with_snapshot_map::<usize, Value>(|map| {
+        let map = map();
+        assert!(map.share_clone().is_empty());
+
+        // Advance the epoch of the original map before inserting.
+        for _ in 0..3 {
+            map.snapshot();
+        }
+
+        let len = if cfg!(miri) { 100 } else { 1000 };
+        for i in 0..len {
+            map.pin().insert(i, Value(i));
+        }
+
+        let copy = map.share_clone();
+        assert_eq!(copy.len(), len);
+
+        // Writes to either map are not visible to the other.
+        for i in 0..len {
+            if i % 2 == 0 {
+                map.pin().remove(&i);
+            } else {
+                copy.pin().insert(i, Value(i + 1));
+            }
+        }
+        copy.pin().insert(len, Value(len));
+
+        assert_eq!(map.len(), len / 2);
+        assert_eq!(copy.len(), len + 1);
+        for i in 0..len {
+            if i % 2 == 0 {
+                assert_eq!(map.pin().get(&i), None);
+                assert_eq!(copy.pin().get(&i), Some(&Value(i)));
+            } else {
+                assert_eq!(map.pin().get(&i), Some(&Value(i)));
+                assert_eq!(copy.pin().get(&i), Some(&Value(i + 1)));
+            }
+        }
+
+        // Shared entries are part of snapshots of the copy, and of copies of the copy.
+        let snapshot = copy.snapshot();
+        assert_eq!(snapshot.len(), len + 1);
+        assert_eq!(snapshot.get(&0), Some(&Value(0)));
+
+        let copy = copy.share_clone();
+        assert_eq!(copy.snapshot().len(), len + 1);
+        assert_eq!(copy.pin().get(&1), Some(&Value(2)));
+    });
+}
+
+#[test]
+fn share_clone_removed() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        for i in 0..1000 {
+            map.pin().insert(i, i);
+        }
+        for i in (0..1000).step_by(2) {
+            map.pin().remove(&i);
+        }
+
+        // Probe chains through removed entries are preserved.
+        let copy = map.share_clone();
+        assert_eq!(copy.len(), 500);
+        for i in 0..1000 {
+            assert_eq!(copy.pin().get(&i), (i % 2 == 1).then_some(&i));
+        }
+    });
+}
+
+#[test]
+fn share_clone_concurrent() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        for i in 0..1000 {
+            map.pin().insert(i, i);
+        }
+
+        // Entries inserted before the copy are always shared, even if the table is
+        // concurrently resized.
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1000..4000 {
+                    map.pin().insert(i, i);
+                }
+            });
+
+            for _ in 0..8 {
+                let copy = map.share_clone();
+                for i in 0..1000 {
+                    assert_eq!(copy.pin().get(&i), Some(&i));
+                }
+                assert_eq!(copy.len(), copy.pin().iter().count());
+            }
+        });
+    });
+}
+
+#[test]
+fn watch() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let watcher = map.watch(1);
+        let changes = || {
+            std::iter::from_fn(|| watcher.try_recv())
+                .map(|change| {
+                    assert_eq!(change.key(), &1);
+                    (
+                        change.kind(),
+                        change.old_value().copied(),
+                        change.new_value().copied(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Changes to other keys are not received.
+        for i in 2..100 {
+            map.pin().insert(i, i);
+        }
+        assert_eq!(changes(), []);
+
+        map.pin().insert(1, 0);
+        map.pin().update(1, |v| v + 1);
+        map.pin().update_or_insert(1, |v| v + 1, 0);
+        map.pin().remove(&1);
+        assert_eq!(
+            changes(),
+            [
+                (ChangeKind::Inserted, None, Some(0)),
+                (ChangeKind::Replaced, Some(0), Some(1)),
+                (ChangeKind::Replaced, Some(1), Some(2)),
+                (ChangeKind::Removed, Some(2), None),
+            ]
+        );
+
+        map.pin().compute(1, |_| Operation::<_, ()>::Insert(3));
+        map.pin().compute(1, |_| Operation::<_, ()>::Remove);
+        map.pin().insert(1, 4);
+        assert_eq!(map.pin().remove_if(&1, |_, &v| v == 0), Err((&1, &4)));
+        assert_eq!(map.pin().remove_if(&1, |_, &v| v == 4), Ok(Some((&1, &4))));
+        assert_eq!(
+            changes(),
+            [
+                (ChangeKind::Inserted, None, Some(3)),
+                (ChangeKind::Removed, Some(3), None),
+                (ChangeKind::Inserted, None, Some(4)),
+                (ChangeKind::Removed, Some(4), None),
+            ]
+        );
+
+        map.pin().insert(1, 5);
+        map.pin().retain(|&k, _| k != 1);
+        map.pin().insert(1, 6);
+        map.pin().clear();
+        assert_eq!(
+            changes(),
+            [
+                (ChangeKind::Inserted, None, Some(5)),
+                (ChangeKind::Removed, Some(5), None),
+                (ChangeKind::Inserted, None, Some(6)),
+                (ChangeKind::Removed, Some(6), None),
+            ]
+        );
+
+        // Dropping the map disconnects the watcher.
+        map.pin().insert(1, 7);
+        drop(map);
+        assert_eq!(watcher.recv().unwrap().new_value(), Some(&7));
+        assert!(watcher.recv().is_none());
+    });
+}
+
+#[test]
+fn watch_all() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let len = if cfg!(miri) { 100 } else { 1000 };
+
+        let changes = map.watch_all();
+        let dropped = map.watch_all();
+        drop(dropped);
+
+        for i in 0..len {
+            map.pin().insert(i, i);
+        }
+        map.pin().retain(|&k, _| k % 2 == 0);
+        map.pin().clear();
+
+        let mut inserted = vec![false; len];
+        let mut removed = vec![false; len];
+        while let Some(change) = changes.try_recv() {
+            let key = *change.key();
+            match change.kind() {
+                ChangeKind::Inserted => assert!(!std::mem::replace(&mut inserted[key], true)),
+                ChangeKind::Removed => assert!(!std::mem::replace(&mut removed[key], true)),
+                ChangeKind::Replaced => panic!("unexpected replace of {key}"),
+            }
+        }
+
+        assert!(inserted.iter().all(|&x| x));
+        assert!(removed.iter().all(|&x| x));
+    });
+}
+
+#[test]
+fn watch_many() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let len = if cfg!(miri) { 100 } else { 1000 };
+
+        let mut watchers = (0..len).map(|i| Some(map.watch(i))).collect::<Vec<_>>();
+
+        // Drop every odd watcher.
+        for watcher in watchers.iter_mut().skip(1).step_by(2) {
+            *watcher = None;
+        }
+
+        for i in 0..len {
+            map.pin().insert(i, i);
+        }
+
+        for (i, watcher) in watchers.iter().enumerate() {
+            let Some(watcher) = watcher else { continue };
+
+            let change = watcher.try_recv().unwrap();
+            assert_eq!(change.key(), &i);
+            assert_eq!(change.new_value(), Some(&i));
+            assert!(watcher.try_recv().is_none());
+        }
+    });
+}
+
+#[test]
+fn watch_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let watcher = map.watch(1);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                map.pin().insert(1, 1);
+            });
+
+            let change = runtime.block_on(watcher.recv_async()).unwrap();
+            assert_eq!(change.new_value(), Some(&1));
+        });
+    });
+}
+
+#[test]
+fn wait_for() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        // The key is already present.
+        map.pin().insert(1, 1);
+        assert_eq!(map.wait_for(&1), &1);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+
+                // Writes to other keys should not wake the waiter.
+                for i in 3..100 {
+                    map.pin().insert(i, i);
+                }
+
+                map.pin().update_or_insert(2, |v| v + 1, 2);
+            });
+
+            assert_eq!(map.wait_for(&2), &2);
+        });
+    });
+}
+
+#[test]
+fn wait_for_timeout() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        assert_eq!(map.wait_for_timeout(&1, Duration::from_millis(10)), None);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                map.pin().compute(1, |_| Operation::<_, ()>::Insert(1));
+            });
+
+            assert_eq!(map.wait_for_timeout(&1, Duration::from_secs(60)), Some(&1));
+        });
+
+        assert_eq!(map.wait_for_timeout(&1, Duration::ZERO), Some(&1));
+    });
+}
+
+#[test]
+fn wait_for_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    with_map::<usize, usize>(|map| {
+        let map = map();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                map.pin().insert(1, 1);
+            });
+
+            assert_eq!(runtime.block_on(map.wait_for_async(&1)), &1);
+        });
+
+        // The future can be spawned on a multi-threaded runtime.
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&map.wait_for_async(&1));
+    });
+}
+
+#[test]
+fn wait_for_many() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let threads = 8;
+
+        thread::scope(|s| {
+            for i in 0..threads {
+                let map = &map;
+                s.spawn(move || {
+                    // Waiters that timed out are not woken.
+                    assert_eq!(map.wait_for_timeout(&(i + threads), Duration::ZERO), None);
+
+                    assert_eq!(map.wait_for(&i), &i);
+                });
+            }
+
+            thread::sleep(Duration::from_millis(10));
+            for i in 0..threads {
+                map.pin().insert(i, i);
+            }
+        });
+    });
+}
+
+#[test]
+fn change_log() {
+    with_logged_map::<usize, usize>(1 << 12, |map| {
+        let map = map();
+
+        map.pin().insert(1, 1);
+        map.pin().insert(2, 2);
+        map.pin().update(1, |v| v + 1);
+        assert!(map.pin().try_insert(2, 3).is_err());
+        map.pin().remove(&2);
+        map.pin().compute(3, |_| Operation::<_, ()>::Insert(3));
+        map.pin().retain(|&k, _| k != 1);
+
+        let changes: Vec<_> = map.changes_since(0).unwrap().collect();
+        assert!(changes.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|(_, change)| (change.kind(), *change.key(), change.new_value().copied()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeKind::Inserted, 1, Some(1)),
+                (ChangeKind::Inserted, 2, Some(2)),
+                (ChangeKind::Replaced, 1, Some(2)),
+                (ChangeKind::Removed, 2, None),
+                (ChangeKind::Inserted, 3, Some(3)),
+                (ChangeKind::Removed, 1, None),
+            ]
+        );
+
+        // Apply the changes in two batches.
+        let follower = HashMap::new();
+        let seq = follower.apply_changes(map.changes_since(0).unwrap().take(3));
+        assert_eq!(follower.pin().get(&1), Some(&2));
+
+        let last = follower.apply_changes(map.changes_since(seq.unwrap()).unwrap());
+        assert_eq!(follower, map);
+        assert_eq!(map.changes_since(last.unwrap()).unwrap().count(), 0);
+
+        // Clearing the map is recorded as removals.
+        for i in 0..1000 {
+            map.pin().insert(i, i);
+        }
+        map.pin().clear();
+
+        let last = follower.apply_changes(map.changes_since(last.unwrap()).unwrap());
+        assert!(last.is_some());
+        assert!(follower.is_empty());
+    });
+}
+
+#[test]
+fn change_log_lagged() {
+    with_logged_map::<usize, usize>(4, |map| {
+        let map = map();
+
+        for i in 0..16 {
+            map.pin().insert(i, i);
+        }
+
+        assert_eq!(map.changes_since(0).unwrap_err(), LaggedError);
+
+        // Only the most recent changes are retained. Note that sequence numbers may be
+        // skipped, so the oldest retained change is not necessarily the 12th.
+        let seq = (0..).find(|&seq| map.changes_since(seq).is_ok()).unwrap();
+        assert!(seq >= 12);
+
+        let keys: Vec<_> = map
+            .changes_since(seq)
+            .unwrap()
+            .map(|(_, change)| *change.key())
+            .collect();
+        assert!(!keys.is_empty() && keys.len() <= 4);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(keys.last(), Some(&15));
+    });
+}
+
+#[test]
+#[should_panic(expected = "the change log is not enabled")]
+fn change_log_disabled() {
+    let map = HashMap::<usize, usize>::new();
+    let _ = map.changes_since(0);
+}
+
+#[test]
+fn heap_size() {
+    with_map::<usize, String>(|map| {
+        let map = map();
+        let empty = map.heap_size();
+
+        for i in 0..100 {
+            map.pin().insert(i, String::with_capacity(64));
+        }
+
+        // Every value owns its capacity, on top of the tables and entries.
+        let size = map.deep_heap_size();
+        assert!(size >= map.heap_size() + 100 * 64);
+        assert!(map.heap_size() >= map.stats().bytes);
+        assert!(map.heap_size() > empty);
+
+        map.pin().clear();
+        assert!(map.deep_heap_size() < size);
+    });
+}
+
+#[test]
+fn heap_size_unbounded() {
+    // Keys and values need not implement `HeapSize`.
+    struct Opaque(#[allow(dead_code)] Vec<u8>);
+
+    with_map::<usize, Opaque>(|map| {
+        let map = map();
+        let empty = map.heap_size();
+
+        for i in 0..100 {
+            map.pin().insert(i, Opaque(Vec::with_capacity(64)));
+        }
+        assert!(map.heap_size() > empty);
+        assert!(map.heap_size() >= map.stats().bytes);
+    });
+}
+
+#[test]
+fn check_invariants() {
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        map.check_invariants();
+
+        for i in 0..1000 {
+            map.pin().insert(i, i);
+        }
+        map.check_invariants();
+
+        for i in 0..500 {
+            map.pin().remove(&i);
+        }
+        map.check_invariants();
+
+        map.pin().clear();
+        map.check_invariants();
+    });
+}
+
+#[test]
+#[cfg(feature = "debug_hash")]
+#[should_panic(expected = "equivalent keys produced different hashes")]
+fn debug_hash() {
+    use papaya_alloy::{Equivalent, PreHashed};
+    use std::hash::{BuildHasher, Hash, Hasher};
+
+    // Find a key with a different hash than `0`, but the same position in the table
+    // and hash metadata.
+    let inconsistent = (1_u64..)
+        .find(|&key| {
+            let hash = PreHashed.hash_one(key);
+            hash & 0xFFFF == 0 && hash >> 57 == PreHashed.hash_one(0_u64) >> 57
+        })
+        .unwrap();
+
+    // A key that is equivalent to `0` but hashes differently.
+    struct Inconsistent(u64);
+
+    impl Hash for Inconsistent {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.hash(state);
+        }
+    }
+
+    impl Equivalent<u64> for Inconsistent {
+        fn equivalent(&self, key: &u64) -> bool {
+            *key == 0
+        }
+    }
+
+    let map = HashMap::<u64, u64, _>::builder().hasher(PreHashed).build();
+
+    map.pin().insert(0, 0);
+    map.pin().get(&Inconsistent(inconsistent));
+}
+
+#[test]
+fn observer() {
+    use papaya_alloy::MapObserver;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Events {
+        started: AtomicUsize,
+        promoted: AtomicUsize,
+        joined: AtomicUsize,
+    }
+
+    impl MapObserver for Events {
+        fn resize_started(&self, old_capacity: usize, new_capacity: usize) {
+            assert!(new_capacity >= old_capacity);
+            self.started.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn resize_promoted(&self, _capacity: usize) {
+            self.promoted.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn copy_joined(&self, _capacity: usize) {
+            self.joined.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let events = Arc::new(Events::default());
+    let map = HashMap::builder().observer(events.clone()).build();
+
+    for i in 0..10_000 {
+        map.pin().insert(i, i);
+    }
+
+    // Complete any in-progress resize.
+    assert_eq!(map.pin().iter().count(), 10_000);
+
+    let started = events.started.load(Ordering::Relaxed);
+    assert!(started > 0);
+    let promoted = events.promoted.load(Ordering::Relaxed);
+    assert!(promoted > 0 && promoted <= started);
+
+    // A single thread joins each resize exactly once, no matter how many chunks it copies.
+    assert_eq!(events.joined.load(Ordering::Relaxed), started);
+}
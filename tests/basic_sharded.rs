@@ -0,0 +1,180 @@
+use papaya_alloy::{MoveError, Operation, ShardedHashMap};
+
+use std::sync::Arc;
+
+mod common;
+use common::with_sharded_map;
+
+#[test]
+fn new() {
+    with_sharded_map::<usize, usize>(|map| drop(map()));
+}
+
+#[test]
+fn shards() {
+    let map: ShardedHashMap<usize, usize> = ShardedHashMap::builder().shards(5).build();
+    assert_eq!(map.shards(), 8);
+
+    let map: ShardedHashMap<usize, usize> = ShardedHashMap::builder().shards(0).build();
+    assert_eq!(map.shards(), 1);
+}
+
+#[test]
+fn insert_and_get() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        for i in 0..1000 {
+            assert_eq!(guard.insert(i, i), None);
+        }
+
+        for i in 0..1000 {
+            assert_eq!(guard.get(&i), Some(&i));
+            assert_eq!(guard.get_key_value(&i), Some((&i, &i)));
+        }
+
+        assert_eq!(guard.insert(0, 1), Some(&0));
+        assert_eq!(guard.get(&0), Some(&1));
+        assert_eq!(guard.get(&1000), None);
+        assert_eq!(guard.len(), 1000);
+    });
+}
+
+#[test]
+fn remove() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        for i in 0..100 {
+            guard.insert(i, i);
+        }
+
+        for i in 0..50 {
+            assert_eq!(guard.remove(&i), Some(&i));
+        }
+
+        assert_eq!(guard.remove(&0), None);
+        assert_eq!(guard.remove_entry(&50), Some((&50, &50)));
+        assert_eq!(guard.remove_if(&51, |_, v| *v == 0), Err((&51, &51)));
+        assert_eq!(guard.remove_if(&51, |_, v| *v == 51), Ok(Some((&51, &51))));
+        assert_eq!(guard.len(), 48);
+    });
+}
+
+#[test]
+fn rename() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        for i in 0..100 {
+            guard.insert(i, i);
+        }
+
+        // Keys are moved between shards.
+        for i in 0..100 {
+            assert_eq!(guard.rename(&i, i + 100), Ok(&i));
+        }
+
+        assert_eq!(guard.rename(&0, 1), Err(MoveError::NotFound));
+        assert_eq!(guard.rename(&100, 101), Err(MoveError::Occupied(&1)));
+        assert_eq!(guard.len(), 100);
+        for i in 0..100 {
+            assert_eq!(guard.get(&i), None);
+            assert_eq!(guard.get(&(i + 100)), Some(&i));
+        }
+    });
+}
+
+#[test]
+fn update_and_compute() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        assert_eq!(guard.update(0, |v| v + 1), None);
+        assert_eq!(guard.update_or_insert(0, |v| v + 1, 0), &0);
+        assert_eq!(guard.update_or_insert(0, |v| v + 1, 0), &1);
+        assert_eq!(guard.get_or_insert(0, 10), &1);
+        assert_eq!(guard.get_or_insert_with(1, || 10), &10);
+        assert!(guard.try_insert(1, 11).is_err());
+        assert_eq!(guard.get_or_try_insert_with(2, || Err(())), Err(()));
+        assert_eq!(guard.get_or_try_insert_with(2, || Ok::<_, ()>(2)), Ok(&2));
+        assert_eq!(guard.try_update(2, |_| Err(())), Err(()));
+        assert_eq!(guard.try_update(2, |v| Ok::<_, ()>(v + 1)), Ok(Some(&3)));
+        assert_eq!(
+            guard.try_update_or_insert_with(3, |_| Err(()), || Ok(3)),
+            Ok(&3)
+        );
+
+        let result = guard.compute(1, |entry| match entry {
+            Some((_, v)) => Operation::Insert::<_, ()>(v * 2),
+            None => Operation::Abort(()),
+        });
+        assert!(matches!(result, papaya_alloy::Compute::Updated { .. }));
+        assert_eq!(guard.get(&1), Some(&20));
+    });
+}
+
+#[test]
+fn aggregate() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = map();
+        let guard = map.pin();
+
+        for i in 0..1000 {
+            guard.insert(i, i);
+        }
+
+        let mut keys = guard.keys().copied().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+        assert_eq!(guard.values().sum::<usize>(), (0..1000).sum());
+
+        guard.retain(|k, _| k % 2 == 0);
+        assert_eq!(guard.len(), 500);
+        assert!(guard.iter().all(|(k, v)| k % 2 == 0 && k == v));
+
+        guard.clear();
+        assert!(guard.is_empty());
+        assert_eq!(guard.iter().count(), 0);
+    });
+}
+
+#[test]
+fn concurrent_insert() {
+    with_sharded_map::<usize, usize>(|map| {
+        let map = Arc::new(map());
+
+        let threads = (0..4)
+            .map(|t| {
+                let map = map.clone();
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        map.pin().insert(t * 1000 + i, i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(map.len(), 4000);
+        for i in 0..4000 {
+            assert_eq!(map.pin().get(&i), Some(&(i % 1000)));
+        }
+    });
+}
+
+#[test]
+fn from_iter() {
+    let map = (0..100)
+        .map(|i| (i, i))
+        .collect::<ShardedHashMap<usize, usize>>();
+    assert_eq!(map.len(), 100);
+    assert_eq!(map.pin().get(&42), Some(&42));
+}
@@ -0,0 +1,144 @@
+use papaya_alloy::HashTable;
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+struct Record {
+    id: usize,
+    name: String,
+}
+
+fn record(id: usize) -> Record {
+    Record {
+        id,
+        name: id.to_string(),
+    }
+}
+
+type Hasher = Box<dyn Fn(&Record) -> u64>;
+
+// Run the test on a table keyed by `Record::id`.
+fn with_table(test: impl FnOnce(&HashTable<Record, Hasher>, RandomState)) {
+    let state = RandomState::new();
+    let hasher = state.clone();
+    let table: HashTable<Record, Hasher> =
+        HashTable::new(Box::new(move |record| hasher.hash_one(record.id)));
+    test(&table, state);
+}
+
+#[test]
+fn insert_and_find() {
+    with_table(|table, state| {
+        let table = table.pin();
+
+        // Insert enough values to trigger a resize.
+        for i in 0..1000 {
+            let inserted = table.insert_unique(state.hash_one(i), record(i));
+            assert_eq!(inserted.id, i);
+        }
+
+        for i in 0..1000 {
+            let found = table.find(state.hash_one(i), |r| r.id == i);
+            assert_eq!(found, Some(&record(i)));
+        }
+
+        assert_eq!(table.find(state.hash_one(1000), |r| r.id == 1000), None);
+        assert_eq!(table.len(), 1000);
+    });
+}
+
+#[test]
+fn find_or_insert_with() {
+    with_table(|table, state| {
+        let table = table.pin();
+        let hash = state.hash_one(1);
+
+        let value = table.find_or_insert_with(hash, |r| r.id == 1, || record(1));
+        assert_eq!(value.name, "1");
+
+        let value = table.find_or_insert_with(
+            hash,
+            |r| r.id == 1,
+            || Record {
+                id: 1,
+                name: "other".to_owned(),
+            },
+        );
+        assert_eq!(value.name, "1");
+        assert_eq!(table.len(), 1);
+    });
+}
+
+#[test]
+fn remove() {
+    with_table(|table, state| {
+        let table = table.pin();
+
+        for i in 0..100 {
+            table.insert_unique(state.hash_one(i), record(i));
+        }
+
+        for i in 0..50 {
+            assert_eq!(
+                table.remove(state.hash_one(i), |r| r.id == i),
+                Some(&record(i))
+            );
+            assert_eq!(table.remove(state.hash_one(i), |r| r.id == i), None);
+        }
+
+        assert_eq!(table.len(), 50);
+    });
+}
+
+#[test]
+fn iter_retain_clear() {
+    with_table(|table, state| {
+        let table = table.pin();
+
+        for i in 0..100 {
+            table.insert_unique(state.hash_one(i), record(i));
+        }
+
+        let mut ids = table.iter().map(|r| r.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, (0..100).collect::<Vec<_>>());
+
+        table.retain(|r| r.id % 2 == 0);
+        assert_eq!(table.len(), 50);
+        assert!(table.iter().all(|r| r.id % 2 == 0));
+
+        table.clear();
+        assert!(table.is_empty());
+    });
+}
+
+#[test]
+fn concurrent_find_or_insert() {
+    let state = RandomState::new();
+    let hasher = state.clone();
+    let table = Arc::new(HashTable::new(move |record: &Record| {
+        hasher.hash_one(record.id)
+    }));
+
+    let threads = (0..4)
+        .map(|_| {
+            let table = table.clone();
+            let state = state.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    let hash = state.hash_one(i);
+                    let value = table.find_or_insert_with(hash, |r| r.id == i, || record(i));
+                    assert_eq!(value.id, i);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(table.len(), 1000);
+}
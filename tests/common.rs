@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use papaya_alloy::{HashMap, HashSet, ResizeMode};
+use papaya_alloy::{HashMap, HashSet, ResizeMode, ShardedHashMap};
 
 // Run the test on different configurations of a `HashMap`.
 pub fn with_map<K, V>(mut test: impl FnMut(&dyn Fn() -> HashMap<K, V>)) {
@@ -29,6 +29,79 @@ pub fn with_map<K, V>(mut test: impl FnMut(&dyn Fn() -> HashMap<K, V>)) {
     );
 }
 
+// Run the test on different configurations of a `HashMap` with a change log of the given
+// capacity.
+pub fn with_logged_map<K, V>(capacity: usize, mut test: impl FnMut(&dyn Fn() -> HashMap<K, V>)) {
+    // Blocking resize mode.
+    if !cfg!(papaya_stress) {
+        test(
+            &(|| {
+                HashMap::builder()
+                    .resize_mode(ResizeMode::Blocking)
+                    .change_log(capacity)
+                    .build()
+            }),
+        );
+    }
+
+    // Incremental resize mode with a small chunk to stress operations on nested tables.
+    test(
+        &(|| {
+            HashMap::builder()
+                .resize_mode(ResizeMode::Incremental(1))
+                .change_log(capacity)
+                .build()
+        }),
+    );
+
+    // Incremental resize mode with a medium-sized chunk to promote interference with incremental
+    // resizing.
+    test(
+        &(|| {
+            HashMap::builder()
+                .resize_mode(ResizeMode::Incremental(128))
+                .change_log(capacity)
+                .build()
+        }),
+    );
+}
+
+// Run the test on different configurations of a `HashMap` with snapshots enabled.
+pub fn with_snapshot_map<K, V>(mut test: impl FnMut(&dyn Fn() -> HashMap<K, V>)) {
+    // Blocking resize mode.
+    if !cfg!(papaya_stress) {
+        test(
+            &(|| {
+                HashMap::builder()
+                    .resize_mode(ResizeMode::Blocking)
+                    .snapshots(true)
+                    .build()
+            }),
+        );
+    }
+
+    // Incremental resize mode with a small chunk to stress operations on nested tables.
+    test(
+        &(|| {
+            HashMap::builder()
+                .resize_mode(ResizeMode::Incremental(1))
+                .snapshots(true)
+                .build()
+        }),
+    );
+
+    // Incremental resize mode with a medium-sized chunk to promote interference with incremental
+    // resizing.
+    test(
+        &(|| {
+            HashMap::builder()
+                .resize_mode(ResizeMode::Incremental(128))
+                .snapshots(true)
+                .build()
+        }),
+    );
+}
+
 // Run the test on different configurations of a `HashSet`.
 pub fn with_set<K>(mut test: impl FnMut(&dyn Fn() -> HashSet<K>)) {
     // Blocking resize mode.
@@ -56,6 +129,34 @@ pub fn with_set<K>(mut test: impl FnMut(&dyn Fn() -> HashSet<K>)) {
     );
 }
 
+// Run the test on different configurations of a `ShardedHashMap`.
+pub fn with_sharded_map<K, V>(mut test: impl FnMut(&dyn Fn() -> ShardedHashMap<K, V>)) {
+    // A single shard, which should behave like a `HashMap`.
+    test(&(|| ShardedHashMap::builder().shards(1).build()));
+
+    // Blocking resize mode.
+    if !cfg!(papaya_stress) {
+        test(
+            &(|| {
+                ShardedHashMap::builder()
+                    .shards(8)
+                    .resize_mode(ResizeMode::Blocking)
+                    .build()
+            }),
+        );
+    }
+
+    // Incremental resize mode with a small chunk to stress operations on nested tables.
+    test(
+        &(|| {
+            ShardedHashMap::builder()
+                .shards(8)
+                .resize_mode(ResizeMode::Incremental(1))
+                .build()
+        }),
+    );
+}
+
 // Prints a log message if `RUST_LOG=debug` is set.
 #[macro_export]
 macro_rules! debug {
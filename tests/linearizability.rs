@@ -0,0 +1,113 @@
+#![cfg(feature = "testing")]
+
+use papaya_alloy::testing::{Effect, Event, History, Kind};
+use papaya_alloy::{HashMap, Operation};
+use rand::prelude::*;
+
+use std::sync::Barrier;
+use std::thread;
+
+mod common;
+use common::{threads, with_map};
+
+// Check random operations on a small set of keys for linearizability.
+#[test]
+fn random_operations() {
+    const KEYS: usize = 8;
+    const OPERATIONS: usize = if cfg!(miri) { 16 } else { 256 };
+
+    with_map::<usize, usize>(|map| {
+        let map = map();
+        let history = History::new();
+        let threads = threads().max(2);
+        let barrier = Barrier::new(threads);
+
+        thread::scope(|s| {
+            for t in 0..threads {
+                let (map, history, barrier) = (&map, &history, &barrier);
+                s.spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    let mut recorder = history.recorder(map);
+                    barrier.wait();
+
+                    for i in 0..OPERATIONS {
+                        let key = rng.gen_range(0..KEYS);
+                        let value = (t * OPERATIONS) + i;
+
+                        match rng.gen_range(0..5) {
+                            0 => drop(recorder.get(&key)),
+                            1 => drop(recorder.insert(key, value)),
+                            2 => drop(recorder.remove(&key)),
+                            3 => drop(recorder.update(key, |v| v + 1)),
+                            _ => drop(recorder.compute(key, |entry| match entry {
+                                Some(v) if v % 2 == 0 => Operation::Remove,
+                                Some(_) => Operation::Abort(()),
+                                None => Operation::Insert(value),
+                            })),
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(history.len(), threads * OPERATIONS);
+        if let Err(violation) = history.check() {
+            panic!("{violation}");
+        }
+    });
+}
+
+// Ensure the checker rejects a read of a value that was overwritten.
+#[test]
+fn stale_read() {
+    let map = HashMap::<usize, usize>::new();
+    let history = History::new();
+
+    {
+        let mut recorder = history.recorder(&map);
+        recorder.insert(0, 1);
+        recorder.insert(0, 2);
+    }
+
+    let call = history.now();
+    let ret = history.now();
+    history.push(Event {
+        thread: 1,
+        kind: Kind::Get,
+        key: 0,
+        observed: Some(1),
+        effect: Effect::None,
+        call,
+        ret,
+    });
+
+    let violation = history.check().unwrap_err();
+    assert_eq!(violation.key, 0);
+    assert_eq!(violation.events.len(), 3);
+}
+
+// Ensure the checker accepts reads of either value during a concurrent write.
+#[test]
+fn concurrent_read() {
+    let history = History::<usize, usize>::new();
+
+    let event = |kind, observed, effect, call, ret| Event {
+        thread: 0,
+        kind,
+        key: 0,
+        observed,
+        effect,
+        call,
+        ret,
+    };
+
+    history.push(event(Kind::Insert, None, Effect::Insert(1), 0, 1));
+    history.push(event(Kind::Insert, Some(1), Effect::Insert(2), 2, 5));
+    history.push(event(Kind::Get, Some(2), Effect::None, 3, 4));
+    history.push(event(Kind::Get, Some(1), Effect::None, 3, 4));
+    history.check().unwrap();
+
+    // A read that starts after the write completes must observe it.
+    history.push(event(Kind::Get, Some(1), Effect::None, 6, 7));
+    assert!(history.check().is_err());
+}
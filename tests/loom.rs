@@ -0,0 +1,411 @@
+// Exhaustive model-checking of small concurrent scenarios.
+//
+// Run with `RUSTFLAGS="--cfg papaya_loom" cargo test --release --test loom`.
+#![cfg(papaya_loom)]
+
+use papaya_alloy::{HashMap, ResizeMode};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::BuildHasherDefault;
+
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+// A deterministic hasher, as the model must be deterministic across executions.
+type Hasher = BuildHasherDefault<DefaultHasher>;
+
+// Create a small map with the given resize mode.
+fn map(resize: ResizeMode) -> Arc<HashMap<usize, usize, Hasher>> {
+    Arc::new(
+        HashMap::builder()
+            .hasher(Hasher::default())
+            .capacity(1)
+            .resize_mode(resize)
+            .build(),
+    )
+}
+
+// Create a small map with the given resize mode and snapshots enabled.
+fn snapshot_map(resize: ResizeMode) -> Arc<HashMap<usize, usize, Hasher>> {
+    Arc::new(
+        HashMap::builder()
+            .hasher(Hasher::default())
+            .capacity(1)
+            .resize_mode(resize)
+            .snapshots(true)
+            .build(),
+    )
+}
+
+// Run the test for both blocking and incremental resizing, with the smallest
+// copy chunk to maximize interleavings between copiers.
+fn with_resize_modes(test: impl Fn(ResizeMode) + Sync + Send + 'static) {
+    let test = std::sync::Arc::new(test);
+
+    for resize in [ResizeMode::Blocking, ResizeMode::Incremental(1)] {
+        let mut model = loom::model::Builder::new();
+
+        // Bound the exploration unless overridden by `LOOM_MAX_PREEMPTIONS`.
+        if model.preemption_bound.is_none() {
+            model.preemption_bound = Some(2);
+        }
+
+        let test = test.clone();
+        model.check(move || test(resize));
+    }
+}
+
+#[test]
+fn insert_insert() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().insert(1, 1), None)
+        });
+
+        assert_eq!(map.pin().insert(2, 2), None);
+        t1.join().unwrap();
+
+        assert_eq!(map.pin().get(&1), Some(&1));
+        assert_eq!(map.pin().get(&2), Some(&2));
+        assert_eq!(map.len(), 2);
+    });
+}
+
+#[test]
+fn insert_same_key() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().insert(1, 1).copied()
+        });
+
+        let old = map.pin().insert(1, 2).copied();
+        let other = t1.join().unwrap();
+
+        // Exactly one of the inserts observed the other.
+        match (old, other) {
+            (None, Some(2)) => assert_eq!(map.pin().get(&1), Some(&1)),
+            (Some(1), None) => assert_eq!(map.pin().get(&1), Some(&2)),
+            _ => panic!("inconsistent inserts: {old:?}, {other:?}"),
+        }
+
+        assert_eq!(map.len(), 1);
+    });
+}
+
+#[test]
+fn insert_remove() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().remove(&1).copied()
+        });
+
+        assert_eq!(map.pin().insert(2, 2), None);
+        assert_eq!(t1.join().unwrap(), Some(1));
+
+        assert_eq!(map.pin().get(&1), None);
+        assert_eq!(map.pin().get(&2), Some(&2));
+        assert_eq!(map.len(), 1);
+    });
+}
+
+#[test]
+fn insert_during_resize() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().reserve(16)
+        });
+
+        assert_eq!(map.pin().insert(2, 2), None);
+        assert_eq!(map.pin().get(&1), Some(&1));
+        t1.join().unwrap();
+
+        assert_eq!(map.pin().get(&1), Some(&1));
+        assert_eq!(map.pin().get(&2), Some(&2));
+        assert_eq!(map.len(), 2);
+    });
+}
+
+#[test]
+fn remove_during_resize() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+        map.pin().insert(2, 2);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().reserve(16)
+        });
+
+        assert_eq!(map.pin().remove(&1).copied(), Some(1));
+        t1.join().unwrap();
+
+        assert_eq!(map.pin().get(&1), None);
+        assert_eq!(map.pin().get(&2), Some(&2));
+        assert_eq!(map.len(), 1);
+    });
+}
+
+#[test]
+fn update_during_resize() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 0);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || {
+                map.pin().reserve(16);
+                map.pin().update(1, |v| v + 1);
+            }
+        });
+
+        map.pin().update(1, |v| v + 1);
+        t1.join().unwrap();
+
+        assert_eq!(map.pin().get(&1), Some(&2));
+    });
+}
+
+#[test]
+fn snapshot_during_update() {
+    with_resize_modes(|resize| {
+        let map = snapshot_map(resize);
+        map.pin().insert(1, 0);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || {
+                map.pin().insert(2, 0);
+                map.pin().remove(&1);
+            }
+        });
+
+        // The snapshot never observes the removal without the insert.
+        let snapshot = map.snapshot();
+        let keys = (snapshot.contains_key(&1), snapshot.contains_key(&2));
+        assert_ne!(keys, (false, false));
+        assert_eq!(snapshot.len(), usize::from(keys.0) + usize::from(keys.1));
+
+        t1.join().unwrap();
+    });
+}
+
+#[test]
+fn rename_during_get() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().rename(&1, 2), Ok(&1))
+        });
+
+        // Once the entry is not found at the old key, it is found at the new key.
+        if map.pin().get(&1).is_none() {
+            assert_eq!(map.pin().get(&2), Some(&1));
+        }
+
+        t1.join().unwrap();
+        assert_eq!(map.len(), 1);
+    });
+}
+
+#[test]
+fn snapshot_during_rename() {
+    with_resize_modes(|resize| {
+        let map = snapshot_map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().rename(&1, 2), Ok(&1))
+        });
+
+        // The snapshot observes the entry at exactly one of its keys.
+        let snapshot = map.snapshot();
+        let keys = (snapshot.contains_key(&1), snapshot.contains_key(&2));
+        assert!(matches!(keys, (true, false) | (false, true)));
+        assert_eq!(snapshot.len(), 1);
+
+        t1.join().unwrap();
+    });
+}
+
+#[test]
+fn rename_during_insert() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().rename(&1, 2).is_ok()
+        });
+
+        map.pin().insert(2, 2);
+        let renamed = t1.join().unwrap();
+
+        // The rename either took effect before the insert, or observed it.
+        assert_eq!(map.pin().get(&1), (!renamed).then_some(&1));
+        assert_eq!(map.pin().get(&2), Some(&2));
+        assert_eq!(map.len(), if renamed { 1 } else { 2 });
+    });
+}
+
+#[test]
+fn pop_any_pop_any() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+        map.pin().insert(2, 2);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || map.pin().pop_any().map(|(&k, _)| k)
+        });
+
+        let popped = map.pin().pop_any().map(|(&k, _)| k);
+
+        // Each entry is removed by exactly one thread.
+        let mut popped = [popped, t1.join().unwrap()];
+        popped.sort();
+        assert_eq!(popped, [Some(1), Some(2)]);
+        assert!(map.pin().is_empty());
+    });
+}
+
+#[test]
+fn pop_any_during_insert() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        map.pin().insert(1, 1);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().insert(2, 2), None)
+        });
+
+        // The existing entry is always found, even if the table is resized.
+        let (&k, &v) = map.pin().pop_any().unwrap();
+        assert_eq!(k, v);
+
+        t1.join().unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.pin().get(&k), None);
+    });
+}
+
+#[test]
+fn watch_during_insert() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().insert(1, 1), None)
+        });
+
+        // An insert that is not observed after subscribing is always received.
+        let watcher = map.watch(1);
+        if map.pin().get(&1).is_none() {
+            assert_eq!(watcher.recv().unwrap().new_value(), Some(&1));
+        }
+
+        t1.join().unwrap();
+    });
+}
+
+#[test]
+fn wait_for_during_insert() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || assert_eq!(map.pin().insert(1, 1), None)
+        });
+
+        // The waiter observes the insert, or is woken by it.
+        assert_eq!(map.wait_for(&1), &1);
+
+        t1.join().unwrap();
+    });
+}
+
+#[test]
+fn get_or_insert_with_once() {
+    with_resize_modes(|resize| {
+        let map = map(resize);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let init = {
+            let calls = calls.clone();
+            move || {
+                calls.fetch_add(1, Ordering::Relaxed);
+                1
+            }
+        };
+
+        let t1 = thread::spawn({
+            let (map, init) = (map.clone(), init.clone());
+            move || assert_eq!(map.get_or_insert_with_once(1, init), &1)
+        });
+
+        assert_eq!(map.get_or_insert_with_once(1, init), &1);
+        t1.join().unwrap();
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn change_log_ordering() {
+    with_resize_modes(|resize| {
+        let map = Arc::new(
+            HashMap::builder()
+                .hasher(Hasher::default())
+                .capacity(1)
+                .resize_mode(resize)
+                .change_log(16)
+                .build(),
+        );
+        map.pin().insert(1, 0);
+
+        let t1 = thread::spawn({
+            let map = map.clone();
+            move || drop(map.pin().insert(1, 1))
+        });
+
+        let t2 = thread::spawn({
+            let map = map.clone();
+            move || drop(map.pin().remove(&1))
+        });
+
+        map.pin().insert(2, 2);
+        t1.join().unwrap();
+        t2.join().unwrap();
+
+        // Applying the changes in order always converges to the same state.
+        let follower = HashMap::builder().hasher(Hasher::default()).build();
+        follower.apply_changes(map.changes_since(0).unwrap());
+        assert_eq!(follower.pin().get(&1), map.pin().get(&1));
+        assert_eq!(follower.pin().get(&2), Some(&2));
+    });
+}
@@ -9,7 +9,7 @@ use std::sync::Barrier;
 use std::thread;
 
 mod common;
-use common::{threads, with_map};
+use common::{threads, with_logged_map, with_map, with_snapshot_map};
 
 // Call `contains_key` in parallel for a shared set of keys.
 #[test]
@@ -86,6 +86,7 @@ fn insert_stress() {
                     });
                 }
             });
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * threads);
         }
     });
@@ -167,6 +168,7 @@ fn insert_overwrite_stress() {
                 }
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES);
         }
     });
@@ -329,6 +331,7 @@ fn update_or_insert_stress() {
                 assert_eq!(*map.get(&i).unwrap(), threads * OPERATIONS);
             }
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES);
         }
     });
@@ -398,6 +401,7 @@ fn remove_update_or_insert_stress() {
                 });
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * OPERATIONS);
 
             for i in 0..ENTRIES {
@@ -480,6 +484,7 @@ fn conditional_remove_update_or_insert_stress() {
                 });
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * OPERATIONS);
 
             for i in 0..ENTRIES {
@@ -554,6 +559,7 @@ fn remove_if_update_or_insert_stress() {
                 });
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * OPERATIONS);
 
             for i in 0..ENTRIES {
@@ -621,6 +627,7 @@ fn insert_remove_stress() {
                 }
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES);
 
             for i in 0..ENTRIES {
@@ -630,6 +637,452 @@ fn insert_remove_stress() {
     });
 }
 
+// Take snapshots in parallel with threads that each move a single key forward, and ensure
+// every snapshot observes exactly one key per thread, or two adjacent keys mid-move.
+#[test]
+#[ignore]
+fn snapshot_stress() {
+    const OPERATIONS: usize = match () {
+        _ if cfg!(miri) => 64,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 12,
+        _ => 1 << 16,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_snapshot_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+
+            for t in 0..threads {
+                map.insert((t, 0), 0);
+            }
+
+            let done = AtomicUsize::new(0);
+            let barrier = Barrier::new(threads + 1);
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (map, done, barrier) = (&map, &done, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for i in 1..OPERATIONS {
+                            map.insert((t, i), i);
+                            assert_eq!(map.remove(&(t, i - 1)), Some(&(i - 1)));
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+
+                s.spawn(|| {
+                    barrier.wait();
+                    while done.load(Ordering::Relaxed) != threads {
+                        let snapshot = map.snapshot();
+
+                        let mut keys = vec![Vec::new(); threads];
+                        for (&(t, i), &value) in snapshot.iter() {
+                            assert_eq!(i, value);
+                            keys[t].push(i);
+                        }
+                        assert_eq!(keys.iter().map(Vec::len).sum::<usize>(), snapshot.len());
+
+                        for mut keys in keys {
+                            keys.sort();
+                            match keys[..] {
+                                [_] => {}
+                                [a, b] => assert_eq!(a + 1, b),
+                                _ => panic!("inconsistent snapshot: {keys:?}"),
+                            }
+                        }
+                    }
+                });
+            });
+
+            map.check_invariants();
+            assert_eq!(map.len(), threads);
+        }
+    });
+}
+
+// Call `update` in parallel for a shared set of keys while taking snapshots, ensuring
+// that every snapshot contains a single version of each key, and that consecutive
+// snapshots never go back in time.
+#[test]
+#[ignore]
+fn snapshot_update_stress() {
+    const ENTRIES: usize = 16;
+    const OPERATIONS: usize = match () {
+        _ if cfg!(miri) => 16,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 10,
+        _ => 1 << 14,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_snapshot_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+
+            for key in 0..ENTRIES {
+                map.insert(key, 0);
+            }
+
+            let done = AtomicUsize::new(0);
+            let barrier = Barrier::new(threads + 1);
+            thread::scope(|s| {
+                for _ in 0..threads {
+                    let (map, done, barrier) = (&map, &done, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for _ in 0..OPERATIONS {
+                            for key in 0..ENTRIES {
+                                map.update(key, |v| v + 1);
+                            }
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+
+                s.spawn(|| {
+                    barrier.wait();
+                    let mut previous = [0; ENTRIES];
+                    while done.load(Ordering::Relaxed) != threads {
+                        let snapshot = map.snapshot();
+                        assert_eq!(snapshot.len(), ENTRIES);
+
+                        let mut values = [None; ENTRIES];
+                        for (&key, &value) in snapshot.iter() {
+                            assert_eq!(values[key].replace(value), None);
+                        }
+
+                        for (key, value) in values.into_iter().enumerate() {
+                            let value = value.unwrap();
+                            assert!(value >= previous[key]);
+                            previous[key] = value;
+                        }
+                    }
+                });
+            });
+
+            map.check_invariants();
+            for key in 0..ENTRIES {
+                assert_eq!(map.get(&key), Some(&(threads * OPERATIONS)));
+            }
+        }
+    });
+}
+
+// Call `share_clone` in parallel with threads that remove and reinsert a shared set of keys,
+// ensuring that every copy contains each key at most once.
+#[test]
+#[ignore]
+fn share_clone_stress() {
+    const ENTRIES: usize = if cfg!(miri) { 64 } else { 256 };
+    const OPERATIONS: usize = match () {
+        _ if cfg!(miri) => 1,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 6,
+        _ => 1 << 8,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    let threads = threads();
+    with_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            for i in 0..ENTRIES {
+                map.insert(i, i);
+            }
+
+            let done = AtomicUsize::new(0);
+            let barrier = Barrier::new(threads + 1);
+            thread::scope(|s| {
+                for _ in 0..threads {
+                    s.spawn(|| {
+                        let mut entries = (0..ENTRIES).collect::<Vec<_>>();
+                        let mut rng = rand::thread_rng();
+                        barrier.wait();
+
+                        for _ in 0..OPERATIONS {
+                            entries.shuffle(&mut rng);
+                            for &i in &entries {
+                                if map.remove(&i).is_some() {
+                                    map.insert(i, i);
+                                }
+                            }
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+
+                s.spawn(|| {
+                    barrier.wait();
+                    while done.load(Ordering::Relaxed) != threads {
+                        let copy = map.share_clone();
+                        copy.check_invariants();
+
+                        let copy = copy.pin();
+                        let mut keys = copy.keys().copied().collect::<Vec<_>>();
+                        assert_eq!(keys.len(), copy.len());
+
+                        keys.sort();
+                        keys.dedup();
+                        assert_eq!(keys.len(), copy.len(), "duplicate keys in copy");
+                    }
+                });
+            });
+
+            map.check_invariants();
+            assert_eq!(map.len(), ENTRIES);
+        }
+    });
+}
+
+// Call `rename` in parallel for a shared set of entries, moving each entry back and forth
+// between two keys, while updating the entries and taking snapshots. Every snapshot must
+// contain each entry at exactly one of its keys.
+#[test]
+#[ignore]
+fn rename_stress() {
+    const ENTRIES: usize = 16;
+    const OPERATIONS: usize = match () {
+        _ if cfg!(miri) => 16,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 10,
+        _ => 1 << 14,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_snapshot_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+
+            for entry in 0..ENTRIES {
+                map.insert(entry, entry);
+            }
+
+            let done = AtomicUsize::new(0);
+            let barrier = Barrier::new(threads + 1);
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (map, done, barrier) = (&map, &done, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for _ in 0..OPERATIONS {
+                            for entry in 0..ENTRIES {
+                                let keys = [entry, entry + ENTRIES];
+                                match t % 3 {
+                                    // Racing updates abort concurrent moves.
+                                    0 => {
+                                        for key in keys {
+                                            map.update(key, |&value| value);
+                                        }
+                                    }
+                                    forward => {
+                                        let [from, to] = keys;
+                                        let (from, to) =
+                                            if forward == 1 { (from, to) } else { (to, from) };
+
+                                        if let Ok(&value) = map.rename(&from, to) {
+                                            assert_eq!(value, entry);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+
+                s.spawn(|| {
+                    barrier.wait();
+                    while done.load(Ordering::Relaxed) != threads {
+                        let snapshot = map.snapshot();
+                        assert_eq!(snapshot.len(), ENTRIES);
+
+                        let mut found = [false; ENTRIES];
+                        for (&key, &value) in snapshot.iter() {
+                            assert_eq!(key % ENTRIES, value);
+                            assert!(!found[value], "entry {value} found at both keys");
+                            found[value] = true;
+                        }
+                    }
+                });
+            });
+
+            map.check_invariants();
+            assert_eq!(map.len(), ENTRIES);
+            for entry in 0..ENTRIES {
+                let found = [entry, entry + ENTRIES].map(|key| map.get(&key));
+                assert!(matches!(found, [Some(_), None] | [None, Some(_)]));
+            }
+        }
+    });
+}
+
+// Call `pop_any` in parallel with inserts of distinct keys, as in a work queue, ensuring
+// every key is removed exactly once.
+#[test]
+#[ignore]
+fn pop_any_stress() {
+    const ENTRIES: usize = match () {
+        _ if cfg!(miri) => 64,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 12,
+        _ => 1 << 16,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+            let barrier = Barrier::new(threads);
+
+            let mut popped = thread::scope(|s| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|t| {
+                        let (map, barrier) = (&map, &barrier);
+                        s.spawn(move || {
+                            let mut popped = Vec::new();
+                            barrier.wait();
+                            for i in 0..ENTRIES {
+                                let key = t * ENTRIES + i;
+                                assert_eq!(map.insert(key, key), None);
+
+                                // Note that the map may be emptied by other threads.
+                                if let Some((&key, &value)) = map.pin().pop_any() {
+                                    assert_eq!(key, value);
+                                    popped.push(key);
+                                }
+                            }
+                            popped
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            map.check_invariants();
+            while let Some((&key, _)) = map.pin().pop_any() {
+                popped.push(key);
+            }
+
+            assert!(map.is_empty());
+            popped.sort();
+            assert_eq!(popped, (0..threads * ENTRIES).collect::<Vec<_>>());
+        }
+    });
+}
+
+// Call `get_or_insert_with_once` in parallel for a shared set of keys, ensuring every key is
+// initialized exactly once.
+#[test]
+#[ignore]
+fn get_or_insert_with_once_stress() {
+    const ENTRIES: usize = match () {
+        _ if cfg!(miri) => 64,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 10,
+        _ => 1 << 14,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+            let calls: Vec<_> = (0..ENTRIES).map(|_| AtomicUsize::new(0)).collect();
+
+            let barrier = Barrier::new(threads);
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (map, calls, barrier) = (&map, &calls, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for i in 0..ENTRIES {
+                            // Start from a different key on every thread to mix contended
+                            // and uncontended initialization.
+                            let key = (i + t * ENTRIES / threads) % ENTRIES;
+                            let value = map.get_or_insert_with_once(key, || {
+                                calls[key].fetch_add(1, Ordering::Relaxed);
+                                key
+                            });
+                            assert_eq!(*value, key);
+                        }
+                    });
+                }
+            });
+
+            map.check_invariants();
+            assert_eq!(map.len(), ENTRIES);
+            for calls in calls {
+                assert_eq!(calls.into_inner(), 1);
+            }
+        }
+    });
+}
+
+// Call `insert`, `update` and `remove` in parallel for a shared set of keys, while a follower
+// map applies the change log concurrently.
+#[test]
+#[ignore]
+fn change_log_stress() {
+    const ENTRIES: usize = 16;
+    const OPERATIONS: usize = match () {
+        _ if cfg!(miri) => 16,
+        _ if cfg!(papaya_stress) || cfg!(papaya_asan) => 1 << 8,
+        _ => 1 << 10,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 16 };
+
+    with_logged_map(1 << 22, |map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let follower = HashMap::new();
+            let threads = threads();
+
+            let mut seq = 0;
+            let done = AtomicUsize::new(0);
+            let barrier = Barrier::new(threads + 1);
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (map, done, barrier) = (&map, &done, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for i in 0..OPERATIONS {
+                            for key in 0..ENTRIES {
+                                match (i + t) % 3 {
+                                    0 => drop(map.insert(key, t)),
+                                    1 => drop(map.update(key, |v| v + 1)),
+                                    _ => drop(map.remove(&key)),
+                                }
+                            }
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+
+                s.spawn(|| {
+                    barrier.wait();
+                    while done.load(Ordering::Relaxed) != threads {
+                        if let Some(last) = follower.apply_changes(map.changes_since(seq).unwrap())
+                        {
+                            assert!(last > seq);
+                            seq = last;
+                        }
+                    }
+                });
+            });
+
+            follower.apply_changes(map.changes_since(seq).unwrap());
+
+            map.check_invariants();
+            assert_eq!(follower, map);
+        }
+    });
+}
+
 // Call `remove` in parallel for a shared set of keys with other threads calling `update`,
 // and a dedicated thread for inserting unrelated keys. This is likely to cause interference
 // with incremental resizing.
@@ -706,6 +1159,7 @@ fn remove_mixed_stress() {
                 assert_eq!(*map.get(&i).unwrap(), usize::MAX);
             }
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES);
         }
     });
@@ -771,6 +1225,7 @@ fn insert_remove_chunk_stress() {
                 assert_eq!(got, []);
             }
 
+            map.check_invariants();
             assert_eq!(map.len(), 0);
         }
     });
@@ -850,6 +1305,7 @@ fn mixed_chunk_stress() {
                 assert_eq!(v, got);
             }
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * threads);
         }
     });
@@ -915,6 +1371,7 @@ fn mixed_entry_stress() {
                 let got: Vec<_> = map.pin().iter().map(|(&k, &v)| (k, v)).collect();
                 assert_eq!(got, []);
             }
+            map.check_invariants();
             assert_eq!(map.len(), 0);
         }
     });
@@ -970,6 +1427,7 @@ fn clear_stress() {
             });
 
             map.pin().clear();
+            map.check_invariants();
             assert_eq!(map.len(), 0);
             assert_eq!(map.pin().iter().count(), 0);
         }
@@ -1023,12 +1481,83 @@ fn retain_stress() {
                 });
             });
 
+            map.check_invariants();
             assert_eq!(map.len(), ENTRIES * (threads - 1));
             assert_eq!(map.pin().iter().count(), ENTRIES * (threads - 1));
         }
     });
 }
 
+// Call `extract_if` in parallel for a shared set of keys during concurrent insertion,
+// ensuring every matching key is extracted exactly once.
+#[test]
+#[ignore]
+fn extract_if_stress() {
+    // Tables never grow under `papaya_stress`, so the map cannot be linearized.
+    if cfg!(papaya_stress) {
+        return;
+    }
+
+    const ENTRIES: usize = match () {
+        _ if cfg!(miri) => 64,
+        _ if cfg!(papaya_asan) => 1 << 12,
+        _ => 1 << 16,
+    };
+    const ITERATIONS: usize = if cfg!(miri) { 1 } else { 32 };
+
+    with_map(|map| {
+        for _ in (0..ITERATIONS).inspect(|e| debug!("{e}/{ITERATIONS}")) {
+            let map = map();
+            let threads = threads();
+
+            // Even keys are extracted, and odd keys are inserted concurrently.
+            for key in (0..ENTRIES).map(|i| i * 2) {
+                map.insert(key, key);
+            }
+
+            let barrier = Barrier::new(threads);
+            let mut extracted = thread::scope(|s| {
+                for t in 0..threads / 2 {
+                    let (map, barrier) = (&map, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        for i in 0..ENTRIES {
+                            let key = (t * ENTRIES + i) * 2 + 1;
+                            assert_eq!(map.insert(key, key), None);
+                        }
+                    });
+                }
+
+                let handles: Vec<_> = (threads / 2..threads)
+                    .map(|_| {
+                        s.spawn(|| {
+                            barrier.wait();
+                            map.pin()
+                                .extract_if(|&key, &value| {
+                                    assert_eq!(key, value);
+                                    key % 2 == 0
+                                })
+                                .map(|(&key, _)| key)
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            map.check_invariants();
+            extracted.sort();
+            assert_eq!(extracted, (0..ENTRIES).map(|i| i * 2).collect::<Vec<_>>());
+            assert_eq!(map.len(), (threads / 2) * ENTRIES);
+            assert!(map.pin().iter().all(|(&key, _)| key % 2 == 1));
+        }
+    });
+}
+
 // Adapted from: https://github.com/jonhoo/flurry/tree/main/tests/jdk
 #[test]
 #[ignore]